@@ -0,0 +1,38 @@
+/*
+ * C interface of sarctool, for the shared library built with
+ *
+ *     cargo rustc --lib --release --features cdylib --crate-type cdylib
+ *
+ * Functions returning int return 0 on success, or the exit code of the `sarc` binary: 2 for
+ * bad arguments, 3 for files that couldn't be read or written, and 4 for anything else. The
+ * message of the error is then given by sarctool_last_error. Paths are UTF-8.
+ */
+#ifndef SARCTOOL_H
+#define SARCTOOL_H
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* Message of the last error on this thread, or NULL. Only valid until the next call. */
+const char *sarctool_last_error(void);
+
+/* Pack a directory into an archive like `sarc zip`. */
+int sarctool_pack_dir(const char *in_dir, const char *out_file);
+
+/* Extract an archive into a directory like `sarc unzip`. */
+int sarctool_extract(const char *in_file, const char *out_dir);
+
+/*
+ * The entries of an archive as a JSON array of objects with their name, hash, offset, size
+ * and type, or NULL on failure. Free it with sarctool_free_string.
+ */
+char *sarctool_list_json(const char *in_file);
+
+void sarctool_free_string(char *string);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
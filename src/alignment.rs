@@ -0,0 +1,115 @@
+//! Data alignment requirements of files inside SARCs, which depend on the game and file type
+use std::num::ParseIntError;
+use std::str::FromStr;
+
+use crate::writer::{SarcWriter, DEFAULT_ALIGNMENT};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Game {
+    Botw,
+    Totk,
+}
+
+impl FromStr for Game {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "botw" => Ok(Game::Botw),
+            "totk" => Ok(Game::Totk),
+            _ => Err(format!("unknown game '{}'", s)),
+        }
+    }
+}
+
+const BOTW_ALIGNMENT: &[(&str, usize)] = &[
+    ("bfres", 0x2000),
+    ("sbfres", 0x2000),
+    ("gtx", 0x2000),
+    ("bffnt", 0x2000),
+    ("bntx", 0x1000),
+    ("sharc", 0x1000),
+    ("sharcb", 0x1000),
+    ("baglblm", 0x1000),
+    ("baglccr", 0x1000),
+    ("baglclwd", 0x1000),
+    ("baglcube", 0x1000),
+    ("bagldof", 0x1000),
+    ("baglenv", 0x1000),
+    ("baglenvset", 0x1000),
+    ("baglfila", 0x1000),
+    ("bagllmap", 0x1000),
+    ("bagllref", 0x1000),
+    ("baglmf", 0x1000),
+    ("baglshpp", 0x1000),
+    ("baglsky", 0x1000),
+];
+
+const TOTK_ALIGNMENT: &[(&str, usize)] = &[
+    ("bfres", 0x1000),
+    ("bntx", 0x1000),
+    ("bfsha", 0x1000),
+    ("bnsh", 0x1000),
+    ("bphsh", 0x80),
+    ("bphcl", 0x80),
+];
+
+impl Game {
+    fn default_alignment(self) -> usize {
+        8
+    }
+
+    fn table(self) -> &'static [(&'static str, usize)] {
+        match self {
+            Game::Botw => BOTW_ALIGNMENT,
+            Game::Totk => TOTK_ALIGNMENT,
+        }
+    }
+}
+
+pub fn parse_int(s: &str) -> Result<usize, ParseIntError> {
+    match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => usize::from_str_radix(hex, 16),
+        None => s.parse(),
+    }
+}
+
+pub fn parse_alignment(s: &str) -> Result<usize, String> {
+    match parse_int(s) {
+        Ok(alignment) if alignment.is_power_of_two() => Ok(alignment),
+        Ok(_) => Err("alignment must be a power of two".to_owned()),
+        Err(err) => Err(err.to_string()),
+    }
+}
+
+pub struct Alignment {
+    pub default: usize,
+    pub game: Option<Game>,
+}
+
+impl Alignment {
+    pub fn new(alignment: Option<usize>, game: Option<Game>) -> Self {
+        Alignment {
+            default: alignment
+                .or_else(|| game.map(Game::default_alignment))
+                .unwrap_or(DEFAULT_ALIGNMENT),
+            game,
+        }
+    }
+
+    fn for_extension(&self, name: &str) -> Option<usize> {
+        let extension = name.rsplit('.').next().unwrap_or_default();
+        self.game?
+            .table()
+            .iter()
+            .find(|(ext, _)| *ext == extension)
+            .map(|&(_, alignment)| alignment)
+    }
+
+    pub fn apply(&self, sarc: &mut SarcWriter) {
+        sarc.alignment = self.default;
+        for entry in &mut sarc.entries {
+            entry.alignment = entry.name.as_deref().and_then(|name| self.for_extension(name));
+        }
+    }
+}
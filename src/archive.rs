@@ -0,0 +1,233 @@
+//! Decoding of input archives, shared by all the commands so that every one of them accepts
+//! uncompressed, yaz0, zstd and LZ4 SARCs and U8 archives alike
+//!
+//! Uncompressed archives are memory mapped rather than read, so commands that only need the
+//! tables or a few entries don't have to load all of a multi-gigabyte pack.
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fs::File;
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use memmap2::Mmap;
+use walkdir::{DirEntry, WalkDir};
+
+use crate::compression::{decompress, Compression};
+use crate::container::Container;
+use crate::dictionary::Dictionaries;
+use crate::layout::Layout;
+use crate::metadata::ByteOrder;
+use crate::writer::{SarcWriter, WriterEntry};
+use crate::{name_map, stdio, ConvertError};
+
+pub struct Archive {
+    /// Outer compression of the file the archive was read from
+    pub compression: Compression,
+    /// The uncompressed archive
+    pub data: ArchiveData,
+    pub layout: Layout,
+    pub dictionaries: Dictionaries,
+    /// Names from `--name-map` for entries that have none in the archive
+    pub mapped_names: HashMap<u32, String>,
+}
+
+pub enum ArchiveData {
+    Owned(Vec<u8>),
+    Mapped(Mmap),
+}
+
+impl Deref for ArchiveData {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            ArchiveData::Owned(data) => data,
+            ArchiveData::Mapped(map) => map,
+        }
+    }
+}
+
+impl ArchiveData {
+    pub fn open(path: &Path) -> Result<Self, ConvertError> {
+        if stdio::is_stdio(path) {
+            return stdio::read(path).map(ArchiveData::Owned)
+        }
+
+        let file = File::open(path).map_err(|err| ConvertError::io(path, err))?;
+        // SAFETY: the archive being modified while it's open is treated the same as any other
+        // corrupt input would be, the map is only ever read through bounds-checked slices
+        match unsafe { Mmap::map(&file) } {
+            Ok(map) => Ok(ArchiveData::Mapped(map)),
+            // empty files and some special files can't be mapped
+            Err(_) => stdio::read(path).map(ArchiveData::Owned),
+        }
+    }
+}
+
+/// Data shared by readers that each need their own cursor over it, like the entries of a zip
+/// that are only decompressed once the archive they're packed into is written
+#[derive(Clone)]
+pub struct SharedData(pub Arc<ArchiveData>);
+
+impl AsRef<[u8]> for SharedData {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+/// Whether the name of a file says it's an archive, compressed or not. Zstd and LZ4 compressed
+/// files need the name of an archive under the `.zs` or `.lz4`, since TOTK compresses everything
+/// that way.
+pub fn is_archive_name(path: &Path) -> bool {
+    let name = path.file_name().unwrap_or_default().to_string_lossy().to_lowercase();
+    match name.strip_suffix(".zs").or_else(|| name.strip_suffix(".lz4")) {
+        Some(inner) => Compression::from_extension(Path::new(inner)) == Some(Compression::None),
+        None => Compression::from_extension(path).is_some(),
+    }
+}
+
+/// Every archive in a directory and its subdirectories, going by their names
+pub fn find_archives(dir: &Path) -> Vec<PathBuf> {
+    WalkDir::new(dir)
+        .sort_by_file_name()
+        .into_iter()
+        .filter_map(|entry| match entry {
+            Ok(entry) => Some(entry),
+            Err(err) => {
+                log::warn!("{}", err);
+                None
+            }
+        })
+        .filter(|entry| entry.file_type().is_file() && is_archive_name(entry.path()))
+        .map(DirEntry::into_path)
+        .collect()
+}
+
+pub struct Entry<'a> {
+    /// From the archive, or the name map for entries without one
+    pub name: Option<&'a str>,
+    pub hash: u32,
+    pub offset: usize,
+    pub data: &'a [u8],
+}
+
+impl Entry<'_> {
+    /// The name, or the hash for entries nothing is known about
+    pub fn display_name(&self) -> Cow<'_, str> {
+        match self.name {
+            Some(name) => Cow::Borrowed(name),
+            None => Cow::Owned(format!("[{:08X}]", self.hash)),
+        }
+    }
+}
+
+impl Archive {
+    pub fn open(path: &Path, dict: Option<&Path>) -> Result<Self, ConvertError> {
+        let archive = Self::open_unchecked(path, dict)?;
+        archive.check().map_err(|err| err.in_file(path))?;
+        Ok(archive)
+    }
+
+    /// Open an archive without making sure every entry is inside of it, for `repair`
+    pub fn open_unchecked(path: &Path, dict: Option<&Path>) -> Result<Self, ConvertError> {
+        let data = ArchiveData::open(path)?;
+        let dictionaries = if Compression::detect(&data) == Compression::Zstd {
+            Dictionaries::load(dict, path)?
+        } else {
+            Dictionaries::default()
+        };
+        let mut archive = Self::from_data(data, &dictionaries).map_err(|err| err.in_file(path))?;
+        archive.dictionaries = dictionaries;
+        Ok(archive)
+    }
+
+    /// Open one of many archives using dictionaries loaded once for all of them, which the
+    /// archive doesn't keep
+    pub fn open_shared(path: &Path, dictionaries: &Dictionaries) -> Result<Self, ConvertError> {
+        let data = ArchiveData::open(path)?;
+        let archive = Self::from_data(data, dictionaries).map_err(|err| err.in_file(path))?;
+        archive.check().map_err(|err| err.in_file(path))?;
+        Ok(archive)
+    }
+
+    /// An archive read from memory, like one nested inside of another
+    pub fn from_bytes(data: Vec<u8>, dictionaries: &Dictionaries) -> Result<Self, ConvertError> {
+        let archive = Self::from_data(ArchiveData::Owned(data), dictionaries)?;
+        archive.check()?;
+        Ok(archive)
+    }
+
+    fn from_data(data: ArchiveData, dictionaries: &Dictionaries) -> Result<Self, ConvertError> {
+        let compression = Compression::detect(&data);
+        let data = match compression {
+            Compression::None => data,
+            _ => ArchiveData::Owned(decompress(&data, dictionaries)?),
+        };
+        let layout = Container::detect(&data).unwrap_or_default().parse(&data)?;
+        log::debug!(
+            "{:?} {:?} compressed, {} byte order, {} entries, data at {:#x}", layout.container, compression,
+            ByteOrder::from(&layout.byte_order), layout.nodes.len(), layout.data_offset
+        );
+        let mut mapped_names = name_map::names(layout.hash_key);
+        mapped_names.retain(|hash, _| layout.nodes.iter().any(|node| node.name.is_none() && node.hash == *hash));
+        Ok(Archive {
+            compression,
+            data,
+            layout,
+            dictionaries: Dictionaries::default(),
+            mapped_names,
+        })
+    }
+
+    pub fn check(&self) -> Result<(), ConvertError> {
+        for (i, node) in self.layout.nodes.iter().enumerate() {
+            let range = self.layout.absolute(node);
+            if range.start > range.end || range.end > self.data.len() {
+                return Err(ConvertError::sarc(format!(
+                    "Data of {} ({:#x}..{:#x}) is outside of the archive, `sarc repair` can fix this",
+                    node.name.clone().unwrap_or_else(|| format!("node {}", i)), range.start, range.end
+                )))
+            }
+        }
+        Ok(())
+    }
+
+    /// A writer reproducing this archive's layout, for when only parts of it need to change
+    pub fn to_writer(&self) -> SarcWriter {
+        let entries = self.layout.nodes.iter()
+            .map(|node| WriterEntry {
+                name: node.name.clone(),
+                hash: node.hash,
+                data: self.data[self.layout.absolute(node)].to_vec().into(),
+                offset: Some(node.data.start as usize),
+                alignment: None,
+            })
+            .collect();
+
+        let mut writer = SarcWriter::new(ByteOrder::from(&self.layout.byte_order).into(), entries);
+        writer.container = self.layout.container;
+        writer.hash_key = self.layout.hash_key;
+        writer.data_offset = Some(self.layout.data_offset as usize);
+        writer.file_size = Some(self.layout.file_size as usize);
+        writer
+    }
+
+    pub fn entries(&self) -> impl ExactSizeIterator<Item = Entry<'_>> {
+        self.layout.nodes.iter()
+            .map(move |node| {
+                let range = self.layout.absolute(node);
+                Entry {
+                    name: node.name.as_deref().or_else(|| self.mapped_names.get(&node.hash).map(String::as_str)),
+                    hash: node.hash,
+                    offset: range.start,
+                    data: &self.data[range],
+                }
+            })
+    }
+
+    /// The entry with this name, or `[HASH]` for one without a name
+    pub fn entry(&self, name: &str) -> Option<Entry<'_>> {
+        self.entries().find(|entry| entry.display_name() == name)
+    }
+}
@@ -0,0 +1,94 @@
+//! Running a command over several archives at once, given as paths or globs
+use std::path::{Path, PathBuf};
+
+use rayon::prelude::*;
+
+use sarctool::{stdio, ConvertError, ConvertErrorKind};
+
+use crate::logger;
+
+/// Expand the inputs given on the command line. Paths that don't exist are treated as globs,
+/// since not every shell expands them.
+pub fn expand(inputs: &[PathBuf]) -> Result<Vec<PathBuf>, ConvertError> {
+    let mut paths = vec![];
+    for input in inputs {
+        if input.exists() || stdio::is_stdio(input) {
+            paths.push(input.clone());
+            continue
+        }
+
+        let pattern = input.to_string_lossy();
+        let matches = glob::glob(&pattern)
+            .map_err(|err| ConvertError::param(&format!("{}: {}", pattern, err)))?
+            .filter_map(Result::ok)
+            .filter(|path| path.is_file())
+            .collect::<Vec<_>>();
+        if matches.is_empty() {
+            return Err(ConvertError::file("no such file").in_file(input))
+        }
+        paths.extend(matches);
+    }
+    Ok(paths)
+}
+
+/// Where to put the output for `input`: inside `out_root` if given, otherwise next to the input
+pub fn output_path(input: &Path, out_root: Option<&Path>, extension: Option<&str>) -> Result<PathBuf, ConvertError> {
+    if stdio::is_stdio(input) {
+        return Err(ConvertError::param("An output has to be given when reading from stdin"))
+    }
+    let name = match extension {
+        Some(extension) => input.file_stem().map(|stem| PathBuf::from(format!("{}.{}", stem.to_string_lossy(), extension))),
+        None => input.file_stem().map(PathBuf::from),
+    };
+    let name = name.ok_or_else(|| {
+        ConvertError::param("can't name an output after it").in_file(input)
+    })?;
+    Ok(match out_root {
+        Some(out_root) => out_root.join(name),
+        None => input.with_file_name(name),
+    })
+}
+
+/// Run `f` for every input, carrying on past failures so one bad archive doesn't stop the rest
+pub fn run<F>(inputs: &[PathBuf], mut f: F) -> Result<(), ConvertError>
+    where F: FnMut(&Path) -> Result<(), ConvertError>
+{
+    if let [input] = inputs {
+        return f(input)
+    }
+
+    let failures: Vec<ConvertErrorKind> = inputs.iter()
+        .filter_map(|input| f(input).err())
+        .map(report)
+        .collect();
+    summarize(failures, inputs.len())
+}
+
+/// [`run`] for commands that only write files, working on several inputs at once. Failures are
+/// still summed up by the first input that failed, in the order given.
+pub fn run_parallel<F>(inputs: &[PathBuf], f: F) -> Result<(), ConvertError>
+    where F: Fn(&Path) -> Result<(), ConvertError> + Sync
+{
+    if let [input] = inputs {
+        return f(input)
+    }
+
+    let failures: Vec<ConvertErrorKind> = inputs.par_iter()
+        .filter_map(|input| f(input).err())
+        .map(report)
+        .collect();
+    summarize(failures, inputs.len())
+}
+
+fn report(err: ConvertError) -> ConvertErrorKind {
+    logger::error(&err);
+    err.kind
+}
+
+fn summarize(failures: Vec<ConvertErrorKind>, inputs: usize) -> Result<(), ConvertError> {
+    let count = failures.len();
+    match failures.into_iter().next() {
+        Some(kind) => Err(ConvertError::new(kind, format!("{} of {} inputs failed", count, inputs))),
+        None => Ok(()),
+    }
+}
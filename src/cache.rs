@@ -0,0 +1,84 @@
+//! The cache `zip --update` keeps in the packed directory: entries converted from YAML and
+//! compressed by earlier builds, each stored under the SHA-256 of what it was made from and how,
+//! so only the files that changed are converted and compressed again
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, RwLock};
+
+use sha2::{Digest, Sha256};
+
+use crate::{stdio, ConvertError};
+
+/// Directory of the cache, inside of the packed directory, which is never packed itself
+pub const CACHE_DIR: &str = ".sarctool-cache";
+
+static CACHE: RwLock<Option<BuildCache>> = RwLock::new(None);
+
+struct BuildCache {
+    dir: PathBuf,
+    /// Names of what the current build has looked up
+    used: Mutex<HashSet<String>>,
+}
+
+/// Keep what [`cached`] makes in `dir` from now on, or stop caching without one
+pub fn init(dir: Option<&Path>) -> Result<(), ConvertError> {
+    if let Some(dir) = dir.filter(|_| !stdio::is_dry_run()) {
+        fs::create_dir_all(dir).map_err(|err| ConvertError::io(dir, err))?;
+    }
+    *CACHE.write().unwrap() = dir.map(|dir| BuildCache { dir: dir.to_owned(), used: Mutex::default() });
+    Ok(())
+}
+
+/// What `make` makes of `data`, from the cache if an earlier build made it. `settings` has to
+/// cover everything else the result depends on. Without a cache this is just `make`.
+pub fn cached<F>(settings: &[&[u8]], data: Vec<u8>, make: F) -> Result<Vec<u8>, ConvertError>
+    where F: FnOnce(Vec<u8>) -> Result<Vec<u8>, ConvertError>
+{
+    let cache = CACHE.read().unwrap();
+    let cache = match cache.as_ref() {
+        Some(cache) => cache,
+        None => return make(data),
+    };
+    let mut hasher = Sha256::new();
+    for part in settings.iter().chain([&&data[..]]) {
+        // lengths keep the parts from running into each other
+        hasher.update((part.len() as u64).to_le_bytes());
+        hasher.update(part);
+    }
+    let name: String = hasher.finalize().iter().map(|byte| format!("{:02x}", byte)).collect();
+    let path = cache.dir.join(&name);
+    cache.used.lock().unwrap().insert(name);
+
+    if let Ok(data) = fs::read(&path) {
+        log::debug!("Reusing {} from the cache", path.display());
+        return Ok(data)
+    }
+    let data = make(data)?;
+    if !stdio::is_dry_run() {
+        // written under another name first, so a build that's cut short can't leave half of it
+        let partial = path.with_extension(format!("partial{}", std::process::id()));
+        if let Err(err) = fs::write(&partial, &data).and_then(|()| fs::rename(&partial, &path)) {
+            log::warn!("Couldn't add {} to the cache: {}", path.display(), err);
+        }
+    }
+    Ok(data)
+}
+
+/// Remove everything the build since the last call didn't use, so the cache doesn't grow with
+/// every edit
+pub fn prune() {
+    let cache = CACHE.read().unwrap();
+    let cache = match cache.as_ref() {
+        Some(cache) if !stdio::is_dry_run() => cache,
+        _ => return,
+    };
+    let mut used = cache.used.lock().unwrap();
+    for entry in fs::read_dir(&cache.dir).into_iter().flatten().flatten() {
+        if !used.contains(entry.file_name().to_string_lossy().as_ref()) {
+            log::debug!("Removing {} from the cache", entry.path().display());
+            let _ = fs::remove_file(entry.path());
+        }
+    }
+    used.clear();
+}
@@ -0,0 +1,120 @@
+//! Checksum manifests, the size and SHA-256 of every entry of an archive, written by
+//! `manifest create` for mod authors to publish and checked by `manifest verify` against the
+//! archive a user installed
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::archive::{Archive, Entry};
+use crate::inspect::sha256;
+use crate::{stdio, ConvertError};
+
+/// Bumped whenever the format changes, so old manifests aren't misread
+const VERSION: u32 = 1;
+
+#[derive(Serialize, Deserialize)]
+pub struct ChecksumManifest {
+    version: u32,
+    /// File name of the archive the manifest was made from
+    pub archive: String,
+    pub entries: Vec<EntryChecksum>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct EntryChecksum {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    pub hash: u32,
+    pub size: usize,
+    pub sha256: String,
+}
+
+impl EntryChecksum {
+    fn display_name(&self) -> String {
+        self.name.clone().unwrap_or_else(|| format!("[{:08X}]", self.hash))
+    }
+}
+
+/// An entry that isn't as the manifest says
+pub enum Mismatch {
+    Missing(String),
+    Added(String),
+    Size { name: String, expected: usize, actual: usize },
+    Changed(String),
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Mismatch::Missing(name) => write!(f, "{} is missing", name),
+            Mismatch::Added(name) => write!(f, "{} isn't in the manifest", name),
+            Mismatch::Size { name, expected, actual } => {
+                write!(f, "{} is {} bytes instead of {}", name, actual, expected)
+            }
+            Mismatch::Changed(name) => write!(f, "{} has different data", name),
+        }
+    }
+}
+
+impl ChecksumManifest {
+    pub fn create(archive: &Archive, archive_name: &str) -> Self {
+        let entries: Vec<Entry> = archive.entries().collect();
+        let entries = entries.par_iter()
+            .map(|entry| EntryChecksum {
+                name: entry.name.map(str::to_owned),
+                hash: entry.hash,
+                size: entry.data.len(),
+                sha256: sha256(entry.data),
+            })
+            .collect();
+        ChecksumManifest { version: VERSION, archive: archive_name.to_owned(), entries }
+    }
+
+    pub fn read(path: &Path) -> Result<Self, ConvertError> {
+        let file = File::open(path).map_err(|err| ConvertError::io(path, err))?;
+        let manifest: ChecksumManifest = serde_json::from_reader(BufReader::new(file))
+            .map_err(|err| ConvertError::file(&format!("{}: not a checksum manifest ({})", path.display(), err)))?;
+        if manifest.version != VERSION {
+            return Err(ConvertError::file(&format!(
+                "{}: made by a different version of sarctool, run `manifest create` again", path.display()
+            )))
+        }
+        Ok(manifest)
+    }
+
+    pub fn write(&self, path: &Path) -> Result<(), ConvertError> {
+        stdio::create(path, |out| {
+            serde_json::to_writer_pretty(out, self).map_err(|err| ConvertError::file(&format!("{}: {}", path.display(), err)))
+        })
+    }
+
+    /// Every entry that differs from the manifest, matched up by hash so a name map doesn't
+    /// need to be given to both commands, in the order of the manifest and then the archive
+    pub fn verify(&self, archive: &Archive) -> Vec<Mismatch> {
+        let entries: HashMap<u32, Entry> = archive.entries().map(|entry| (entry.hash, entry)).collect();
+        let mut mismatches: Vec<Mismatch> = self.entries.par_iter()
+            .filter_map(|expected| {
+                let name = expected.display_name();
+                match entries.get(&expected.hash) {
+                    None => Some(Mismatch::Missing(name)),
+                    Some(entry) if entry.data.len() != expected.size => {
+                        Some(Mismatch::Size { name, expected: expected.size, actual: entry.data.len() })
+                    }
+                    Some(entry) if sha256(entry.data) != expected.sha256 => Some(Mismatch::Changed(name)),
+                    Some(_) => None,
+                }
+            })
+            .collect();
+
+        let listed: HashMap<u32, &EntryChecksum> = self.entries.iter().map(|entry| (entry.hash, entry)).collect();
+        mismatches.extend(archive.entries()
+            .filter(|entry| !listed.contains_key(&entry.hash))
+            .map(|entry| Mismatch::Added(entry.display_name().into_owned())));
+        mismatches
+    }
+}
@@ -0,0 +1,351 @@
+//! Outer compression of an archive, detected from the magic of the file
+use std::io::{Cursor, Read, Write};
+use std::path::Path;
+use std::sync::mpsc;
+
+use indicatif::ProgressBar;
+
+use serde::{Deserialize, Serialize};
+
+use crate::dictionary::{self, Dictionaries};
+use crate::{progress, ConvertError};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Compression {
+    None,
+    Yaz0,
+    Zstd,
+    /// LZ4 frames
+    Lz4,
+    /// A single raw LZ4 block, after its decompressed size as a little endian u32
+    #[serde(rename = "lz4-block")]
+    Lz4Block,
+}
+
+pub const ZSTD_MAGIC: &[u8] = b"\x28\xB5\x2F\xFD";
+pub const LZ4_MAGIC: &[u8] = b"\x04\x22\x4D\x18";
+
+/// Extensions of archives which get an `s` prefix when yaz0-compressed
+const SARC_EXTENSIONS: &[&str] = &[
+    "arc", "pack", "bactorpack", "beventpack", "bgenv", "blarc", "bmodelsh", "bquestpack", "bstats",
+];
+
+#[derive(Debug, Clone, Copy)]
+pub struct CompressionOptions<'a> {
+    /// 0 stores the data without compressing it, 1-9 trade speed for size and 10 is the
+    /// slowest but smallest
+    pub yaz0_level: usize,
+    pub zstd_level: i32,
+    /// Worker threads used by zstd, 0 to compress on the calling thread
+    pub threads: u32,
+    pub dictionary: Option<&'a [u8]>,
+}
+
+impl Default for CompressionOptions<'_> {
+    fn default() -> Self {
+        CompressionOptions {
+            yaz0_level: 10,
+            zstd_level: zstd::DEFAULT_COMPRESSION_LEVEL,
+            threads: 0,
+            dictionary: None,
+        }
+    }
+}
+
+impl Compression {
+    pub fn detect(data: &[u8]) -> Self {
+        if data.starts_with(b"Yaz0") {
+            Compression::Yaz0
+        } else if data.starts_with(ZSTD_MAGIC) {
+            Compression::Zstd
+        } else if data.starts_with(LZ4_MAGIC) {
+            Compression::Lz4
+        } else if is_lz4_block(data) {
+            Compression::Lz4Block
+        } else {
+            Compression::None
+        }
+    }
+
+    /// The compression conventionally used for files with the given name, if the extension is
+    /// one of the known SARC extensions
+    pub fn from_extension(path: &Path) -> Option<Self> {
+        let extension = path.extension().map(|ext| ext.to_string_lossy().to_lowercase());
+        match extension.as_deref() {
+            Some("zs") => Some(Compression::Zstd),
+            Some("lz4") => Some(Compression::Lz4),
+            Some("szs") => Some(Compression::Yaz0),
+            Some("sarc") => Some(Compression::None),
+            Some(ext) if SARC_EXTENSIONS.contains(&ext) => Some(Compression::None),
+            Some(ext) if ext.strip_prefix('s').is_some_and(|ext| SARC_EXTENSIONS.contains(&ext)) => {
+                Some(Compression::Yaz0)
+            }
+            _ => None,
+        }
+    }
+
+    /// The compression conventionally used for an entry inside of an archive: zstd for `.zs`, and
+    /// yaz0 for `.szs` and extensions with an `s` prefix like `.sbfres`
+    pub fn for_entry(name: &str) -> Option<Self> {
+        let (_, extension) = name.rsplit_once('.')?;
+        let extension = extension.to_lowercase();
+        if extension == "zs" {
+            Some(Compression::Zstd)
+        } else if extension == "szs" || (extension.len() > 2 && extension.starts_with("sb")) {
+            Some(Compression::Yaz0)
+        } else {
+            None
+        }
+    }
+
+    pub fn compress(self, data: Vec<u8>, options: &CompressionOptions) -> Result<Vec<u8>, ConvertError> {
+        if self == Compression::None {
+            return Ok(data)
+        }
+        let progress = progress::bytes(data.len(), "Compressing");
+        let out = self.compress_with(data, options, &progress)?;
+        progress.finish_and_clear();
+        Ok(out)
+    }
+
+    /// Compress without a progress bar of its own, for entries compressed alongside each other
+    pub fn compress_entry(self, data: Vec<u8>, options: &CompressionOptions) -> Result<Vec<u8>, ConvertError> {
+        self.compress_with(data, options, &ProgressBar::hidden())
+    }
+
+    fn compress_with(self, data: Vec<u8>, options: &CompressionOptions, progress: &ProgressBar) -> Result<Vec<u8>, ConvertError> {
+        let out = match self {
+            Compression::None => data,
+            Compression::Yaz0 if options.yaz0_level < 10 => yaz0_compress(&data, options.yaz0_level, progress),
+            Compression::Yaz0 => {
+                let mut out = vec![];
+                let (tx, rx) = mpsc::channel::<yaz0::deflate::ProgressMsg>();
+                std::thread::scope(|scope| {
+                    scope.spawn(|| {
+                        for msg in rx {
+                            progress.set_position(msg.read_head as u64);
+                        }
+                    });
+                    yaz0::Yaz0Writer::new(&mut out)
+                        .compress_and_write_with_progress(&data, yaz0::CompressionLevel::Lookahead { quality: 10 }, tx)
+                })
+                .map_err(|err| ConvertError::yaz0(format!("{:?}", err)))?;
+                out
+            }
+            Compression::Zstd => {
+                let mut encoder = zstd_encoder(vec![], options)?;
+                for chunk in data.chunks(PROGRESS_CHUNK) {
+                    encoder.write_all(chunk).map_err(zstd_error)?;
+                    progress.inc(chunk.len() as u64);
+                }
+                encoder.finish().map_err(zstd_error)?
+            }
+            Compression::Lz4 => {
+                let mut encoder = lz4_flex::frame::FrameEncoder::new(vec![]);
+                for chunk in data.chunks(PROGRESS_CHUNK) {
+                    encoder.write_all(chunk)?;
+                    progress.inc(chunk.len() as u64);
+                }
+                encoder.finish().map_err(lz4_error)?
+            }
+            Compression::Lz4Block => lz4_flex::block::compress_prepend_size(&data),
+        };
+        Ok(out)
+    }
+
+    /// Compress the `len` bytes produced by `write` straight into `out`. Only yaz0 and LZ4
+    /// blocks need the whole input up front, everything else is streamed.
+    pub fn compress_to<F>(
+        self, len: usize, out: &mut dyn Write, options: &CompressionOptions, write: F
+    ) -> Result<(), ConvertError>
+        where F: FnOnce(&mut dyn Write) -> Result<(), ConvertError>
+    {
+        match self {
+            Compression::None => {
+                let progress = progress::bytes(len, "Writing");
+                write(&mut progress.wrap_write(out))?;
+                progress.finish_and_clear();
+            }
+            Compression::Yaz0 | Compression::Lz4Block => {
+                let mut data = Vec::with_capacity(len);
+                write(&mut data)?;
+                out.write_all(&self.compress(data, options)?)?;
+            }
+            Compression::Zstd => {
+                let progress = progress::bytes(len, "Compressing");
+                let mut encoder = zstd_encoder(out, options)?;
+                write(&mut progress.wrap_write(&mut encoder))?;
+                encoder.finish().map_err(zstd_error)?;
+                progress.finish_and_clear();
+            }
+            Compression::Lz4 => {
+                let progress = progress::bytes(len, "Compressing");
+                let mut encoder = lz4_flex::frame::FrameEncoder::new(out);
+                write(&mut progress.wrap_write(&mut encoder))?;
+                encoder.finish().map_err(lz4_error)?;
+                progress.finish_and_clear();
+            }
+        }
+        Ok(())
+    }
+}
+
+fn zstd_error(err: std::io::Error) -> ConvertError {
+    ConvertError::zstd(err.to_string())
+}
+
+fn lz4_error<E: ToString>(err: E) -> ConvertError {
+    ConvertError::lz4(err.to_string())
+}
+
+/// Raw LZ4 blocks have no magic, but the SARC magic of one starts the literals of its first
+/// sequence, after the size and the token with the length of the literals
+fn is_lz4_block(data: &[u8]) -> bool {
+    let token = match data.get(4) {
+        Some(token) => token >> 4,
+        None => return false,
+    };
+    let literals = match token {
+        15 => 6 + data[5..].iter().take_while(|&&byte| byte == 0xFF).count(),
+        4..=14 => 5,
+        _ => return false,
+    };
+    data.get(literals..literals + 4) == Some(b"SARC")
+}
+
+fn zstd_encoder<'a, W: Write>(out: W, options: &CompressionOptions<'a>) -> Result<zstd::stream::Encoder<'a, W>, ConvertError> {
+    let level = options.zstd_level;
+    let mut encoder = match options.dictionary {
+        Some(dictionary) => zstd::stream::Encoder::with_dictionary(out, level, dictionary),
+        None => zstd::stream::Encoder::new(out, level),
+    }.map_err(zstd_error)?;
+    encoder.multithread(options.threads).map_err(zstd_error)?;
+    Ok(encoder)
+}
+
+/// How often compression progress is updated
+const PROGRESS_CHUNK: usize = 0x100000;
+
+const YAZ0_WINDOW: usize = 0x1000;
+const YAZ0_MIN_MATCH: usize = 3;
+const YAZ0_MAX_MATCH: usize = 0x111;
+const HASH_BITS: usize = 15;
+
+fn hash3(data: &[u8], pos: usize) -> usize {
+    ((data[pos] as usize) << 10 ^ (data[pos + 1] as usize) << 5 ^ data[pos + 2] as usize)
+        & ((1 << HASH_BITS) - 1)
+}
+
+struct HashChains {
+    head: Vec<usize>,
+    prev: Vec<usize>,
+}
+
+impl HashChains {
+    fn insert(&mut self, data: &[u8], pos: usize) {
+        if pos + YAZ0_MIN_MATCH <= data.len() {
+            let hash = hash3(data, pos);
+            self.prev[pos] = self.head[hash];
+            self.head[hash] = pos;
+        }
+    }
+}
+
+/// Yaz0 encoder for the faster levels: matches are found through hash chains, which are searched
+/// up to a length depending on the level. Level 0 only writes literals.
+fn yaz0_compress(data: &[u8], level: usize, progress: &ProgressBar) -> Vec<u8> {
+    let max_chain = if level == 0 { 0 } else { 1 << (level - 1) };
+    let mut chains = HashChains {
+        head: vec![usize::MAX; 1 << HASH_BITS],
+        prev: vec![usize::MAX; data.len()],
+    };
+
+    let mut out = Vec::with_capacity(0x10 + data.len() + data.len() / 8 + 1);
+    out.extend_from_slice(b"Yaz0");
+    out.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    out.extend_from_slice(&[0; 8]);
+
+    let mut pos = 0;
+    let mut next_progress = PROGRESS_CHUNK;
+    while pos < data.len() {
+        if pos >= next_progress {
+            progress.set_position(pos as u64);
+            next_progress = pos + PROGRESS_CHUNK;
+        }
+        let flags = out.len();
+        out.push(0);
+        for bit in 0..8 {
+            if pos >= data.len() {
+                break
+            }
+
+            let max_len = YAZ0_MAX_MATCH.min(data.len() - pos);
+            let (mut best_len, mut best_dist) = (0, 0);
+            if max_chain != 0 && max_len >= YAZ0_MIN_MATCH {
+                let mut candidate = chains.head[hash3(data, pos)];
+                let mut chain = 0;
+                while candidate != usize::MAX && pos - candidate <= YAZ0_WINDOW && chain < max_chain {
+                    let len = data[candidate..].iter()
+                        .zip(&data[pos..pos + max_len])
+                        .take_while(|(a, b)| a == b)
+                        .count();
+                    if len > best_len {
+                        best_len = len;
+                        best_dist = pos - candidate;
+                        if len == max_len {
+                            break
+                        }
+                    }
+                    candidate = chains.prev[candidate];
+                    chain += 1;
+                }
+            }
+
+            if best_len >= YAZ0_MIN_MATCH {
+                let dist = best_dist - 1;
+                if best_len < 0x12 {
+                    out.push((((best_len - 2) << 4) | (dist >> 8)) as u8);
+                    out.push(dist as u8);
+                } else {
+                    out.push((dist >> 8) as u8);
+                    out.push(dist as u8);
+                    out.push((best_len - 0x12) as u8);
+                }
+                for pos in pos..pos + best_len {
+                    chains.insert(data, pos);
+                }
+                pos += best_len;
+            } else {
+                out[flags] |= 0x80 >> bit;
+                out.push(data[pos]);
+                chains.insert(data, pos);
+                pos += 1;
+            }
+        }
+    }
+
+    out
+}
+
+pub fn decompress(data: &[u8], dictionaries: &Dictionaries) -> Result<Vec<u8>, ConvertError> {
+    match Compression::detect(data) {
+        Compression::None => Ok(data.to_vec()),
+        Compression::Yaz0 => yaz0::Yaz0Archive::new(Cursor::new(data))
+            .and_then(|mut archive| archive.decompress())
+            .map_err(|err| ConvertError::yaz0(format!("{:?}", err))),
+        Compression::Zstd => match dictionaries.for_frame(data) {
+            Some(dictionary) => dictionary::decompress_with(data, dictionary),
+            None if zstd::zstd_safe::get_dict_id_from_frame(data).is_some() => Err(ConvertError::zstd(
+                "compressed with a zstd dictionary, pass the dictionaries with --dict"
+            )),
+            None => zstd::stream::decode_all(data).map_err(|err| ConvertError::zstd(err.to_string())),
+        },
+        Compression::Lz4 => {
+            let mut out = vec![];
+            lz4_flex::frame::FrameDecoder::new(data).read_to_end(&mut out).map_err(lz4_error)?;
+            Ok(out)
+        }
+        Compression::Lz4Block => lz4_flex::block::decompress_size_prepended(data).map_err(lz4_error),
+    }
+}
@@ -0,0 +1,55 @@
+//! User defaults for packing options, read from `~/.config/sarctool/config.toml` or `--config`
+//!
+//! Anything given on the command line wins over the config. A `--preset` on the command line
+//! also replaces the byte order and compression of the config, since those are what the preset
+//! is for.
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+
+use crate::compression::Compression;
+use crate::metadata::ByteOrder;
+use crate::preset::Preset;
+use crate::ConvertError;
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Config {
+    pub endian: Option<ByteOrder>,
+    pub compression: Option<Compression>,
+    pub zstd_level: Option<i32>,
+    pub yaz0_level: Option<usize>,
+    pub dict: Option<PathBuf>,
+    pub preset: Option<Preset>,
+}
+
+impl Config {
+    /// Load `path`, or the default config file if there is one
+    pub fn load(path: Option<&Path>) -> Result<Self, ConvertError> {
+        let path = match path.map(Path::to_owned).or_else(default_path) {
+            Some(path) => path,
+            None => return Ok(Config::default()),
+        };
+
+        log::debug!("Loading config from {}", path.display());
+        let text = fs::read_to_string(&path)
+            .map_err(|err| ConvertError::file(&format!("{}: {}", path.display(), err)))?;
+        let config: Config = toml::from_str(&text)
+            .map_err(|err| ConvertError::param(&format!("{}: {}", path.display(), err)))?;
+        if config.yaz0_level.is_some_and(|level| level > 10) {
+            return Err(ConvertError::param(&format!(
+                "{}: yaz0-level must be between 0 and 10", path.display()
+            )))
+        }
+        Ok(config)
+    }
+}
+
+fn default_path() -> Option<PathBuf> {
+    let config_dir = std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".config")))
+        .or_else(|| std::env::var_os("APPDATA").map(PathBuf::from))?;
+    Some(config_dir.join("sarctool").join("config.toml")).filter(|path| path.is_file())
+}
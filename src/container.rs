@@ -0,0 +1,178 @@
+//! The kinds of archive sarctool reads and writes, told apart by their magic
+//!
+//! Each one implements [`ArchiveContainer`], which reads the entries of an archive into a
+//! [`Layout`] and writes the entries of a [`SarcWriter`] out again. Everything else, from the
+//! outer compression to extracting and packing directories, is shared, so a new kind of archive
+//! only needs those two halves. Other crates can add their own with [`register`].
+use std::fmt;
+use std::io::Write;
+use std::ops::Deref;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::RwLock;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::layout::Layout;
+use crate::u8_archive::U8Container;
+use crate::writer::{SarcWriter, DEFAULT_ALIGNMENT};
+use crate::ConvertError;
+
+/// Names `--container` takes
+pub const CONTAINERS: &[&str] = &["sarc", "u8"];
+
+/// A kind of archive: its entries come out of [`parse`](Self::parse) and go in through
+/// [`write`](Self::write), with what's needed to rebuild it as it was in the [`Layout`]
+pub trait ArchiveContainer: Send + Sync {
+    /// Lowercase name, used by `--container` and recorded in the metadata of extracted archives
+    fn name(&self) -> &'static str;
+
+    /// Extensions of outputs that should be written as this kind of archive, in lowercase
+    fn extensions(&self) -> &'static [&'static str] {
+        &[]
+    }
+
+    /// Whether uncompressed data is this kind of archive, usually by its magic
+    fn detect(&self, data: &[u8]) -> bool;
+
+    /// The entries and layout of an uncompressed archive
+    fn parse(&self, data: &[u8]) -> Result<Layout, ConvertError>;
+
+    /// Write the entries, using the offsets and sizes of the writer as far as they fit
+    fn write(&self, archive: &SarcWriter, out: &mut dyn Write) -> Result<(), ConvertError>;
+
+    /// Size of what [`write`](Self::write) would write
+    fn file_size(&self, archive: &SarcWriter) -> usize;
+
+    /// Alignment of the data of entries, when nothing else asks for one
+    fn default_alignment(&self) -> usize {
+        DEFAULT_ALIGNMENT
+    }
+
+    /// Whether the games look entries up by their hash in a table that has to be sorted
+    fn sorted_by_hash(&self) -> bool {
+        false
+    }
+}
+
+/// SARC, the archive of most titles since the Wii U
+pub struct SarcContainer;
+
+impl ArchiveContainer for SarcContainer {
+    fn name(&self) -> &'static str {
+        "sarc"
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["sarc"]
+    }
+
+    fn detect(&self, data: &[u8]) -> bool {
+        data.starts_with(b"SARC")
+    }
+
+    fn parse(&self, data: &[u8]) -> Result<Layout, ConvertError> {
+        Layout::parse(data)
+    }
+
+    fn write(&self, archive: &SarcWriter, out: &mut dyn Write) -> Result<(), ConvertError> {
+        archive.write_sarc(out)
+    }
+
+    fn file_size(&self, archive: &SarcWriter) -> usize {
+        archive.sarc_file_size()
+    }
+
+    fn sorted_by_hash(&self) -> bool {
+        true
+    }
+}
+
+/// One of the kinds of archive, compared by name
+#[derive(Clone, Copy)]
+pub struct Container(&'static dyn ArchiveContainer);
+
+impl Container {
+    pub const SARC: Container = Container(&SarcContainer);
+    pub const U8: Container = Container(&U8Container);
+
+    /// The container of uncompressed data, if it's an archive at all
+    pub fn detect(data: &[u8]) -> Option<Self> {
+        containers().into_iter().find(|container| container.0.detect(data))
+    }
+
+    /// The container the name of an output calls for, if it's clear from it. `.szs` is used
+    /// for more than one.
+    pub fn from_extension(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_string_lossy().to_lowercase();
+        containers().into_iter().find(|container| container.extensions().contains(&extension.as_str()))
+    }
+}
+
+static REGISTERED: RwLock<Vec<Container>> = RwLock::new(Vec::new());
+
+/// Make a kind of archive known to every command, after the built-in ones
+pub fn register(container: &'static dyn ArchiveContainer) {
+    REGISTERED.write().unwrap().push(Container(container));
+}
+
+/// Every known kind of archive, built-in ones first
+pub fn containers() -> Vec<Container> {
+    let mut containers = vec![Container::SARC, Container::U8];
+    containers.extend(REGISTERED.read().unwrap().iter().copied());
+    containers
+}
+
+impl Deref for Container {
+    type Target = dyn ArchiveContainer;
+
+    fn deref(&self) -> &Self::Target {
+        self.0
+    }
+}
+
+impl Default for Container {
+    fn default() -> Self {
+        Container::SARC
+    }
+}
+
+impl PartialEq for Container {
+    fn eq(&self, other: &Self) -> bool {
+        self.name() == other.name()
+    }
+}
+
+impl fmt::Debug for Container {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl fmt::Display for Container {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Container {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        containers().into_iter()
+            .find(|container| container.name() == s)
+            .ok_or_else(|| format!("unknown container '{}'", s))
+    }
+}
+
+impl Serialize for Container {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.name())
+    }
+}
+
+impl<'de> Deserialize<'de> for Container {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?.parse().map_err(serde::de::Error::custom)
+    }
+}
@@ -0,0 +1,755 @@
+//! Rewriting archives: changing their layout or byte order, repairing them, merging mods into
+//! them, and converting them to and from other archive formats
+use std::collections::{BTreeMap, HashMap};
+use std::convert::TryFrom;
+use std::fs::{self, File};
+use std::io::{prelude::*, BufWriter, Cursor};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use rayon::prelude::*;
+use time::OffsetDateTime;
+use zip::{DateTime, ZipArchive, ZipWriter, write::FileOptions};
+
+use crate::archive::{self, Archive, ArchiveData, Entry, SharedData};
+use crate::compression::{self, Compression, CompressionOptions};
+use crate::config::Config;
+use crate::container::Container;
+use crate::dictionary::Dictionaries;
+use crate::layout::DEFAULT_HASH_KEY;
+use crate::metadata::{ArchiveMetadata, ByteOrder, EntryMetadata, METADATA_FILE};
+use crate::pack::{self, write, write_compressed, write_with, PackArgs};
+use crate::tarball::{self, TarCompression};
+use crate::writer::{self, EntryData, SarcWriter, WriterEntry, DEFAULT_ALIGNMENT};
+use crate::{progress, sevenz, stdio, ConvertError};
+
+/// Change the outer compression of an archive to the one `pack` picks, without touching the
+/// archive inside of it
+pub fn recompress(pack: &PackArgs, in_file: &Path, out_file: &Path, dict: Option<&Path>) -> Result<(), ConvertError> {
+    let data = ArchiveData::open(in_file)?;
+    let from = Compression::detect(&data);
+    let payload = match from {
+        // copied out of the map, in case the output is the same file
+        Compression::None => data.to_vec(),
+        _ => {
+            let dictionaries = if from == Compression::Zstd {
+                Dictionaries::load(dict, in_file)?
+            } else {
+                Dictionaries::default()
+            };
+            compression::decompress(&data, &dictionaries).map_err(|err| err.in_file(in_file))?
+        }
+    };
+    drop(data);
+    if Container::detect(&payload).is_none() {
+        return Err(ConvertError::sarc("not an archive, compressed or not").in_file(in_file))
+    }
+
+    let to = pack.compression(out_file);
+    let dictionaries = if to == Compression::Zstd && (dict.is_some() || pack.find_dictionaries()) {
+        Dictionaries::load(dict, out_file)?
+    } else {
+        Dictionaries::default()
+    };
+    let options = CompressionOptions {
+        dictionary: dictionaries.for_output(out_file),
+        ..pack.compression_options()
+    };
+    log::info!("Recompressing {} from {:?} to {:?}", in_file.display(), from, to);
+    stdio::create(out_file, |out| {
+        to.compress_to(payload.len(), out, &options, |out| out.write_all(&payload).map_err(ConvertError::from))
+    })
+}
+
+/// Rewrite an archive in another byte order, keeping everything else
+pub fn convert_endian(in_file: &Path, out_file: &Path, to: ByteOrder, dict: Option<&Path>) -> Result<(), ConvertError> {
+    let archive = Archive::open(in_file, dict)?;
+    let mut sarc = archive.to_writer();
+    sarc.byte_order = to.into();
+    write_compressed(sarc, out_file, archive.compression, &archive.dictionaries)
+}
+
+/// Rewrite an archive with whatever byte order, alignment or compression `pack` gives, keeping
+/// the rest as it was
+pub fn convert(pack: &PackArgs, in_file: &Path, out_file: &Path, dict: Option<&Path>) -> Result<(), ConvertError> {
+    let archive = Archive::open(in_file, dict)?;
+    let mut sarc = archive.to_writer();
+    if pack.sets_byte_order() {
+        sarc.byte_order = pack.byte_order();
+    }
+    if pack.sets_alignment() {
+        sarc.data_offset = None;
+        sarc.file_size = None;
+        for entry in &mut sarc.entries {
+            entry.offset = None;
+        }
+        pack.alignment().apply(&mut sarc);
+    }
+    let compression = pack.explicit_compression().unwrap_or(archive.compression);
+    write_with(sarc, out_file, compression, pack, dict)
+}
+
+/// Replace the data of one entry, `[HASH]` for one without a name
+pub fn update(in_file: &Path, name: &str, data: Vec<u8>, out_file: &Path, dict: Option<&Path>) -> Result<(), ConvertError> {
+    let archive = Archive::open(in_file, dict)?;
+    let i = archive.entries()
+        .position(|entry| entry.display_name() == name)
+        .ok_or_else(|| ConvertError::file(&format!("no entry named {}", name)).in_file(in_file))?;
+    replace_entries(archive, vec![(i, data)], out_file)
+}
+
+/// Replace the data of entries by their position in the archive. The other entries keep their
+/// offsets unless new data runs into them, in which case they move along keeping the alignment
+/// they had.
+pub fn replace_entries(archive: Archive, replaced: Vec<(usize, Vec<u8>)>, out_file: &Path) -> Result<(), ConvertError> {
+    let mut sarc = archive.to_writer();
+    for entry in &mut sarc.entries {
+        entry.alignment = entry.offset.map(|offset| match offset {
+            0 => DEFAULT_ALIGNMENT,
+            offset => (1 << offset.trailing_zeros()).min(DEFAULT_ALIGNMENT),
+        });
+    }
+    for (i, data) in replaced {
+        let entry = &mut sarc.entries[i];
+        if entry.data.len() != data.len() {
+            // the archive ends with its last entry wherever that ends up
+            sarc.file_size = None;
+        }
+        log::info!("Updating {} ({} -> {} bytes)", writer::display_name(entry), entry.data.len(), data.len());
+        entry.data = data.into();
+    }
+
+    let Archive { compression, dictionaries, data, .. } = archive;
+    // unmapped before the archive is written over
+    drop(data);
+    write_compressed(sarc, out_file, compression, &dictionaries)
+}
+
+/// Rebuild a damaged archive from whatever can still be read of it, returning what was fixed
+pub fn repair(in_file: &Path, out_file: &Path, dict: Option<&Path>) -> Result<Vec<String>, ConvertError> {
+    let Archive { compression, data, layout, dictionaries, .. } = Archive::open_unchecked(in_file, dict)?;
+    let mut fixes = vec![];
+
+    if layout.file_size as usize != data.len() {
+        fixes.push(format!(
+            "Header file size was {:#x}, actual size is {:#x}", layout.file_size, data.len()
+        ));
+    }
+    if layout.container.sorted_by_hash() && layout.nodes.windows(2).any(|nodes| nodes[0].hash > nodes[1].hash) {
+        fixes.push("SFAT was not sorted by hash".to_owned());
+    }
+
+    let alignment = layout.alignment(DEFAULT_ALIGNMENT);
+    let mut entries = vec![];
+    for (i, node) in layout.nodes.iter().enumerate() {
+        let display_name = node.name.clone().unwrap_or_else(|| format!("node {}", i));
+        let range = layout.absolute(node);
+        let file_data = match data.get(range.clone()) {
+            Some(file_data) => file_data.to_vec(),
+            None => {
+                fixes.push(format!(
+                    "Dropped {}: data {:#x}..{:#x} is outside of the archive",
+                    display_name, range.start, range.end
+                ));
+                continue
+            }
+        };
+        if node.name.is_none() && node.name_offset().is_some() {
+            fixes.push(format!("Could not read the name of {}, kept it unnamed", display_name));
+        }
+
+        let entry = match &node.name {
+            Some(name) => {
+                let entry = WriterEntry::named(name.clone(), file_data, layout.hash_key);
+                if entry.hash != node.hash {
+                    fixes.push(format!("Fixed hash of {}: {:08X} -> {:08X}", name, node.hash, entry.hash));
+                }
+                entry
+            }
+            None => WriterEntry {
+                name: None, hash: node.hash, data: file_data.into(), offset: None, alignment: None
+            },
+        };
+        // entries stay where they are, and only those in the way of others move, keeping the
+        // alignment they had
+        entries.push(WriterEntry {
+            offset: Some(node.data.start as usize),
+            alignment: Some(match node.data.start {
+                0 => alignment,
+                offset => (1 << offset.trailing_zeros()).min(DEFAULT_ALIGNMENT),
+            }),
+            ..entry
+        });
+    }
+
+    let placed = {
+        let refs: Vec<&WriterEntry> = entries.iter().collect();
+        let ties: Vec<usize> = (0..entries.len()).collect();
+        writer::place_data(&refs, &ties, alignment).1
+    };
+    let moved = entries.iter().zip(&placed).filter(|(entry, &(start, _))| entry.offset != Some(start)).count();
+    if moved > 0 {
+        fixes.push(format!("Moved {} entries that overlapped others", moved));
+    }
+
+    let mut writer = SarcWriter::new(layout.byte_order, entries);
+    writer.container = layout.container;
+    writer.alignment = alignment;
+    writer.hash_key = layout.hash_key;
+    writer.data_offset = Some(layout.data_offset as usize);
+    writer.file_size = Some(data.len());
+    write_compressed(writer, out_file, compression, &dictionaries)?;
+    Ok(fixes)
+}
+
+/// Files of each mod by their path relative to the mod, with the mods having each one in order
+pub fn mod_files(mods: &[PathBuf]) -> Result<BTreeMap<String, Vec<PathBuf>>, ConvertError> {
+    let mut files: BTreeMap<String, Vec<PathBuf>> = BTreeMap::new();
+    for mod_dir in mods {
+        if !mod_dir.is_dir() {
+            return Err(ConvertError::file("not a directory").in_file(mod_dir))
+        }
+        for entry in walkdir::WalkDir::new(mod_dir).sort_by_file_name() {
+            let entry = entry.map_err(|err| ConvertError::file(&err.to_string()))?;
+            if entry.file_type().is_file() {
+                let relative = entry.path().strip_prefix(mod_dir).unwrap_or(entry.path());
+                files.entry(relative.to_string_lossy().replace("\\", "/")).or_default().push(entry.into_path());
+            }
+        }
+    }
+    Ok(files)
+}
+
+/// Layer the files of each mod over a base dump, in order, merging the archives several mods
+/// change entry by entry
+pub fn merge_mods(base: &Path, mods: &[PathBuf], out_dir: &Path, dict: Option<&Path>) -> Result<(), ConvertError> {
+    if !base.is_dir() {
+        return Err(ConvertError::file("not a directory").in_file(base))
+    }
+    let files = mod_files(mods)?;
+    let dictionaries = Dictionaries::load_in(dict, base)?;
+
+    let progress = progress::entries(files.len(), "Merging");
+    files.par_iter().try_for_each(|(relative, paths)| -> Result<(), ConvertError> {
+        progress.inc(1);
+        let out_file = out_dir.join(relative);
+        if let Some(parent) = out_file.parent() {
+            stdio::create_dir_all(parent)?;
+        }
+        if !archive::is_archive_name(Path::new(relative)) {
+            // loose files can only be replaced as a whole
+            if paths.len() > 1 {
+                log::warn!("{} is in {} mods, using the one from {}", relative, paths.len(), paths[paths.len() - 1].display());
+            }
+            stdio::copy(&paths[paths.len() - 1], &out_file)?;
+            return Ok(())
+        }
+        merge_archive(&base.join(relative), paths, &out_file, &dictionaries).map_err(|err| err.in_entry(relative))
+    })?;
+    progress.finish_and_clear();
+    Ok(())
+}
+
+/// Apply the entries each mod changed from the base archive on top of it, in order
+pub fn merge_archive(base: &Path, paths: &[PathBuf], out_file: &Path, dictionaries: &Dictionaries) -> Result<(), ConvertError> {
+    let base = if base.is_file() { Some(Archive::open_shared(base, dictionaries)?) } else { None };
+    let base_entries: HashMap<String, &[u8]> = base.iter()
+        .flat_map(Archive::entries)
+        .map(|entry| (entry.display_name().into_owned(), entry.data))
+        .collect();
+
+    let mods = paths.iter()
+        .map(|path| Archive::open_shared(path, dictionaries))
+        .collect::<Result<Vec<_>, _>>()?;
+    let first = base.as_ref().unwrap_or(&mods[0]);
+    let mut sarc = match &base {
+        Some(base) => base.to_writer(),
+        None => SarcWriter::new(ByteOrder::from(&first.layout.byte_order).into(), vec![]),
+    };
+    sarc.hash_key = first.layout.hash_key;
+    let mut positions: HashMap<String, usize> = sarc.entries.iter()
+        .enumerate()
+        .map(|(i, entry)| (writer::display_name(entry), i))
+        .collect();
+
+    // which mod last changed each entry, to warn about mods overwriting each other
+    let mut changed_by: HashMap<String, &Path> = HashMap::new();
+    for (path, archive) in paths.iter().zip(&mods) {
+        for entry in archive.entries() {
+            let name = entry.display_name().into_owned();
+            if base_entries.get(&name) == Some(&entry.data) {
+                continue
+            }
+            if let Some(other) = changed_by.insert(name.clone(), path) {
+                log::warn!("{} is changed by both {} and {}, using the latter", name, other.display(), path.display());
+            }
+            match positions.get(&name) {
+                Some(&i) => sarc.entries[i].data = entry.data.to_vec().into(),
+                None => {
+                    positions.insert(name, sarc.entries.len());
+                    sarc.entries.push(WriterEntry {
+                        name: entry.name.map(String::from),
+                        hash: entry.hash,
+                        data: entry.data.to_vec().into(),
+                        offset: None,
+                        alignment: None,
+                    });
+                }
+            }
+        }
+    }
+    write_compressed(sarc, out_file, first.compression, dictionaries)
+}
+
+/// Convert an archive to a zip. The entries get the modification time of the archive, since
+/// SARCs have none of their own, and the zip gets the metadata `unzip` would write, so
+/// `from_zip` can rebuild the archive as it was. When reproducible, the entries all get the
+/// earliest time a zip can have instead, rather than that of the archive or the current one.
+pub fn to_zip(in_file: &Path, out_file: &Path, options: FileOptions, write_metadata: bool, dict: Option<&Path>) -> Result<(), ConvertError> {
+    let archive = Archive::open(in_file, dict)?;
+    let modified = fs::metadata(in_file).and_then(|metadata| metadata.modified()).ok();
+    let options = match zip_time(modified, stdio::is_reproducible()) {
+        Some(modified) => options.last_modified_time(modified),
+        None => options,
+    };
+
+    let metadata = serde_yaml::to_string(&zip_metadata(&archive))?;
+    let metadata_entry = Entry { name: Some(METADATA_FILE), hash: 0, offset: 0, data: metadata.as_bytes() };
+    let entries: Vec<Entry> = archive.entries().chain(write_metadata.then_some(metadata_entry)).collect();
+
+    if stdio::is_stdio(out_file) || stdio::is_dry_run() {
+        // zips are written to memory first, since stdout can't seek
+        let data = write_zip(entries.into_iter(), options, Cursor::new(vec![]))?.into_inner();
+        return stdio::write(out_file, &data)
+    }
+    if !stdio::should_write(out_file)? {
+        return Ok(())
+    }
+
+    let file = File::create(out_file).map_err(|err| ConvertError::io(out_file, err))?;
+    let result = write_zip(entries.into_iter(), options, BufWriter::new(file))
+        .and_then(|mut out| out.flush().map_err(ConvertError::from));
+    if result.is_err() {
+        let _ = fs::remove_file(out_file);
+    }
+    result.map(drop).map_err(|err| err.in_file(out_file))
+}
+
+/// Time to give the entries of a zip made from an archive modified at `modified`, leaving it to
+/// the zip writer when there's none
+fn zip_time(modified: Option<SystemTime>, reproducible: bool) -> Option<DateTime> {
+    if reproducible {
+        return Some(DateTime::default())
+    }
+    modified.and_then(|modified| DateTime::try_from(OffsetDateTime::from(modified)).ok())
+}
+
+/// Metadata of an archive with its entries at their paths in a zip
+fn zip_metadata(archive: &Archive) -> ArchiveMetadata {
+    ArchiveMetadata::new(&archive.layout, archive.compression, archive.entries()
+        .enumerate()
+        .map(|(i, entry)| EntryMetadata {
+            name: entry.name.map(String::from),
+            path: zip_name(&entry, i),
+            hash: entry.hash,
+            offset: (entry.offset - archive.layout.data_offset as usize) as u32,
+            index: entry.name.is_none().then_some(i),
+            byml_version: None,
+        })
+        .collect())
+}
+
+/// Path of an entry in a zip, with unnamed entries numbered by their position in the archive
+fn zip_name(entry: &Entry, i: usize) -> String {
+    entry.name.map(String::from).unwrap_or_else(|| format!("{}.bin", i))
+}
+
+/// Entries at least this big are written with zip64 sizes, since a deflated entry can end up
+/// slightly larger than it started
+pub const ZIP64_ENTRY_SIZE: usize = 0xF000_0000;
+
+/// Convert the entries and finish the zip, so its central directory is always written
+pub fn write_zip<'a, W, I>(entries: I, options: FileOptions, out: W) -> Result<W, ConvertError>
+    where W: Write + Seek, I: ExactSizeIterator<Item = Entry<'a>>
+{
+    let mut zip = ZipWriter::new(out);
+    let progress = progress::entries(entries.len(), "Converting");
+    for (i, file) in entries.enumerate() {
+        progress.inc(1);
+        let name = zip_name(&file, i);
+        log::info!("Adding {}", name);
+        zip.start_file(name, options.large_file(file.data.len() >= ZIP64_ENTRY_SIZE))?;
+        zip.write_all(file.data)?;
+    }
+    progress.finish_and_clear();
+    Ok(zip.finish()?)
+}
+
+/// Pack the files of a zip into an archive, the way the metadata left in it by `to_zip` says it
+/// was unless `pack` says otherwise, and with the modification time of its newest file
+pub fn from_zip(pack: &PackArgs, config: &Config, in_file: &Path, out_file: &Path, dict: Option<&Path>) -> Result<(), ConvertError> {
+    let zip_file = ArchiveData::open(in_file)?;
+    let ZipContents { files, metadata, modified } = read_zip(zip_file).map_err(|err| err.in_file(in_file))?;
+
+    let mut pack = pack.clone();
+    if let Some(metadata) = &metadata {
+        pack.apply_metadata(metadata);
+    }
+    pack.apply_config(config);
+    let mut sarc = SarcWriter::new(pack.byte_order(), files);
+    pack.alignment().apply(&mut sarc);
+    if let Some(metadata) = &metadata {
+        metadata.restore(&mut sarc);
+    }
+
+    write(sarc, out_file, &pack, dict)?;
+    match modified {
+        Some(modified) => stdio::set_modified(out_file, modified),
+        None => Ok(()),
+    }
+}
+
+/// How an archive compares to itself after being extracted and packed again
+pub struct RoundTrip {
+    pub compression: Compression,
+    /// Sizes of the file before and after, outer compression included
+    pub sizes: (usize, usize),
+    /// Whether the whole file came out the same
+    pub identical: bool,
+    /// Whether the archive came out the same once decompressed, even if the compression didn't
+    pub archive_identical: bool,
+    /// Ranges of the decompressed archive that came out different, with what was there
+    pub regions: Vec<(Range<usize>, String)>,
+    /// Entries that came out different, and how
+    pub entries: Vec<String>,
+}
+
+/// Differing bytes closer than this are reported as one region
+const REGION_GAP: usize = 0x10;
+
+/// Ranges where two buffers differ, including where one is longer
+fn differences(a: &[u8], b: &[u8]) -> Vec<Range<usize>> {
+    let mut ranges: Vec<Range<usize>> = vec![];
+    for i in (0..a.len().max(b.len())).filter(|&i| a.get(i) != b.get(i)) {
+        match ranges.last_mut() {
+            Some(range) if i - range.end < REGION_GAP => range.end = i + 1,
+            _ => ranges.push(i..i + 1),
+        }
+    }
+    ranges
+}
+
+/// Extract an archive in memory and pack it again the way `into-zip` and `from-zip` would, with
+/// the settings of `pack` and the config, then compare the result to the original
+pub fn roundtrip(pack: &PackArgs, config: &Config, in_file: &Path, dict: Option<&Path>) -> Result<RoundTrip, ConvertError> {
+    let original = ArchiveData::open(in_file)?;
+    let archive = Archive::open(in_file, dict)?;
+    let metadata = zip_metadata(&archive);
+    let files = archive.entries()
+        .enumerate()
+        .map(|(i, entry)| WriterEntry::named(zip_name(&entry, i), entry.data.to_vec(), DEFAULT_HASH_KEY))
+        .collect();
+
+    let mut pack = pack.clone();
+    pack.apply_metadata(&metadata);
+    pack.apply_config(config);
+    let mut sarc = SarcWriter::new(pack.byte_order(), files);
+    pack.alignment().apply(&mut sarc);
+    metadata.restore(&mut sarc);
+    let packed = pack::to_bytes(sarc, in_file, pack.compression(in_file), &pack, dict)?;
+    let sizes = (original.len(), packed.len());
+    let identical = packed[..] == original[..];
+    let repacked = Archive::from_bytes(packed, &archive.dictionaries)?;
+
+    let label = |offset: usize| {
+        if offset < archive.layout.data_offset as usize {
+            return "header and tables".to_owned()
+        }
+        archive.entries()
+            .find(|entry| (entry.offset..entry.offset + entry.data.len()).contains(&offset))
+            .map_or_else(|| "padding".to_owned(), |entry| entry.display_name().into_owned())
+    };
+    let regions: Vec<_> = differences(&archive.data, &repacked.data).into_iter()
+        .map(|range| {
+            let label = if range.start >= archive.data.len() { "past the end".to_owned() } else { label(range.start) };
+            (range, label)
+        })
+        .collect();
+
+    let mut entries = vec![];
+    for entry in archive.entries() {
+        let name = entry.display_name();
+        match repacked.entry(&name) {
+            None => entries.push(format!("{} is missing", name)),
+            Some(packed) if packed.data != entry.data => entries.push(format!("{} has different data", name)),
+            Some(packed) if packed.offset != entry.offset => entries.push(format!(
+                "{} moved from {:#x} to {:#x}", name, entry.offset, packed.offset
+            )),
+            Some(_) => (),
+        }
+    }
+    for entry in repacked.entries().filter(|entry| archive.entry(&entry.display_name()).is_none()) {
+        entries.push(format!("{} was added", entry.display_name()));
+    }
+
+    Ok(RoundTrip {
+        compression: archive.compression,
+        sizes,
+        identical,
+        archive_identical: regions.is_empty(),
+        regions,
+        entries,
+    })
+}
+
+/// Convert an archive to a tarball, compressed the way `compression` says
+pub fn to_tar(in_file: &Path, out_file: &Path, compression: TarCompression, dict: Option<&Path>) -> Result<(), ConvertError> {
+    let archive = Archive::open(in_file, dict)?;
+    stdio::create(out_file, |out| tarball::write_tar(archive.entries(), compression, out))
+        .map_err(|err| err.in_file(out_file))
+}
+
+/// Pack the files of a tarball into an archive
+pub fn from_tar(pack: &PackArgs, in_file: &Path, out_file: &Path, dict: Option<&Path>) -> Result<(), ConvertError> {
+    let data = ArchiveData::open(in_file)?;
+    let files = tarball::read_tar(&data).map_err(|err| err.in_file(in_file))?;
+
+    let mut sarc = SarcWriter::new(pack.byte_order(), files);
+    pack.alignment().apply(&mut sarc);
+
+    write(sarc, out_file, pack, dict)
+}
+
+/// Convert an archive to a 7z archive
+pub fn to_7z(in_file: &Path, out_file: &Path, dict: Option<&Path>) -> Result<(), ConvertError> {
+    let archive = Archive::open(in_file, dict)?;
+    if stdio::is_stdio(out_file) || stdio::is_dry_run() {
+        // the header is written last and points back at the data, so stdout goes through memory
+        let data = sevenz::write_7z(archive.entries(), Cursor::new(vec![]))?.into_inner();
+        return stdio::write(out_file, &data)
+    }
+    if !stdio::should_write(out_file)? {
+        return Ok(())
+    }
+
+    let file = File::create(out_file).map_err(|err| ConvertError::io(out_file, err))?;
+    let result = sevenz::write_7z(archive.entries(), BufWriter::new(file))
+        .and_then(|mut out| out.flush().map_err(ConvertError::from));
+    if result.is_err() {
+        let _ = fs::remove_file(out_file);
+    }
+    result.map(drop).map_err(|err| err.in_file(out_file))
+}
+
+/// Pack the files of a 7z archive into an archive
+pub fn from_7z(pack: &PackArgs, in_file: &Path, out_file: &Path, dict: Option<&Path>) -> Result<(), ConvertError> {
+    let data = ArchiveData::open(in_file)?;
+    let files = sevenz::read_7z(&data).map_err(|err| err.in_file(in_file))?;
+
+    let mut sarc = SarcWriter::new(pack.byte_order(), files);
+    pack.alignment().apply(&mut sarc);
+
+    write(sarc, out_file, pack, dict)
+}
+
+/// What's in a zip besides its files
+pub struct ZipContents {
+    pub files: Vec<WriterEntry>,
+    /// Metadata left by `to_zip`
+    pub metadata: Option<ArchiveMetadata>,
+    /// Modification time of the newest file
+    pub modified: Option<SystemTime>,
+}
+
+/// Entries of every file in a zip, refusing zips with more files than a SARC can hold. Files are
+/// only decompressed as the archive is written, straight from `zip_file`.
+pub fn read_zip(zip_file: ArchiveData) -> Result<ZipContents, ConvertError> {
+    let mut zip = ZipArchive::new(Cursor::new(SharedData(Arc::new(zip_file))))?;
+
+    // checked up front rather than after reading everything into memory
+    let files = (0..zip.len())
+        .map(|i| zip.by_index(i).map(|file| !file.name().ends_with('/') && file.name() != METADATA_FILE))
+        .collect::<Result<Vec<_>, _>>()?;
+    let count = files.iter().filter(|&&is_file| is_file).count();
+    if count > u16::MAX as usize {
+        return Err(ConvertError::param(&format!(
+            "{} files don't fit in a SARC, the most it can hold is {}", count, u16::MAX
+        )))
+    }
+
+    let metadata = match zip.by_name(METADATA_FILE) {
+        Ok(file) => {
+            log::debug!("Using the layout recorded in {}", METADATA_FILE);
+            Some(serde_yaml::from_reader(file).map_err(|err| ConvertError::from(err).in_file(Path::new(METADATA_FILE)))?)
+        }
+        Err(_) => None,
+    };
+
+    let mut entries = Vec::with_capacity(count);
+    let mut modified = None;
+    for index in (0..zip.len()).filter(|&i| files[i]) {
+        let file = zip.by_index_raw(index)?;
+        let name = file.name().to_owned();
+        let len = usize::try_from(file.size())
+            .map_err(|_| ConvertError::file("too large to pack").in_entry(&name))?;
+        modified = modified.max(file.last_modified().to_time().ok().map(SystemTime::from));
+        drop(file);
+        log::info!("Adding {}", name);
+        let data = EntryData::Zip { zip: zip.clone(), index, len };
+        entries.push(WriterEntry::named(name, data, DEFAULT_HASH_KEY));
+    }
+    Ok(ZipContents { files: entries, metadata, modified })
+}
+
+#[cfg(test)]
+mod tests {
+    use sarc::Endian;
+
+    use super::*;
+    use crate::zip_method::{ZipMethod, ZIP_METHODS};
+    use crate::ConvertErrorKind;
+
+    fn entries<'a>(names: &'a [String], data: &'a [u8]) -> impl ExactSizeIterator<Item = Entry<'a>> {
+        names.iter().map(move |name| Entry { name: Some(name), hash: 0, offset: 0, data })
+    }
+
+    fn zip_with_files(count: usize) -> Vec<u8> {
+        let names: Vec<String> = (0..count).map(|i| format!("{}.bin", i)).collect();
+        write_zip(entries(&names, b"data"), FileOptions::default(), Cursor::new(vec![])).unwrap().into_inner()
+    }
+
+    #[test]
+    fn zip64_entries_are_read() {
+        let mut zip = ZipWriter::new(Cursor::new(vec![]));
+        let options = FileOptions::default().large_file(true);
+        zip.start_file("a.bin", options).unwrap();
+        zip.write_all(b"first").unwrap();
+        zip.start_file("dir/b.bin", options).unwrap();
+        zip.write_all(b"second").unwrap();
+        let zip = zip.finish().unwrap();
+
+        let entries = read_zip(ArchiveData::Owned(zip.into_inner())).unwrap().files;
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].name.as_deref(), Some("a.bin"));
+        assert_eq!(entries[1].name.as_deref(), Some("dir/b.bin"));
+        assert_eq!(entries[1].data.len(), 6);
+        assert_eq!(entries.into_iter().nth(1).unwrap().data.into_bytes().unwrap(), b"second");
+    }
+
+    #[test]
+    fn damaged_zip_entries_are_noticed_when_written() {
+        let names = ["a.bin".to_owned()];
+        let options = ZipMethod::Stored.options(None).unwrap();
+        let mut zip = write_zip(entries(&names, b"data"), options, Cursor::new(vec![])).unwrap().into_inner();
+        let at = zip.windows(4).position(|bytes| bytes == b"data").unwrap();
+        zip[at] = b'D';
+
+        let entries = read_zip(ArchiveData::Owned(zip)).unwrap().files;
+        assert!(entries.into_iter().next().unwrap().data.into_bytes().is_err());
+    }
+
+    #[test]
+    fn every_zip_method_round_trips() {
+        let names = ["a.bin".to_owned(), "b.bin".to_owned()];
+        let data = b"data data data data";
+        for method in ZIP_METHODS {
+            let options = method.parse::<ZipMethod>().unwrap().options(None).unwrap();
+            let zip = write_zip(entries(&names, data), options, Cursor::new(vec![])).unwrap();
+            let entries = read_zip(ArchiveData::Owned(zip.into_inner())).unwrap().files;
+            assert_eq!(entries.len(), 2, "{}", method);
+            assert_eq!(entries[0].data.len(), data.len(), "{}", method);
+        }
+    }
+
+    #[test]
+    fn zip_levels_are_checked() {
+        assert!(ZipMethod::Deflate.options(Some(9)).is_ok());
+        assert!(ZipMethod::Deflate.options(Some(10)).is_err());
+        assert!(ZipMethod::Bzip2.options(Some(0)).is_err());
+        assert!(ZipMethod::Zstd.options(Some(19)).is_ok());
+        assert!(ZipMethod::Stored.options(Some(1)).is_err());
+    }
+
+    #[test]
+    fn zip_keeps_every_entry_of_a_full_sarc() {
+        let count = u16::MAX as usize;
+        let zip = zip_with_files(count);
+        assert_eq!(ZipArchive::new(Cursor::new(&zip)).unwrap().len(), count);
+        assert_eq!(read_zip(ArchiveData::Owned(zip)).unwrap().files.len(), count);
+    }
+
+    #[test]
+    fn zip_over_65535_entries_is_written_and_rejected_for_sarc() {
+        let count = u16::MAX as usize + 10;
+        let zip = zip_with_files(count);
+        assert_eq!(ZipArchive::new(Cursor::new(&zip)).unwrap().len(), count);
+
+        let err = read_zip(ArchiveData::Owned(zip)).err().unwrap();
+        assert!(matches!(err.kind, ConvertErrorKind::Param));
+    }
+
+    #[test]
+    fn sarc_over_65535_entries_is_rejected() {
+        let files = (0..u16::MAX as usize + 1)
+            .map(|i| WriterEntry::named(format!("{}.bin", i), vec![], DEFAULT_HASH_KEY))
+            .collect();
+        let sarc = SarcWriter::new(Endian::Little, files);
+        assert!(sarc.write(&mut std::io::sink()).is_err());
+    }
+
+    #[test]
+    fn config_leaves_the_layout_of_converted_archives() {
+        let config = Config { endian: Some(ByteOrder::Big), preset: Some("totk".parse().unwrap()), zstd_level: Some(19), ..Config::default() };
+        let mut pack = PackArgs::default();
+        pack.apply_compression_config(&config);
+        assert!(!pack.sets_byte_order());
+        assert!(!pack.sets_alignment());
+        assert_eq!(pack.zstd_level, Some(19));
+    }
+
+    #[test]
+    fn reproducible_zips_are_identical() {
+        let names = ["a.bin".to_owned()];
+        let zip = |modified: SystemTime| {
+            let options = ZipMethod::Deflate.options(None).unwrap();
+            let options = options.last_modified_time(zip_time(Some(modified), true).unwrap());
+            write_zip(entries(&names, b"data"), options, Cursor::new(vec![])).unwrap().into_inner()
+        };
+        let year = std::time::Duration::from_secs(365 * 24 * 60 * 60);
+        assert_eq!(zip(SystemTime::now()), zip(SystemTime::now() - year));
+        assert!(zip_time(Some(SystemTime::now()), false).is_some());
+    }
+
+    #[test]
+    fn repair_leaves_a_valid_archive_as_it_is() {
+        let files = vec![
+            WriterEntry::named("a.bin".to_owned(), b"first".to_vec(), DEFAULT_HASH_KEY),
+            WriterEntry::named("b.bin".to_owned(), b"second".to_vec(), DEFAULT_HASH_KEY),
+        ];
+        let mut sarc = SarcWriter::new(Endian::Little, files);
+        sarc.alignment = 8;
+        let mut data = vec![];
+        sarc.write(&mut data).unwrap();
+
+        let dir = tempfile::tempdir().unwrap();
+        let (in_file, out_file) = (dir.path().join("in.sarc"), dir.path().join("out.sarc"));
+        fs::write(&in_file, &data).unwrap();
+        assert!(repair(&in_file, &out_file, None).unwrap().is_empty());
+        assert_eq!(fs::read(&out_file).unwrap(), data);
+    }
+
+    #[test]
+    #[ignore = "deflates over 4 GiB, run with --ignored"]
+    fn zip_entry_over_4_gib() {
+        let names = ["big.bin".to_owned()];
+        let data = vec![0u8; u32::MAX as usize + 0x1000];
+        let zip = write_zip(entries(&names, &data), FileOptions::default(), Cursor::new(vec![])).unwrap().into_inner();
+
+        let mut zip = ZipArchive::new(Cursor::new(zip)).unwrap();
+        let mut file = zip.by_index(0).unwrap();
+        assert_eq!(file.size(), data.len() as u64);
+        assert_eq!(std::io::copy(&mut file, &mut std::io::sink()).unwrap(), data.len() as u64);
+    }
+}
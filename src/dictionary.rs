@@ -0,0 +1,113 @@
+//! zstd dictionaries, as used by Tears of the Kingdom (`ZsDic.pack.zs`)
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use crate::layout::Layout;
+use crate::ConvertError;
+
+const DICTIONARY_ARCHIVES: &[&str] = &["ZsDic.pack.zs", "ZsDic.pack", "Pack/ZsDic.pack.zs"];
+
+struct Dictionary {
+    name: String,
+    id: u32,
+    data: Vec<u8>,
+}
+
+#[derive(Default)]
+pub struct Dictionaries {
+    dictionaries: Vec<Dictionary>,
+}
+
+impl Dictionaries {
+    /// Load the dictionaries from `path`, or if none is given, from a `ZsDic.pack` found next to
+    /// `near` or in one of its parent directories
+    pub fn load(path: Option<&Path>, near: &Path) -> Result<Self, ConvertError> {
+        Self::load_in(path, near.parent().unwrap_or(near))
+    }
+
+    /// Like `load`, looking in `dir` and its parents
+    pub fn load_in(path: Option<&Path>, dir: &Path) -> Result<Self, ConvertError> {
+        match path.map(Path::to_owned).or_else(|| Self::find(dir)) {
+            Some(path) => Self::from_file(&path).map_err(|err| err.in_file(&path)),
+            None => Ok(Self::default()),
+        }
+    }
+
+    fn find(dir: &Path) -> Option<PathBuf> {
+        dir.ancestors()
+            .flat_map(|dir| DICTIONARY_ARCHIVES.iter().map(move |name| dir.join(name)))
+            .find(|path| path.is_file())
+    }
+
+    /// Either a single dictionary or an archive of `.zsdic` files
+    fn from_file(path: &Path) -> Result<Self, ConvertError> {
+        let mut data = fs::read(path).map_err(|err| ConvertError::io(path, err))?;
+        if data.starts_with(crate::compression::ZSTD_MAGIC) {
+            data = zstd::stream::decode_all(&data[..]).map_err(|err| ConvertError::zstd(err.to_string()))?;
+        }
+
+        let name = path.file_name().unwrap_or_default().to_string_lossy().into_owned();
+        let dictionaries: Vec<Dictionary> = if data.starts_with(b"SARC") {
+            let layout = Layout::parse(&data)?;
+            layout.nodes.iter()
+                .filter_map(|node| {
+                    let name = node.name.as_ref().filter(|name| name.ends_with(".zsdic"))?;
+                    Some(Dictionary::new(name.clone(), data.get(layout.absolute(node))?.to_vec()))
+                })
+                .collect()
+        } else {
+            vec![Dictionary::new(name, data)]
+        };
+
+        for dictionary in &dictionaries {
+            log::debug!("Loaded dictionary {} ({:08X}) from {}", dictionary.name, dictionary.id, path.display());
+        }
+        Ok(Dictionaries { dictionaries })
+    }
+
+    /// The dictionary a zstd frame was compressed with
+    pub fn for_frame(&self, frame: &[u8]) -> Option<&[u8]> {
+        let id = zstd::zstd_safe::get_dict_id_from_frame(frame)?.get();
+        self.dictionaries.iter()
+            .find(|dictionary| dictionary.id == id)
+            .map(|dictionary| &dictionary.data[..])
+    }
+
+    /// The dictionary TOTK expects a file with the given name to be compressed with
+    pub fn for_output(&self, path: &Path) -> Option<&[u8]> {
+        if let [dictionary] = &self.dictionaries[..] {
+            return Some(&dictionary.data)
+        }
+
+        let name = path.file_name()?.to_string_lossy();
+        let dictionary = if name.ends_with(".pack.zs") {
+            "pack.zsdic"
+        } else if name.ends_with(".bcett.byml.zs") {
+            "bcett.byml.zsdic"
+        } else {
+            "zs.zsdic"
+        };
+        self.dictionaries.iter()
+            .find(|dict| dict.name == dictionary)
+            .map(|dict| &dict.data[..])
+    }
+}
+
+impl Dictionary {
+    fn new(name: String, data: Vec<u8>) -> Self {
+        Dictionary {
+            name,
+            id: zstd::zstd_safe::get_dict_id_from_dict(&data).map_or(0, |id| id.get()),
+            data,
+        }
+    }
+}
+
+pub fn decompress_with(data: &[u8], dictionary: &[u8]) -> Result<Vec<u8>, ConvertError> {
+    let mut out = vec![];
+    zstd::stream::read::Decoder::with_dictionary(data, dictionary)
+        .and_then(|mut decoder| decoder.read_to_end(&mut out))
+        .map_err(|err| ConvertError::zstd(err.to_string()))?;
+    Ok(out)
+}
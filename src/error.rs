@@ -0,0 +1,197 @@
+//! The error every fallible function of sarctool returns
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+
+#[derive(Serialize)]
+pub struct ConvertError {
+    pub message: String,
+    pub kind: ConvertErrorKind,
+    /// File the error happened in, also at the start of the message
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<PathBuf>,
+    /// Entry of an archive the error happened in, also at the start of the message
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub entry: Option<String>,
+}
+
+impl fmt::Debug for ConvertError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "ConvertError '{:?}', message: \"{}\"", self.kind, self.message)
+    }
+}
+
+impl fmt::Display for ConvertError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConvertErrorKind {
+    Param,
+    Nus3audio,
+    Msc,
+    File,
+    HandleNone,
+    YamlError,
+    Utf8Error,
+    ParseIntError,
+    MessageFormat,
+    WaveError,
+    SarcError,
+    ZipError,
+    SevenZError,
+    Byml,
+    Aamp,
+    Yaz0Error,
+    ZstdError,
+    Lz4Error,
+    /// An external program run by `exec` failed
+    Command,
+    /// Returned through the C interface, where a panic can't unwind, and reported by
+    /// `--error-format json`
+    Panic,
+}
+
+impl ConvertErrorKind {
+    /// 2 for bad arguments, 3 for I/O errors and 4 for files that couldn't be parsed
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            ConvertErrorKind::Param => 2,
+            ConvertErrorKind::File => 3,
+            _ => 4,
+        }
+    }
+}
+
+impl ConvertError {
+    pub fn new(kind: ConvertErrorKind, message: String) -> ConvertError {
+        ConvertError { message, kind, path: None, entry: None }
+    }
+
+    pub fn param(message: &str) -> ConvertError {
+        ConvertError::new(ConvertErrorKind::Param, message.to_string())
+    }
+
+    pub fn nus3audio(message: &str) -> ConvertError {
+        ConvertError::new(ConvertErrorKind::Nus3audio, message.to_string())
+    }
+
+    pub fn file(message: &str) -> ConvertError {
+        ConvertError::new(ConvertErrorKind::File, message.to_string())
+    }
+
+    pub fn msc(message: &str) -> ConvertError {
+        ConvertError::new(ConvertErrorKind::Msc, message.to_string())
+    }
+
+    pub fn message_format(message: &str) -> ConvertError {
+        ConvertError::new(ConvertErrorKind::MessageFormat, message.to_string())
+    }
+
+    pub fn io(path: &Path, err: std::io::Error) -> ConvertError {
+        ConvertError::new(ConvertErrorKind::File, format!("{}: {}", path.display(), err)).at(path)
+    }
+
+    /// Prefix the message with the file the error happened in
+    pub fn in_file(self, path: &Path) -> ConvertError {
+        ConvertError { message: format!("{}: {}", path.display(), self.message), ..self }.at(path)
+    }
+
+    /// Prefix the message with the entry the error happened in
+    pub fn in_entry(self, name: &str) -> ConvertError {
+        ConvertError {
+            message: format!("{}: {}", name, self.message),
+            entry: self.entry.or_else(|| Some(name.to_owned())),
+            ..self
+        }
+    }
+
+    /// The innermost file is kept, since that's the one the error is about
+    fn at(self, path: &Path) -> ConvertError {
+        ConvertError { path: self.path.or_else(|| Some(path.to_owned())), ..self }
+    }
+
+    pub fn sarc<S: AsRef<str>>(message: S) -> ConvertError {
+        ConvertError::new(ConvertErrorKind::SarcError, message.as_ref().to_string())
+    }
+
+    pub fn byml<S: AsRef<str>>(message: S) -> ConvertError {
+        ConvertError::new(ConvertErrorKind::Byml, message.as_ref().to_string())
+    }
+
+    pub fn aamp<S: AsRef<str>>(message: S) -> ConvertError {
+        ConvertError::new(ConvertErrorKind::Aamp, message.as_ref().to_string())
+    }
+
+    pub fn yaz0<S: AsRef<str>>(message: S) -> ConvertError {
+        ConvertError::new(ConvertErrorKind::Yaz0Error, format!("Yaz0Error: {}", message.as_ref()))
+    }
+
+    pub fn zstd<S: AsRef<str>>(message: S) -> ConvertError {
+        ConvertError::new(ConvertErrorKind::ZstdError, format!("ZstdError: {}", message.as_ref()))
+    }
+
+    pub fn lz4<S: AsRef<str>>(message: S) -> ConvertError {
+        ConvertError::new(ConvertErrorKind::Lz4Error, format!("Lz4Error: {}", message.as_ref()))
+    }
+}
+
+impl std::convert::From<std::io::Error> for ConvertError {
+    fn from(err: std::io::Error) -> Self {
+        ConvertError::new(ConvertErrorKind::File, err.to_string())
+    }
+}
+
+impl std::convert::From<std::str::Utf8Error> for ConvertError {
+    fn from(err: std::str::Utf8Error) -> Self {
+        ConvertError::new(ConvertErrorKind::Utf8Error, format!("{:?}", err))
+    }
+}
+
+impl std::convert::From<std::num::ParseIntError> for ConvertError {
+    fn from(err: std::num::ParseIntError) -> Self {
+        ConvertError::new(ConvertErrorKind::ParseIntError, format!("{:?}", err))
+    }
+}
+
+impl std::convert::From<sarc::parser::Error> for ConvertError {
+    fn from(err: sarc::parser::Error) -> Self {
+        ConvertError::new(ConvertErrorKind::SarcError, format!("SarcParseError: {:?}", err))
+    }
+}
+
+impl std::convert::From<sarc::writer::Error> for ConvertError {
+    fn from(err: sarc::writer::Error) -> Self {
+        ConvertError::new(ConvertErrorKind::SarcError, format!("SarcWriteError: {:?}", err))
+    }
+}
+
+impl std::convert::From<zip::result::ZipError> for ConvertError {
+    fn from(err: zip::result::ZipError) -> Self {
+        ConvertError::new(ConvertErrorKind::ZipError, format!("ZipError: {:?}", err))
+    }
+}
+
+impl std::convert::From<sevenz_rust::Error> for ConvertError {
+    fn from(err: sevenz_rust::Error) -> Self {
+        ConvertError::new(ConvertErrorKind::SevenZError, format!("7zError: {}", err))
+    }
+}
+
+impl std::convert::From<serde_yaml::Error> for ConvertError {
+    fn from(err: serde_yaml::Error) -> Self {
+        ConvertError::new(ConvertErrorKind::YamlError, format!("YamlError: {}", err))
+    }
+}
+
+impl std::convert::From<csv::Error> for ConvertError {
+    fn from(err: csv::Error) -> Self {
+        ConvertError::new(ConvertErrorKind::File, err.to_string())
+    }
+}
+
+impl std::error::Error for ConvertError {}
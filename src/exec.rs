@@ -0,0 +1,91 @@
+//! Running an external program over the entries of an archive, each extracted to a temporary
+//! file, optionally putting what it leaves in the file back into the archive
+use std::ffi::OsString;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+use crate::archive::Archive;
+use crate::{convert, extract, progress, stdio, ConvertError, ConvertErrorKind};
+
+/// Arguments of the command for one file: `{}` is replaced by its path, which is added to the
+/// end if no argument has one
+fn arguments(args: &[String], path: &Path) -> Vec<OsString> {
+    if !args.iter().any(|arg| arg.contains("{}")) {
+        return args.iter().map(OsString::from).chain(std::iter::once(path.as_os_str().to_owned())).collect()
+    }
+    args.iter()
+        .map(|arg| OsString::from(arg.replace("{}", &path.to_string_lossy())))
+        .collect()
+}
+
+/// Run `command` once for every entry whose name matches `filter`, one at a time. With
+/// `write_back`, entries the command changed are replaced in the archive written there.
+pub fn exec(
+    in_file: &Path,
+    filter: Option<&glob::Pattern>,
+    command: &[String],
+    write_back: Option<&Path>,
+    dict: Option<&Path>,
+) -> Result<(), ConvertError> {
+    let (program, args) = command.split_first().ok_or_else(|| ConvertError::param("No command to run"))?;
+    let archive = Archive::open(in_file, dict)?;
+    let matching: Vec<_> = archive.entries()
+        .enumerate()
+        .filter(|(_, entry)| match (filter, entry.name) {
+            (Some(filter), Some(name)) => filter.matches(name),
+            (Some(_), None) => false,
+            (None, _) => true,
+        })
+        .collect();
+
+    // a new directory with a random name, so nobody else can have put anything in it, which is
+    // removed along with the entries when dropped
+    let temp_dir = tempfile::Builder::new()
+        .prefix("sarctool-exec-")
+        .tempdir()
+        .map_err(|err| ConvertError::io(&std::env::temp_dir(), err))?;
+    let mut replaced = vec![];
+    for (n, (i, entry)) in matching.into_iter().enumerate() {
+        let name = entry.display_name();
+        // each entry gets its own directory, so the file keeps its name and extension
+        let path = temp_dir.path().join(n.to_string()).join(extract::safe_path(&name).as_ref());
+        let args = arguments(args, &path);
+        if stdio::is_dry_run() {
+            let args: Vec<_> = args.iter().map(|arg| arg.to_string_lossy()).collect();
+            progress::suspend(|| println!("Would run {} {} for {}", program, args.join(" "), name));
+            continue
+        }
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(|err| ConvertError::io(parent, err))?;
+        }
+        fs::write(&path, entry.data).map_err(|err| ConvertError::io(&path, err))?;
+        log::info!("Running {} for {}", program, name);
+        let status = Command::new(program)
+            .args(&args)
+            .status()
+            .map_err(|err| ConvertError::io(Path::new(program), err))?;
+        if !status.success() {
+            return Err(ConvertError::new(
+                ConvertErrorKind::Command, format!("{} failed ({})", program, status)
+            ).in_entry(&name))
+        }
+
+        if write_back.is_some() {
+            let data = fs::read(&path).map_err(|err| ConvertError::io(&path, err))?;
+            if data != entry.data {
+                replaced.push((i, data));
+            }
+        }
+    }
+
+    match write_back {
+        Some(out_file) if !replaced.is_empty() => convert::replace_entries(archive, replaced, out_file),
+        Some(_) => {
+            log::info!("No entries were changed, leaving the archive as it is");
+            Ok(())
+        }
+        None => Ok(()),
+    }
+}
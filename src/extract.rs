@@ -0,0 +1,381 @@
+//! Extracting the entries of archives into directories, along with the metadata `zip` needs to
+//! rebuild them as they were
+use std::borrow::Cow;
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+use rayon::prelude::*;
+
+use crate::archive::Archive;
+use crate::compression::{self, Compression};
+use crate::filetype::FileType;
+use crate::inspect::crc32;
+use crate::metadata::{ArchiveMetadata, EntryMetadata};
+use crate::stdio::{self, Existing};
+use crate::yaml::{self, YamlFormat};
+use crate::{msbt, progress, ConvertError};
+
+/// How to extract an archive, by default as it is along with its metadata
+pub struct ExtractOptions {
+    /// Record the layout in `.sarctool.yml`, so `zip` can rebuild the archive as it was
+    pub write_metadata: bool,
+    /// Formats to extract as YAML, which `zip` turns back into binary
+    pub to_yaml: Vec<YamlFormat>,
+    /// Also write the messages of MSBT entries as text next to them
+    pub msbt_to_text: bool,
+    /// Decompress yaz0 and zstd compressed entries
+    pub decompress_inner: bool,
+    /// Extract every entry into `out_dir` itself, leaving out its directories
+    pub flatten: bool,
+    /// Which entries to extract, by their data
+    pub filter: EntryFilter,
+}
+
+impl Default for ExtractOptions {
+    fn default() -> Self {
+        ExtractOptions {
+            write_metadata: true,
+            to_yaml: vec![],
+            msbt_to_text: false,
+            decompress_inner: false,
+            flatten: false,
+            filter: EntryFilter::default(),
+        }
+    }
+}
+
+/// Entries to extract by the data stored in the archive, before any decompression. Everything
+/// passes by default.
+#[derive(Debug, Clone, Default)]
+pub struct EntryFilter {
+    pub min_size: Option<usize>,
+    pub max_size: Option<usize>,
+    /// Entries have to start with one of these, unless there are none
+    pub magic: Vec<Magic>,
+}
+
+impl EntryFilter {
+    pub fn is_empty(&self) -> bool {
+        self.min_size.is_none() && self.max_size.is_none() && self.magic.is_empty()
+    }
+
+    pub fn matches(&self, data: &[u8]) -> bool {
+        self.min_size.is_none_or(|min| data.len() >= min)
+            && self.max_size.is_none_or(|max| data.len() <= max)
+            && (self.magic.is_empty() || self.magic.iter().any(|magic| magic.matches(data)))
+    }
+}
+
+/// What an entry starts with: the magic of a known type, by its name, or bytes given in hex
+#[derive(Debug, Clone, PartialEq)]
+pub enum Magic {
+    Type(FileType),
+    Bytes(Vec<u8>),
+}
+
+impl Magic {
+    fn matches(&self, data: &[u8]) -> bool {
+        match self {
+            Magic::Type(file_type) => FileType::detect(data) == Some(*file_type),
+            Magic::Bytes(bytes) => data.starts_with(bytes),
+        }
+    }
+}
+
+impl FromStr for Magic {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(file_type) = FileType::from_name(s) {
+            return Ok(Magic::Type(file_type))
+        }
+        let digits = s.strip_prefix("0x").unwrap_or(s);
+        if digits.is_empty() || !digits.len().is_multiple_of(2) || !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+            let names: Vec<&str> = FileType::ALL.iter().map(|file_type| file_type.name()).collect();
+            return Err(format!("'{}' is neither hex bytes nor one of {}", s, names.join(", ")))
+        }
+        Ok(Magic::Bytes((0..digits.len()).step_by(2).map(|i| u8::from_str_radix(&digits[i..i + 2], 16).unwrap()).collect()))
+    }
+}
+
+/// A size in bytes, or in KiB, MiB or GiB with a `K`, `M` or `G` after it
+pub fn parse_size(s: &str) -> Result<usize, String> {
+    let upper = s.trim().to_ascii_uppercase();
+    let number = upper.trim_end_matches("IB").trim_end_matches('B');
+    let (number, unit) = match number.char_indices().last() {
+        Some((i, 'K')) => (&number[..i], 1 << 10),
+        Some((i, 'M')) => (&number[..i], 1 << 20),
+        Some((i, 'G')) => (&number[..i], 1 << 30),
+        _ => (number, 1),
+    };
+    let invalid = || format!("'{}' isn't a size like 4096, 4K or 1.5M", s);
+    let number: f64 = number.trim().parse().map_err(|_| invalid())?;
+    // parsing also takes `nan` and `inf`
+    if !number.is_finite() || number < 0.0 {
+        return Err(invalid())
+    }
+    Ok((number * unit as f64) as usize)
+}
+
+/// Path with `~n` added before the extensions of its file name, so `a/File.bcett.byml` becomes
+/// `a/File~2.bcett.byml`
+fn numbered_path(path: &str, n: usize) -> String {
+    let name_start = path.rfind('/').map_or(0, |i| i + 1);
+    // a dot at the start of the name isn't an extension
+    let stem_end = path[name_start..].char_indices()
+        .skip(1)
+        .find(|&(_, c)| c == '.')
+        .map_or(path.len(), |(i, _)| name_start + i);
+    format!("{}~{}{}", &path[..stem_end], n, &path[stem_end..])
+}
+
+/// Names Windows keeps for devices, whatever extension they're given
+const RESERVED_NAMES: &[&str] = &[
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8", "COM9",
+    "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// The name of an entry as a relative path that can't leave the directory it's extracted to, on
+/// any OS. Empty, `.` and `..` components are left out, `\` separates them like `/` does on
+/// Windows, and the names and characters Windows doesn't allow are made safe with `_`. Empty if
+/// nothing is left of the name.
+pub fn safe_path(name: &str) -> Cow<'_, str> {
+    let path = name.split(&['/', '\\'][..])
+        .filter(|component| !component.is_empty() && *component != "." && *component != "..")
+        .map(safe_component)
+        .collect::<Vec<_>>()
+        .join("/");
+    if path == name { Cow::Borrowed(name) } else { Cow::Owned(path) }
+}
+
+fn safe_component(component: &str) -> String {
+    let mut safe: String = component.chars()
+        .map(|c| if c < ' ' || "<>:\"|?*".contains(c) { '_' } else { c })
+        .collect();
+    // Windows drops dots and spaces from the end of names
+    if safe.ends_with(&['.', ' '][..]) {
+        safe.pop();
+        safe.push('_');
+    }
+    let stem = safe.split('.').next().unwrap_or_default();
+    if RESERVED_NAMES.iter().any(|reserved| stem.eq_ignore_ascii_case(reserved)) {
+        safe.insert(0, '_');
+    }
+    safe
+}
+
+/// Path of an entry with no name, after the CRC32 of its data so it stays the same however the
+/// archive is reordered, with the extension of its type if it's known
+fn unnamed_path(data: &[u8]) -> String {
+    let extension = FileType::detect(data).map_or("bin", FileType::extension);
+    format!("{}.{}", crc32(data).to_uppercase(), extension)
+}
+
+/// Rename entries whose paths would land on the same file, in order, so the first keeps its path
+/// and the rest are numbered. Paths differing only in case count as the same, since they are on
+/// case-insensitive filesystems like those of Windows and macOS.
+pub(crate) fn rename_collisions(entries: &mut [EntryMetadata]) {
+    let original: HashSet<String> = entries.iter().map(|entry| entry.path.to_lowercase()).collect();
+    let mut taken = HashSet::new();
+    for entry in entries {
+        if taken.insert(entry.path.to_lowercase()) {
+            continue
+        }
+        let renamed = (2..)
+            .map(|n| numbered_path(&entry.path, n))
+            .find(|path| !original.contains(&path.to_lowercase()) && !taken.contains(&path.to_lowercase()))
+            .unwrap();
+        log::warn!("{} collides with another entry, extracting it as {}", entry.path, renamed);
+        taken.insert(renamed.to_lowercase());
+        entry.path = renamed;
+    }
+}
+
+/// Metadata of the entries of an archive, at the paths they're extracted to before any
+/// conversion: their names, or names from the name map, made safe, and made up from the data of
+/// entries without one. Paths that collide are left as they are.
+pub(crate) fn entry_metadata(archive: &Archive) -> Vec<EntryMetadata> {
+    archive.layout.nodes.iter().zip(archive.entries()).enumerate()
+        .map(|(index, (node, entry))| {
+            // mapped names are only used for the path, so the entry stays unnamed when repacked
+            let path = match entry.name {
+                Some(name) => match safe_path(name) {
+                    Cow::Borrowed(name) => name.to_owned(),
+                    Cow::Owned(safe) => {
+                        let path = if safe.is_empty() { unnamed_path(entry.data) } else { safe };
+                        log::warn!("{} isn't a safe path to extract to, extracting it as {}", name, path);
+                        path
+                    }
+                },
+                None => {
+                    let path = unnamed_path(entry.data);
+                    log::warn!("entry with hash {:08X} has no name, extracting it as {}", entry.hash, path);
+                    path
+                }
+            };
+            EntryMetadata {
+                name: node.name.clone(),
+                path,
+                hash: node.hash,
+                offset: node.data.start,
+                index: node.name.is_none().then_some(index),
+                byml_version: None,
+            }
+        })
+        .collect()
+}
+
+/// Extract every entry of an archive into `out_dir`
+pub fn unzip(in_file: &Path, out_dir: &Path, options: &ExtractOptions, dict: Option<&Path>) -> Result<(), ConvertError> {
+    if stdio::is_stdio(out_dir) {
+        return Err(ConvertError::param("Can't extract to stdout, use `sarc cat` for single entries"))
+    }
+    let is_empty = fs::read_dir(out_dir).map_or(true, |mut dir| dir.next().is_none());
+    if !is_empty && stdio::existing() == Existing::Refuse {
+        return Err(ConvertError::file(&format!(
+            "{} isn't empty, pass --force to extract over it or --skip-existing to keep what's there",
+            out_dir.display()
+        )))
+    }
+    let archive = Archive::open(in_file, dict)?;
+    let Archive { data, layout, compression, dictionaries, .. } = &archive;
+    let mut entries = entry_metadata(&archive);
+    let mut formats = vec![];
+    let mut selected = vec![];
+    for (entry, node) in entries.iter_mut().zip(&layout.nodes) {
+        let node_data = &data[layout.absolute(node)];
+        selected.push(options.filter.matches(node_data));
+        let format = YamlFormat::detect(&entry.path, node_data).filter(|format| options.to_yaml.contains(format));
+        entry.byml_version = (format == Some(YamlFormat::Byml)).then(|| yaml::byml_version(node_data));
+        if options.flatten {
+            entry.path = entry.path.rsplit('/').next().unwrap_or_default().to_owned();
+        }
+        if format.is_some() {
+            entry.path = yaml::yaml_path(&entry.path);
+        }
+        formats.push(format);
+    }
+
+    rename_collisions(&mut entries);
+
+    let progress = progress::entries(selected.iter().filter(|&&selected| selected).count(), "Extracting");
+    let nodes = layout.nodes.par_iter().zip(&entries).zip(&formats).zip(&selected);
+    nodes.try_for_each(|(((node, entry), format), &selected)| -> Result<(), ConvertError> {
+        if !selected {
+            return Ok(())
+        }
+        let mut path = out_dir.to_owned();
+        path.extend(std::iter::once(&entry.path));
+
+        if let Some(parent) = path.parent() {
+            stdio::create_dir_all(parent)?;
+        }
+
+        let range = layout.absolute(node);
+        log::info!("Extracting {}", entry.path);
+        log::debug!("{}: {:#x}..{:#x} -> {}", entry.path, range.start, range.end, path.display());
+        let decompressed;
+        let contents = match &data[range] {
+            contents if options.decompress_inner && Compression::detect(contents) != Compression::None => {
+                log::debug!("Decompressing {}", entry.path);
+                decompressed = compression::decompress(contents, dictionaries).map_err(|err| err.in_entry(&entry.path))?;
+                &decompressed[..]
+            }
+            contents => contents,
+        };
+        if let Some(format) = format {
+            let yaml = format.to_yaml(contents).map_err(|err| err.in_entry(&entry.path))?;
+            stdio::write(&path, yaml.as_bytes())?;
+        } else {
+            stdio::write(&path, contents)?;
+        }
+        if options.msbt_to_text && FileType::detect(contents) == Some(FileType::Msbt) {
+            let text = msbt::to_text(contents).map_err(|err| err.in_entry(&entry.path))?;
+            let text_path = out_dir.join(msbt::export_path(&entry.path));
+            stdio::write(&text_path, text.as_bytes())?;
+        }
+        progress.inc(1);
+        Ok(())
+    })?;
+    progress.finish_and_clear();
+
+    // a directory with only some of the entries can't rebuild the archive
+    if options.write_metadata && options.filter.is_empty() {
+        let mut metadata = ArchiveMetadata::new(layout, *compression, entries);
+        if !stdio::is_stdio(in_file) {
+            metadata.archive = in_file.file_name().map(|name| name.to_string_lossy().into_owned());
+        }
+        metadata.write(out_dir)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(path: &str) -> EntryMetadata {
+        EntryMetadata { name: Some(path.to_owned()), path: path.to_owned(), hash: 0, offset: 0, index: None, byml_version: None }
+    }
+
+    #[test]
+    fn collisions_are_numbered_in_order() {
+        let mut entries: Vec<EntryMetadata> = ["x.txt", "File.bcett.byml", "x~2.txt", "file.bcett.byml", "x.txt", ".hidden", ".Hidden"]
+            .iter()
+            .map(|path| entry(path))
+            .collect();
+        rename_collisions(&mut entries);
+        let paths: Vec<&str> = entries.iter().map(|entry| entry.path.as_str()).collect();
+        assert_eq!(paths, ["x.txt", "File.bcett.byml", "x~2.txt", "file~2.bcett.byml", "x~3.txt", ".hidden", ".Hidden~2"]);
+    }
+
+    #[test]
+    fn numbering_keeps_directories_and_extensions() {
+        assert_eq!(numbered_path("a.b/c.bfres.zs", 2), "a.b/c~2.bfres.zs");
+        assert_eq!(numbered_path("noext", 4), "noext~4");
+    }
+
+    #[test]
+    fn sizes_and_magic_parse() {
+        assert_eq!(parse_size("4096"), Ok(4096));
+        assert_eq!(parse_size("4K"), Ok(4096));
+        assert_eq!(parse_size("1.5m"), Ok(3 << 19));
+        assert_eq!(parse_size("2MiB"), Ok(2 << 20));
+        assert!(parse_size("big").is_err());
+        for size in ["nan", "inf", "-inf", "infinity", "NaNK", "-1"] {
+            assert!(parse_size(size).is_err(), "{}", size);
+        }
+        assert_eq!("byml".parse(), Ok(Magic::Type(FileType::Byml)));
+        assert_eq!("0x59423400".parse(), Ok(Magic::Bytes(b"YB4\0".to_vec())));
+        assert!("BYM".parse::<Magic>().is_err());
+    }
+
+    #[test]
+    fn filters_match_stored_data() {
+        let filter = EntryFilter { min_size: Some(4), max_size: Some(8), magic: vec![Magic::Type(FileType::Aamp)] };
+        assert!(filter.matches(b"AAMP\x02\0\0\0"));
+        assert!(!filter.matches(b"AAM"));
+        assert!(!filter.matches(b"AAMP\x02\0\0\0\0"));
+        assert!(!filter.matches(b"BY\0\x02"));
+        assert!(EntryFilter::default().matches(b""));
+    }
+
+    #[test]
+    fn unnamed_entries_are_named_after_their_data() {
+        assert_eq!(unnamed_path(b"YB\x04\x00"), format!("{}.byml", crc32(b"YB\x04\x00").to_uppercase()));
+        assert_eq!(unnamed_path(b"abc"), "352441C2.bin");
+    }
+
+    #[test]
+    fn unsafe_paths_stay_inside() {
+        assert_eq!(safe_path("Actor/Pack/a.bactorpack"), "Actor/Pack/a.bactorpack");
+        assert_eq!(safe_path("../../etc/passwd"), "etc/passwd");
+        assert_eq!(safe_path("/abs//./x"), "abs/x");
+        assert_eq!(safe_path("C:\\Windows\\..\\x"), "C_/Windows/x");
+        assert_eq!(safe_path("a/con.txt"), "a/_con.txt");
+        assert_eq!(safe_path("a?b. "), "a_b._");
+        assert_eq!(safe_path(".."), "");
+    }
+}
@@ -0,0 +1,137 @@
+//! C interface for embedding sarctool in tools that aren't written in Rust, like mod managers,
+//! built with `cargo rustc --lib --release --features cdylib --crate-type cdylib`. The
+//! declarations are in `include/sarctool.h`.
+//!
+//! Functions return 0 on success, or the exit code the `sarc` binary would have exited with,
+//! and leave the message of the error for [`sarctool_last_error`]. Paths are UTF-8.
+use std::cell::RefCell;
+use std::ffi::{CStr, CString};
+use std::os::raw::{c_char, c_int};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::Path;
+use std::ptr;
+
+use crate::archive::Archive;
+use crate::filetype::FileType;
+use crate::metadata::ArchiveMetadata;
+use crate::pack::{self, PackArgs};
+use crate::scan::ScanArgs;
+use crate::extract::{self, ExtractOptions};
+use crate::{ConvertError, ConvertErrorKind};
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn set_last_error(err: &ConvertError) {
+    // messages can't have nulls in them, but a path in one could
+    let message = CString::new(err.message.replace('\0', "")).unwrap_or_default();
+    LAST_ERROR.with(|last| *last.borrow_mut() = Some(message));
+}
+
+/// Run `f`, turning errors and panics into an exit code, since neither can cross into C
+fn run<T>(f: impl FnOnce() -> Result<T, ConvertError>) -> Result<T, c_int> {
+    let result = panic::catch_unwind(AssertUnwindSafe(f)).unwrap_or_else(|panic| {
+        let message = panic.downcast_ref::<&str>().map(|message| message.to_string())
+            .or_else(|| panic.downcast_ref::<String>().cloned())
+            .unwrap_or_default();
+        Err(ConvertError::new(ConvertErrorKind::Panic, format!("panicked: {}", message)))
+    });
+    result.map_err(|err| {
+        set_last_error(&err);
+        err.kind.exit_code()
+    })
+}
+
+fn status(result: Result<(), c_int>) -> c_int {
+    result.err().unwrap_or(0)
+}
+
+unsafe fn path<'a>(path: *const c_char, what: &str) -> Result<&'a Path, ConvertError> {
+    if path.is_null() {
+        return Err(ConvertError::param(&format!("{} is null", what)))
+    }
+    CStr::from_ptr(path).to_str()
+        .map(Path::new)
+        .map_err(|_| ConvertError::param(&format!("{} isn't UTF-8", what)))
+}
+
+/// Message of the last error on this thread, or null if nothing has failed. It's owned by
+/// sarctool, and only valid until the next call on the same thread.
+#[no_mangle]
+pub extern "C" fn sarctool_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| last.borrow().as_ref().map_or(ptr::null(), |message| message.as_ptr()))
+}
+
+/// Pack a directory into an archive like `sarc zip`, reproducing the layout recorded in its
+/// `.sarctool.yml` if it has one, and compressing by the extension of `out_file`.
+///
+/// # Safety
+///
+/// Both paths must be null or point to null-terminated strings.
+#[no_mangle]
+pub unsafe extern "C" fn sarctool_pack_dir(in_dir: *const c_char, out_file: *const c_char) -> c_int {
+    status(run(|| {
+        let (in_dir, out_file) = (path(in_dir, "in_dir")?, path(out_file, "out_file")?);
+        let metadata = ArchiveMetadata::find(in_dir, false, false)?;
+        let mut pack = PackArgs::default();
+        if let Some(metadata) = &metadata {
+            pack.apply_metadata(metadata);
+        }
+        pack::pack_dir(&pack, &ScanArgs::default(), in_dir, out_file, metadata.as_ref(), None, None)
+    }))
+}
+
+/// Extract an archive into a directory like `sarc unzip`, along with its `.sarctool.yml`.
+///
+/// # Safety
+///
+/// Both paths must be null or point to null-terminated strings.
+#[no_mangle]
+pub unsafe extern "C" fn sarctool_extract(in_file: *const c_char, out_dir: *const c_char) -> c_int {
+    status(run(|| {
+        let (in_file, out_dir) = (path(in_file, "in_file")?, path(out_dir, "out_dir")?);
+        extract::unzip(in_file, out_dir, &ExtractOptions::default(), None)
+    }))
+}
+
+/// The entries of an archive as a JSON array of objects with their `name` (null if the archive
+/// has none), `hash`, `offset`, `size` and `type`, or null on failure. It has to be freed with
+/// [`sarctool_free_string`].
+///
+/// # Safety
+///
+/// `in_file` must be null or point to a null-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn sarctool_list_json(in_file: *const c_char) -> *mut c_char {
+    let json = run(|| {
+        let archive = Archive::open(path(in_file, "in_file")?, None)?;
+        let entries: Vec<_> = archive.entries()
+            .map(|entry| serde_json::json!({
+                "name": entry.name,
+                "hash": entry.hash,
+                "offset": entry.offset,
+                "size": entry.data.len(),
+                "type": FileType::detect(entry.data).map(FileType::name),
+            }))
+            .collect();
+        Ok(serde_json::Value::from(entries).to_string())
+    });
+    match json {
+        // JSON escapes any nulls in names
+        Ok(json) => CString::new(json).map_or(ptr::null_mut(), CString::into_raw),
+        Err(_) => ptr::null_mut(),
+    }
+}
+
+/// Free a string returned by sarctool.
+///
+/// # Safety
+///
+/// `string` must be null or have come from sarctool, and not have been freed already.
+#[no_mangle]
+pub unsafe extern "C" fn sarctool_free_string(string: *mut c_char) {
+    if !string.is_null() {
+        drop(CString::from_raw(string));
+    }
+}
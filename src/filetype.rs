@@ -0,0 +1,114 @@
+//! Identification of common inner file formats by their magic bytes
+
+/// Versions BYML files have been written in
+const BYML_VERSIONS: std::ops::RangeInclusive<u16> = 1..=7;
+
+/// The two byte magic of BYML is common enough in text that the version after it has to be one
+/// too, read in the byte order the magic is in
+fn is_byml(data: &[u8]) -> bool {
+    let version = match data {
+        [b'B', b'Y', hi, lo, ..] => u16::from_be_bytes([*hi, *lo]),
+        [b'Y', b'B', lo, hi, ..] => u16::from_le_bytes([*lo, *hi]),
+        _ => return false,
+    };
+    BYML_VERSIONS.contains(&version)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FileType {
+    Byml,
+    Aamp,
+    Sarc,
+    U8,
+    Bntx,
+    Bfres,
+    Msbt,
+    Yaz0,
+    Zstd,
+    Lz4,
+}
+
+impl FileType {
+    pub const ALL: &'static [FileType] = &[
+        FileType::Byml, FileType::Aamp, FileType::Sarc, FileType::U8, FileType::Bntx, FileType::Bfres,
+        FileType::Msbt, FileType::Yaz0, FileType::Zstd, FileType::Lz4,
+    ];
+
+    pub fn detect(data: &[u8]) -> Option<Self> {
+        let magic = |magic: &[u8]| data.starts_with(magic);
+
+        if is_byml(data) {
+            Some(FileType::Byml)
+        } else if magic(b"AAMP") {
+            Some(FileType::Aamp)
+        } else if magic(b"SARC") {
+            Some(FileType::Sarc)
+        } else if magic(b"\x55\xAA\x38\x2D") {
+            Some(FileType::U8)
+        } else if magic(b"BNTX") {
+            Some(FileType::Bntx)
+        } else if magic(b"FRES") {
+            Some(FileType::Bfres)
+        } else if magic(b"MsgStdBn") {
+            Some(FileType::Msbt)
+        } else if magic(b"Yaz0") {
+            Some(FileType::Yaz0)
+        } else if magic(b"\x28\xB5\x2F\xFD") {
+            Some(FileType::Zstd)
+        } else if magic(b"\x04\x22\x4D\x18") {
+            Some(FileType::Lz4)
+        } else {
+            None
+        }
+    }
+
+    /// The type with this name, in any case
+    pub fn from_name(name: &str) -> Option<Self> {
+        FileType::ALL.iter().copied().find(|file_type| file_type.name().eq_ignore_ascii_case(name))
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            FileType::Byml => "BYML",
+            FileType::Aamp => "AAMP",
+            FileType::Sarc => "SARC",
+            FileType::U8 => "U8",
+            FileType::Bntx => "BNTX",
+            FileType::Bfres => "BFRES",
+            FileType::Msbt => "MSBT",
+            FileType::Yaz0 => "Yaz0",
+            FileType::Zstd => "zstd",
+            FileType::Lz4 => "LZ4",
+        }
+    }
+
+    /// Extension files of the type usually have, for naming entries that have no name
+    pub fn extension(self) -> &'static str {
+        match self {
+            FileType::Byml => "byml",
+            FileType::Aamp => "aamp",
+            FileType::Sarc => "sarc",
+            FileType::U8 => "arc",
+            FileType::Bntx => "bntx",
+            FileType::Bfres => "bfres",
+            FileType::Msbt => "msbt",
+            FileType::Yaz0 => "szs",
+            FileType::Zstd => "zs",
+            FileType::Lz4 => "lz4",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn byml_needs_a_version() {
+        assert_eq!(FileType::detect(b"BY\0\x02"), Some(FileType::Byml));
+        assert_eq!(FileType::detect(b"YB\x07\0"), Some(FileType::Byml));
+        assert_eq!(FileType::detect(b"YB\0\x07"), None);
+        assert_eq!(FileType::detect(b"BY the way"), None);
+        assert_eq!(FileType::detect(b"BY"), None);
+    }
+}
@@ -0,0 +1,133 @@
+//! An index of every entry in every archive of a game dump, built once by `index build` so
+//! `index query` doesn't have to open thousands of archives for each lookup
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::archive::{self, Archive};
+use crate::dictionary::Dictionaries;
+use crate::layout;
+use crate::{progress, stdio, ConvertError};
+
+/// Bumped whenever the format changes, so old indexes are rebuilt instead of misread
+const VERSION: u32 = 1;
+
+#[derive(Serialize, Deserialize)]
+pub struct Index {
+    version: u32,
+    /// Directory the index was built from
+    pub root: PathBuf,
+    pub archives: Vec<IndexedArchive>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct IndexedArchive {
+    /// Relative to the root, with `/` as the separator
+    pub path: String,
+    pub hash_key: u32,
+    pub entries: Vec<IndexedEntry>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct IndexedEntry {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    pub hash: u32,
+    pub size: usize,
+}
+
+/// What to look up, either a hash or a glob matched against names
+pub enum Query {
+    Hash(u32),
+    Name(glob::Pattern),
+}
+
+impl Query {
+    /// `0x1234ABCD` and `[1234ABCD]`, like unnamed entries are listed, are hashes, anything else
+    /// is a name
+    pub fn parse(query: &str) -> Result<Self, ConvertError> {
+        let hash = query.strip_prefix("0x")
+            .or_else(|| query.strip_prefix('[').and_then(|query| query.strip_suffix(']')));
+        match hash {
+            Some(hash) => u32::from_str_radix(hash, 16)
+                .map(Query::Hash)
+                .map_err(|_| ConvertError::param(&format!("'{}' is not a hex hash", query))),
+            None => glob::Pattern::new(query)
+                .map(Query::Name)
+                .map_err(|err| ConvertError::param(&err.to_string())),
+        }
+    }
+
+    /// Unnamed entries match names that hash to their hash
+    fn matches(&self, entry: &IndexedEntry, hash_key: u32) -> bool {
+        match (self, &entry.name) {
+            (Query::Hash(hash), _) => entry.hash == *hash,
+            (Query::Name(pattern), Some(name)) => pattern.matches(name),
+            (Query::Name(pattern), None) => layout::hash(pattern.as_str(), hash_key) == entry.hash,
+        }
+    }
+}
+
+impl Index {
+    pub fn build(root: &Path, dictionaries: &Dictionaries) -> Self {
+        let paths = archive::find_archives(root);
+        let progress = progress::entries(paths.len(), "Indexing");
+        let archives = paths.par_iter()
+            .filter_map(|path| {
+                progress.inc(1);
+                let archive = match Archive::open_shared(path, dictionaries) {
+                    Ok(archive) => archive,
+                    Err(err) => {
+                        log::warn!("Skipping {}", err);
+                        return None
+                    }
+                };
+                Some(IndexedArchive {
+                    path: path.strip_prefix(root).unwrap_or(path).to_string_lossy().replace("\\", "/"),
+                    hash_key: archive.layout.hash_key,
+                    entries: archive.layout.nodes.iter()
+                        .map(|node| IndexedEntry {
+                            name: node.name.clone(),
+                            hash: node.hash,
+                            size: node.data.len(),
+                        })
+                        .collect(),
+                })
+            })
+            .collect();
+        progress.finish_and_clear();
+        Index { version: VERSION, root: root.to_owned(), archives }
+    }
+
+    pub fn read(path: &Path) -> Result<Self, ConvertError> {
+        let file = File::open(path).map_err(|err| ConvertError::io(path, err))?;
+        let index: Index = serde_json::from_reader(BufReader::new(file))
+            .map_err(|err| ConvertError::file(&format!("{}: not an index ({})", path.display(), err)))?;
+        if index.version != VERSION {
+            return Err(ConvertError::file(&format!(
+                "{}: made by a different version of sarctool, run `index build` again", path.display()
+            )))
+        }
+        Ok(index)
+    }
+
+    pub fn write(&self, path: &Path) -> Result<(), ConvertError> {
+        stdio::create(path, |out| {
+            serde_json::to_writer(out, self).map_err(|err| ConvertError::file(&format!("{}: {}", path.display(), err)))
+        })
+    }
+
+    /// Every matching entry, along with the archive it's in
+    pub fn query<'a>(&'a self, query: &'a Query) -> impl Iterator<Item = (&'a IndexedArchive, &'a IndexedEntry)> + 'a {
+        self.archives.iter()
+            .flat_map(|archive| archive.entries.iter().map(move |entry| (archive, entry)))
+            .filter(move |(archive, entry)| query.matches(entry, archive.hash_key))
+    }
+
+    pub fn entry_count(&self) -> usize {
+        self.archives.iter().map(|archive| archive.entries.len()).sum()
+    }
+}
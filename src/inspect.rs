@@ -0,0 +1,431 @@
+//! Looking into archives without changing them: listing, decoding, checking, comparing and
+//! searching their entries
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use rayon::prelude::*;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+use crate::archive::{self, Archive, Entry};
+use crate::compression::{self, Compression};
+use crate::dictionary::Dictionaries;
+use crate::filetype::FileType;
+use crate::layout;
+use crate::search::Pattern;
+use crate::yaml::YamlFormat;
+use crate::{extract, msbt, progress, stdio, ConvertError};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SortKey {
+    Size,
+    Name,
+    Offset,
+}
+
+impl FromStr for SortKey {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "size" => Ok(SortKey::Size),
+            "name" => Ok(SortKey::Name),
+            "offset" => Ok(SortKey::Offset),
+            _ => Err(format!("unknown sort key '{}'", s)),
+        }
+    }
+}
+
+/// Entries whose names match `filter`, in archive order unless sorted
+pub fn entries<'a>(
+    archive: &'a Archive, filter: Option<&glob::Pattern>, sort: Option<SortKey>, reverse: bool
+) -> Vec<Entry<'a>> {
+    let mut entries: Vec<Entry> = archive.entries()
+        .filter(|entry| match (filter, entry.name) {
+            (Some(filter), Some(name)) => filter.matches(name),
+            (Some(_), None) => false,
+            (None, _) => true,
+        })
+        .collect();
+
+    match sort {
+        Some(SortKey::Size) => entries.sort_by_key(|entry| entry.data.len()),
+        Some(SortKey::Name) => entries.sort_by_key(|entry| entry.name),
+        Some(SortKey::Offset) => entries.sort_by_key(|entry| entry.offset),
+        None => (),
+    }
+    if reverse {
+        entries.reverse();
+    }
+    entries
+}
+
+pub fn sha256(data: &[u8]) -> String {
+    Sha256::digest(data).iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+pub fn crc32(data: &[u8]) -> String {
+    let mut hasher = crc32fast::Hasher::new();
+    hasher.update(data);
+    format!("{:08x}", hasher.finalize())
+}
+
+/// An entry as text: the messages of an MSBT file, or a BYML or AAMP file as YAML
+pub fn decoded(entry: &Entry) -> Result<String, ConvertError> {
+    if FileType::detect(entry.data) == Some(FileType::Msbt) {
+        return msbt::to_text(entry.data)
+    }
+    match YamlFormat::detect(&entry.display_name(), entry.data) {
+        Some(format) => format.to_yaml(entry.data),
+        None => Err(ConvertError::param("not an MSBT, BYML or AAMP file, so it can't be decoded")),
+    }
+}
+
+/// Everything wrong with an archive opened with [`Archive::open_unchecked`]
+pub fn problems(archive: &Archive) -> Vec<String> {
+    let layout = &archive.layout;
+    let mut problems = vec![];
+
+    if let Err(err) = archive.check() {
+        problems.push(err.message);
+    }
+    if layout.container.sorted_by_hash() && layout.nodes.windows(2).any(|nodes| nodes[0].hash > nodes[1].hash) {
+        problems.push("SFAT is not sorted by hash".to_owned());
+    }
+    for node in &layout.nodes {
+        if let Some(name) = &node.name {
+            let hash = layout::hash(name, layout.hash_key);
+            if hash != node.hash {
+                problems.push(format!("{} has hash {:08X} instead of {:08X}", name, node.hash, hash));
+            }
+        }
+    }
+    // from the nodes rather than the entries, whose data may be outside of the archive
+    let names = layout.nodes.iter().map(|node| {
+        let name = node.name.as_ref().or_else(|| archive.mapped_names.get(&node.hash));
+        (name.cloned().unwrap_or_else(|| format!("[{:08X}]", node.hash)), node.hash)
+    });
+    problems.extend(layout::name_problems(names));
+    problems
+}
+
+/// The archive in a dump matching a modded one, by the longest part of its path found in the
+/// dump, or else by its name if only one archive in the dump has it
+pub fn vanilla_archive(against: &Path, modded: &Path) -> Result<PathBuf, ConvertError> {
+    if !against.is_dir() {
+        return Ok(against.to_owned())
+    }
+    let modded = fs::canonicalize(modded).map_err(|err| ConvertError::io(modded, err))?;
+    // the modded archive may be inside of the dump itself
+    let is_same_file = |path: &Path| fs::canonicalize(path).ok().as_ref() == Some(&modded);
+    let components: Vec<_> = modded.components().collect();
+    for start in 0..components.len() {
+        let path: PathBuf = against.join(components[start..].iter().collect::<PathBuf>());
+        if path.is_file() && !is_same_file(&path) {
+            return Ok(path)
+        }
+    }
+
+    let file_name = modded.file_name().unwrap_or_default();
+    let found: Vec<PathBuf> = walkdir::WalkDir::new(against)
+        .into_iter()
+        .filter_map(Result::ok)
+        .filter(|entry| entry.file_type().is_file() && entry.file_name() == file_name && !is_same_file(entry.path()))
+        .map(walkdir::DirEntry::into_path)
+        .collect();
+    match &found[..] {
+        [path] => Ok(path.clone()),
+        [] => Err(ConvertError::file(&format!(
+            "{}: no {} in {}", modded.display(), file_name.to_string_lossy(), against.display()
+        ))),
+        paths => Err(ConvertError::file(&format!(
+            "{}: {} archives in {} are named {}, pass one of them with --against", modded.display(),
+            paths.len(), against.display(), file_name.to_string_lossy()
+        ))),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Change {
+    Added,
+    Modified,
+    Deleted,
+}
+
+impl Change {
+    /// Letter `diff` marks the change with, like `git status --short`
+    pub fn letter(self) -> char {
+        match self {
+            Change::Added => 'A',
+            Change::Modified => 'M',
+            Change::Deleted => 'D',
+        }
+    }
+}
+
+/// Entries added to or changed in `modded`, in its order, followed by those deleted from
+/// `vanilla`, in its order
+pub fn diff(modded: &Archive, vanilla: &Archive) -> Vec<(Change, String)> {
+    let vanilla_entries: HashMap<String, &[u8]> = vanilla.entries()
+        .map(|entry| (entry.display_name().into_owned(), entry.data))
+        .collect();
+    let modded_names: HashSet<String> = modded.entries().map(|entry| entry.display_name().into_owned()).collect();
+
+    let mut changes = vec![];
+    for entry in modded.entries() {
+        let name = entry.display_name().into_owned();
+        match vanilla_entries.get(&name) {
+            None => changes.push((Change::Added, name)),
+            Some(data) if *data != entry.data => changes.push((Change::Modified, name)),
+            Some(_) => (),
+        }
+    }
+    for entry in vanilla.entries() {
+        let name = entry.display_name().into_owned();
+        if !modded_names.contains(&name) {
+            changes.push((Change::Deleted, name));
+        }
+    }
+    changes
+}
+
+/// Write entries to `out_dir`, at their names
+pub fn export<'a>(entries: impl IntoIterator<Item = Entry<'a>>, out_dir: &Path) -> Result<(), ConvertError> {
+    for entry in entries {
+        let path = out_dir.join(extract::safe_path(&entry.display_name()).as_ref());
+        if let Some(parent) = path.parent() {
+            stdio::create_dir_all(parent)?;
+        }
+        log::info!("Exporting {}", entry.display_name());
+        stdio::write(&path, entry.data)?;
+    }
+    Ok(())
+}
+
+/// Names of the entries matching `pattern` in each archive under `dir`, skipping archives that
+/// can't be read
+pub fn find(dir: &Path, pattern: &glob::Pattern, dict: Option<&Path>) -> Result<Vec<(PathBuf, Vec<String>)>, ConvertError> {
+    if !dir.is_dir() {
+        return Err(ConvertError::file("not a directory").in_file(dir))
+    }
+    let archives = archive::find_archives(dir);
+    // a dump has a single set of dictionaries for all of its archives
+    let dictionaries = Dictionaries::load_in(dict, dir)?;
+
+    let progress = progress::entries(archives.len(), "Searching");
+    let found: Vec<Vec<String>> = archives.par_iter()
+        .map(|path| {
+            progress.inc(1);
+            match Archive::open_shared(path, &dictionaries) {
+                Ok(archive) => archive.entries()
+                    .map(|entry| entry.display_name().into_owned())
+                    .filter(|name| pattern.matches(name))
+                    .collect(),
+                Err(err) => {
+                    log::warn!("Skipping {}", err);
+                    vec![]
+                }
+            }
+        })
+        .collect();
+    progress.finish_and_clear();
+    Ok(archives.into_iter().zip(found).collect())
+}
+
+/// Offsets of `pattern` in each entry, which are offsets into the decompressed data of
+/// compressed entries with `decompress_inner`
+pub fn grep<'a>(archive: &'a Archive, pattern: &Pattern, decompress_inner: bool) -> Result<Vec<(Entry<'a>, Vec<usize>)>, ConvertError> {
+    let entries: Vec<Entry> = archive.entries().collect();
+    let found = entries.par_iter()
+        .map(|entry| {
+            if decompress_inner && Compression::detect(entry.data) != Compression::None {
+                let data = compression::decompress(entry.data, &archive.dictionaries)
+                    .map_err(|err| err.in_entry(&entry.display_name()))?;
+                Ok(pattern.offsets(&data))
+            } else {
+                Ok(pattern.offsets(entry.data))
+            }
+        })
+        .collect::<Result<Vec<_>, ConvertError>>()?;
+    Ok(entries.into_iter().zip(found).collect())
+}
+
+/// Entries with the same data
+pub struct Duplicates {
+    /// Size of each copy
+    pub size: usize,
+    pub names: Vec<String>,
+}
+
+impl Duplicates {
+    /// Space taken by every copy after the first
+    pub fn wasted(&self) -> usize {
+        self.size * (self.names.len() - 1)
+    }
+}
+
+/// Groups of named entries with the same data, the most wasted space first
+pub fn duplicates(entries: &[(String, &[u8])]) -> Vec<Duplicates> {
+    let digests: Vec<String> = entries.par_iter().map(|(_, data)| sha256(data)).collect();
+
+    let mut groups: HashMap<&str, Vec<usize>> = HashMap::new();
+    for (i, digest) in digests.iter().enumerate() {
+        groups.entry(digest).or_default().push(i);
+    }
+    let mut groups: Vec<Vec<usize>> = groups.into_values().filter(|group| group.len() > 1).collect();
+    groups.sort_by_key(|group| group[0]);
+    let mut duplicates: Vec<Duplicates> = groups.into_iter()
+        .map(|group| Duplicates {
+            size: entries[group[0]].1.len(),
+            names: group.iter().map(|&i| entries[i].0.clone()).collect(),
+        })
+        .collect();
+    // stable, so groups wasting as much stay in archive order
+    duplicates.sort_by_key(|group| std::cmp::Reverse(group.wasted()));
+    duplicates
+}
+
+/// How many entries there are of something, and how much space they take
+#[derive(Debug, Default, Clone, Copy, Serialize)]
+pub struct Total {
+    pub count: usize,
+    pub size: u64,
+}
+
+impl Total {
+    fn add(&mut self, size: u64) {
+        self.count += 1;
+        self.size += size;
+    }
+
+    fn merge(&mut self, other: Total) {
+        self.count += other.count;
+        self.size += other.size;
+    }
+}
+
+/// Upper bounds of the sizes entries are grouped by
+pub const SIZE_BUCKETS: [u64; 5] = [1 << 10, 1 << 14, 1 << 18, 1 << 22, 1 << 26];
+
+#[derive(Debug, Default, Serialize)]
+pub struct ArchiveStats {
+    pub path: PathBuf,
+    /// Size of the file, compressed if the archive is
+    pub file_size: u64,
+    pub entries: Total,
+}
+
+/// Totals of every entry of a set of archives
+#[derive(Debug, Default, Serialize)]
+pub struct Stats {
+    pub archives: Vec<ArchiveStats>,
+    pub entries: Total,
+    /// By the type detected from the data, or `-` for unknown types
+    pub by_type: BTreeMap<String, Total>,
+    /// By the extension of the name, `(none)` without one and `(unnamed)` for entries without
+    /// a name
+    pub by_extension: BTreeMap<String, Total>,
+    /// By size, with entries counted in the first of [`SIZE_BUCKETS`] they're smaller than, or
+    /// in the last total if they're bigger than all of them
+    pub by_size: [Total; SIZE_BUCKETS.len() + 1],
+}
+
+impl Stats {
+    fn add(&mut self, archive: &Archive, path: &Path) {
+        let mut archive_stats = ArchiveStats {
+            path: path.to_owned(),
+            file_size: fs::metadata(path).map_or(0, |metadata| metadata.len()),
+            entries: Total::default(),
+        };
+        for entry in archive.entries() {
+            let size = entry.data.len() as u64;
+            archive_stats.entries.add(size);
+            let file_type = FileType::detect(entry.data).map_or("-", FileType::name);
+            self.by_type.entry(file_type.to_owned()).or_default().add(size);
+            let extension = match entry.name {
+                Some(name) => {
+                    let file_name = name.rsplit('/').next().unwrap_or(name);
+                    file_name.rsplit_once('.').map_or("(none)", |(_, extension)| extension)
+                }
+                None => "(unnamed)",
+            };
+            self.by_extension.entry(extension.to_owned()).or_default().add(size);
+            let bucket = SIZE_BUCKETS.iter().position(|&bound| size < bound).unwrap_or(SIZE_BUCKETS.len());
+            self.by_size[bucket].add(size);
+        }
+        self.entries.merge(archive_stats.entries);
+        self.archives.push(archive_stats);
+    }
+
+    fn merge(&mut self, other: Stats) {
+        self.archives.extend(other.archives);
+        self.entries.merge(other.entries);
+        for (file_type, total) in other.by_type {
+            self.by_type.entry(file_type).or_default().merge(total);
+        }
+        for (extension, total) in other.by_extension {
+            self.by_extension.entry(extension).or_default().merge(total);
+        }
+        for (bucket, total) in self.by_size.iter_mut().zip(other.by_size) {
+            bucket.merge(total);
+        }
+    }
+}
+
+/// Totals by type, extension and size of the entries of the given archives and every archive
+/// in the given directories, skipping archives that can't be read
+pub fn stats(inputs: &[PathBuf], dict: Option<&Path>) -> Result<Stats, ConvertError> {
+    let mut stats = Stats::default();
+    for input in inputs {
+        let (archives, dictionaries) = if input.is_dir() {
+            // a dump has a single set of dictionaries for all of its archives
+            (archive::find_archives(input), Dictionaries::load_in(dict, input)?)
+        } else {
+            (vec![input.clone()], Dictionaries::load(dict, input)?)
+        };
+        let progress = progress::entries(archives.len(), "Reading");
+        let found: Vec<Stats> = archives.par_iter()
+            .filter_map(|path| {
+                progress.inc(1);
+                let archive = Archive::open_shared(path, &dictionaries)
+                    .map_err(|err| log::warn!("Skipping {}", err))
+                    .ok()?;
+                let mut stats = Stats::default();
+                stats.add(&archive, path);
+                Some(stats)
+            })
+            .collect();
+        progress.finish_and_clear();
+        for found in found {
+            stats.merge(found);
+        }
+    }
+    Ok(stats)
+}
+
+#[cfg(test)]
+mod tests {
+    use sarc::Endian;
+
+    use super::*;
+    use crate::layout::DEFAULT_HASH_KEY;
+    use crate::writer::{SarcWriter, WriterEntry};
+
+    #[test]
+    fn data_outside_of_the_archive_is_a_problem() {
+        let files = vec![WriterEntry::named("a.bin".to_owned(), b"data".to_vec(), DEFAULT_HASH_KEY)];
+        let mut data = vec![];
+        SarcWriter::new(Endian::Little, files).write(&mut data).unwrap();
+        // the end of the data of the first node, after the SARC and SFAT headers
+        data[0x2C..0x30].copy_from_slice(&0xFFFFu32.to_le_bytes());
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("broken.sarc");
+        fs::write(&path, &data).unwrap();
+
+        let problems = problems(&Archive::open_unchecked(&path, None).unwrap());
+        assert_eq!(problems.len(), 1, "{:?}", problems);
+        assert!(problems[0].contains("outside of the archive"), "{}", problems[0]);
+    }
+}
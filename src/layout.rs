@@ -0,0 +1,192 @@
+//! Direct access to the SARC header and SFAT/SFNT tables, for the details `sarc::SarcFile` doesn't
+//! keep around (data offsets, hashes, the hash key, ...)
+use std::collections::HashMap;
+use std::convert::TryInto;
+use std::ops::Range;
+
+use sarc::Endian;
+
+use crate::container::Container;
+use crate::ConvertError;
+
+pub const SARC_HEADER_SIZE: usize = 0x14;
+pub const SFAT_HEADER_SIZE: usize = 0xC;
+pub const SFAT_NODE_SIZE: usize = 0x10;
+pub const SFNT_HEADER_SIZE: usize = 0x8;
+
+pub const DEFAULT_HASH_KEY: u32 = 0x65;
+
+/// The SFAT name hash, for an arbitrary hash key
+pub fn hash(name: &str, key: u32) -> u32 {
+    name.chars().fold(0u32, |hash, c| hash.wrapping_mul(key).wrapping_add(c as u32))
+}
+
+/// Entries the games can't look up: names that are used more than once, and different names
+/// with the same hash. Takes the name, or something to show for unnamed entries, and the hash of
+/// every entry.
+pub fn name_problems<I: IntoIterator<Item = (String, u32)>>(entries: I) -> Vec<String> {
+    let mut by_hash: HashMap<u32, Vec<String>> = HashMap::new();
+    for (name, hash) in entries {
+        by_hash.entry(hash).or_default().push(name);
+    }
+    let mut shared: Vec<(u32, Vec<String>)> = by_hash.into_iter().filter(|(_, names)| names.len() > 1).collect();
+    shared.sort();
+
+    let mut problems = vec![];
+    for (hash, mut names) in shared {
+        names.sort();
+        let mut distinct = names.clone();
+        distinct.dedup();
+        for name in &distinct {
+            let count = names.iter().filter(|other| *other == name).count();
+            if count > 1 {
+                problems.push(format!("{} is in the archive {} times", name, count));
+            }
+        }
+        if distinct.len() > 1 {
+            problems.push(format!("{} have the same hash {:08X}", distinct.join(", "), hash));
+        }
+    }
+    problems
+}
+
+pub struct Layout {
+    pub container: Container,
+    pub byte_order: Endian,
+    pub file_size: u32,
+    pub data_offset: u32,
+    pub hash_key: u32,
+    pub nodes: Vec<Node>,
+}
+
+pub struct Node {
+    pub hash: u32,
+    pub attributes: u32,
+    pub name: Option<String>,
+    /// Range of the file's data, relative to the start of the data section
+    pub data: Range<u32>,
+}
+
+impl Node {
+    /// The top byte of the attributes is 0 for unnamed entries, and otherwise a 1-based index
+    /// among entries with the same hash
+    pub fn name_offset(&self) -> Option<u32> {
+        if self.attributes >> 24 != 0 {
+            Some((self.attributes & 0xFFFFFF) * 4)
+        } else {
+            None
+        }
+    }
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    big_endian: bool,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes<const N: usize>(&self, offset: usize) -> Result<[u8; N], ConvertError> {
+        self.data.get(offset..offset + N)
+            .map(|bytes| bytes.try_into().unwrap())
+            .ok_or_else(|| ConvertError::sarc(format!("Unexpected end of file at {:#x}", offset)))
+    }
+
+    fn u16(&self, offset: usize) -> Result<u16, ConvertError> {
+        let bytes = self.bytes(offset)?;
+        Ok(if self.big_endian {
+            u16::from_be_bytes(bytes)
+        } else {
+            u16::from_le_bytes(bytes)
+        })
+    }
+
+    fn u32(&self, offset: usize) -> Result<u32, ConvertError> {
+        let bytes = self.bytes(offset)?;
+        Ok(if self.big_endian {
+            u32::from_be_bytes(bytes)
+        } else {
+            u32::from_le_bytes(bytes)
+        })
+    }
+}
+
+fn magic(data: &[u8], offset: usize, magic: &[u8; 4]) -> Result<(), ConvertError> {
+    if data.get(offset..offset + 4) == Some(&magic[..]) {
+        Ok(())
+    } else {
+        Err(ConvertError::sarc(format!(
+            "Expected {} magic at {:#x}", String::from_utf8_lossy(magic), offset
+        )))
+    }
+}
+
+pub(crate) fn string_at(names: &[u8], offset: usize) -> Option<String> {
+    let name = names.get(offset..)?;
+    let end = name.iter().position(|&b| b == 0)?;
+    std::str::from_utf8(&name[..end]).ok().map(String::from)
+}
+
+impl Layout {
+    /// Parse the tables of an uncompressed SARC
+    pub fn parse(data: &[u8]) -> Result<Self, ConvertError> {
+        magic(data, 0, b"SARC")?;
+        let byte_order = match data.get(6..8) {
+            Some([0xFE, 0xFF]) => Endian::Big,
+            Some([0xFF, 0xFE]) => Endian::Little,
+            _ => return Err(ConvertError::sarc("Invalid byte order mark")),
+        };
+        let reader = Reader { data, big_endian: matches!(byte_order, Endian::Big) };
+        let header_size = reader.u16(4)? as usize;
+        let file_size = reader.u32(8)?;
+        let data_offset = reader.u32(0xC)?;
+
+        let sfat = header_size;
+        magic(data, sfat, b"SFAT")?;
+        let node_count = reader.u16(sfat + 6)? as usize;
+        let hash_key = reader.u32(sfat + 8)?;
+
+        let sfnt = sfat + SFAT_HEADER_SIZE + node_count * SFAT_NODE_SIZE;
+        magic(data, sfnt, b"SFNT")?;
+        let names = data.get(sfnt + SFNT_HEADER_SIZE..data_offset as usize).unwrap_or_default();
+
+        let nodes = (0..node_count)
+            .map(|i| {
+                let node = sfat + SFAT_HEADER_SIZE + i * SFAT_NODE_SIZE;
+                let mut node = Node {
+                    hash: reader.u32(node)?,
+                    attributes: reader.u32(node + 4)?,
+                    name: None,
+                    data: reader.u32(node + 8)?..reader.u32(node + 0xC)?,
+                };
+                node.name = node.name_offset().and_then(|off| string_at(names, off as usize));
+                Ok(node)
+            })
+            .collect::<Result<_, ConvertError>>()?;
+
+        Ok(Layout {
+            container: Container::SARC,
+            byte_order,
+            file_size,
+            data_offset,
+            hash_key,
+            nodes,
+        })
+    }
+
+    /// Absolute offset of a node's data within the archive
+    pub fn absolute(&self, node: &Node) -> Range<usize> {
+        let base = self.data_offset as usize;
+        base + node.data.start as usize..base + node.data.end as usize
+    }
+
+    /// Largest alignment the data of every entry has, up to `max`
+    pub fn alignment(&self, max: usize) -> usize {
+        self.nodes.iter()
+            .map(|node| match self.absolute(node).start {
+                0 => max,
+                start => max.min(1 << start.trailing_zeros()),
+            })
+            .min()
+            .unwrap_or(max)
+    }
+}
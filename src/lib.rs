@@ -0,0 +1,67 @@
+//! Packing, extracting, converting and inspecting SARC archives, the library behind the `sarc`
+//! command line tool.
+//!
+//! Each command of the tool is a function here, taking the same options and returning a
+//! [`ConvertError`] when it fails:
+//!
+//! - [`pack`] builds archives from directories and manifests
+//! - [`extract`] extracts them, recording what's needed to rebuild them as they were
+//! - [`convert`] rewrites them, and converts them to and from zip, tar and 7z
+//! - [`inspect`] lists, decodes, checks, compares and searches them
+//!
+//! Archives are opened with [`archive::Archive`], which handles their outer compression:
+//!
+//! ```no_run
+//! use std::path::Path;
+//! use sarctool::archive::Archive;
+//!
+//! let archive = Archive::open(Path::new("Player.sbactorpack"), None)?;
+//! for entry in archive.entries() {
+//!     println!("{} ({} bytes)", entry.display_name(), entry.data.len());
+//! }
+//! # Ok::<(), sarctool::ConvertError>(())
+//! ```
+//!
+//! SARC and U8 archives are both read and written through [`container::ArchiveContainer`], which
+//! other kinds of archive can implement and add with [`container::register`].
+pub mod alignment;
+pub mod archive;
+pub mod cache;
+pub mod checksums;
+pub mod compression;
+pub mod config;
+pub mod container;
+pub mod convert;
+pub mod dictionary;
+mod error;
+pub mod exec;
+pub mod extract;
+#[cfg(feature = "cdylib")]
+pub mod ffi;
+pub mod filetype;
+pub mod index;
+pub mod inspect;
+pub mod layout;
+pub mod manifest;
+pub mod metadata;
+#[cfg(unix)]
+pub mod mount;
+pub mod msbt;
+pub mod name_map;
+pub mod pack;
+pub mod preset;
+pub mod progress;
+#[cfg(feature = "python")]
+mod python;
+pub mod scan;
+pub mod search;
+pub mod sevenz;
+pub mod stdio;
+pub mod tarball;
+pub mod u8_archive;
+pub mod watch;
+pub mod writer;
+pub mod yaml;
+pub mod zip_method;
+
+pub use error::{ConvertError, ConvertErrorKind};
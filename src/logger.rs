@@ -0,0 +1,97 @@
+//! stderr logger behind `-v`/`-q`, optionally writing one JSON object per line for tools
+//! wrapping sarctool
+use std::io::Write;
+use std::panic;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use log::{Level, LevelFilter, Log, Metadata, Record};
+
+use sarctool::{progress, ConvertError, ConvertErrorKind};
+
+/// Whether failures are written as JSON, for `--error-format json`
+static ERROR_JSON: AtomicBool = AtomicBool::new(false);
+
+struct Logger {
+    json: bool,
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        // messages of the library (`sarctool`) and the `sarc` binary keep their level, while
+        // those of dependencies, like fuser noting an unmount it raced, are only of use when
+        // debugging and so are treated as debug messages at most
+        let level = if matches!(metadata.target().split("::").next(), Some("sarctool" | "sarc")) {
+            metadata.level()
+        } else {
+            metadata.level().max(Level::Debug)
+        };
+        level <= log::max_level()
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return
+        }
+
+        let line = if self.json {
+            serde_json::json!({
+                "level": record.level().as_str().to_lowercase(),
+                "target": record.target(),
+                "message": record.args().to_string(),
+            }).to_string()
+        } else {
+            match record.level() {
+                Level::Error => format!("error: {}", record.args()),
+                Level::Warn => format!("warning: {}", record.args()),
+                Level::Info => record.args().to_string(),
+                Level::Debug | Level::Trace => format!("debug: {}", record.args()),
+            }
+        };
+        write_line(&line);
+    }
+
+    fn flush(&self) {}
+}
+
+fn write_line(line: &str) {
+    progress::suspend(|| {
+        let _ = writeln!(std::io::stderr().lock(), "{}", line);
+    });
+}
+
+/// Report a failure, as a JSON object of its kind, message, path and entry with `error_json`
+pub fn error(err: &ConvertError) {
+    if ERROR_JSON.load(Ordering::Relaxed) {
+        write_line(&serde_json::to_string(err).unwrap_or_default());
+    } else {
+        log::error!("{}", err);
+    }
+}
+
+/// Warnings and errors are shown by default, `quiet` leaves only errors and every `verbose`
+/// adds a level. `error_json` reports failures, panics included, as JSON.
+pub fn init(verbose: u64, quiet: bool, json: bool, error_json: bool) {
+    ERROR_JSON.store(error_json, Ordering::Relaxed);
+    if error_json {
+        panic::set_hook(Box::new(|info| {
+            let message = info.payload().downcast_ref::<&str>().map(|message| message.to_string())
+                .or_else(|| info.payload().downcast_ref::<String>().cloned())
+                .unwrap_or_default();
+            let location = info.location().map(|location| format!(" at {}", location)).unwrap_or_default();
+            error(&ConvertError::new(ConvertErrorKind::Panic, format!("panicked{}: {}", location, message)));
+        }));
+    }
+
+    let level = if quiet {
+        LevelFilter::Error
+    } else {
+        match verbose {
+            0 => LevelFilter::Warn,
+            1 => LevelFilter::Info,
+            2 => LevelFilter::Debug,
+            _ => LevelFilter::Trace,
+        }
+    };
+    log::set_boxed_logger(Box::new(Logger { json })).expect("logger was already set");
+    log::set_max_level(level);
+}
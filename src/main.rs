@@ -1,16 +1,91 @@
-use std::fs::{self, File};
+use std::collections::HashSet;
+use std::ffi::OsString;
+use std::fs;
 use std::io::prelude::*;
 use std::path::{Path, PathBuf};
-use prettytable::{Table, Row, Cell, row, cell, format::{FormatBuilder, LinePosition, LineSeparator}};
+use std::str::FromStr;
+use std::time::Instant;
+use rayon::prelude::*;
+use prettytable::{Table, Row, Cell, format::{FormatBuilder, LinePosition, LineSeparator, TableFormat}};
 use humansize::{FileSize, file_size_opts::CONVENTIONAL};
 
-use sarc::{SarcFile, Endian, SarcEntry};
-use zip::{CompressionMethod, ZipArchive, ZipWriter, result::ZipError, write::FileOptions};
+use sarc::Endian;
 
 use structopt::StructOpt;
 
+use sarctool::*;
+
+mod batch;
+mod logger;
+mod script;
+use alignment::{parse_int, Alignment};
+use archive::{Archive, Entry};
+use checksums::ChecksumManifest;
+use compression::{Compression, CompressionOptions};
+use config::Config;
+use container::Container;
+use dictionary::Dictionaries;
+use extract::{EntryFilter, ExtractOptions};
+use filetype::FileType;
+use index::Index;
+use inspect::{crc32, sha256, SortKey};
+use layout::{Layout, DEFAULT_HASH_KEY};
+use name_map::NameMap;
+use metadata::{ArchiveMetadata, ByteOrder, METADATA_FILE};
+use pack::PackArgs;
+use scan::ScanArgs;
+use search::{Pattern, PatternKind};
+use stdio::Existing;
+use tarball::{TarCompression, TAR_COMPRESSIONS};
+use watch::FileCache;
+use writer::{SarcWriter, WriterEntry};
+use yaml::YamlFormat;
+use zip_method::{ZipMethod, ZIP_METHODS};
+
 #[derive(StructOpt, Debug, Clone)]
 struct Args {
+    #[structopt(long, global = true)]
+    dict: Option<PathBuf>,
+    /// CSV of `hash,name` rows, or just names, for entries of archives without a name table
+    #[structopt(long, global = true)]
+    name_map: Option<PathBuf>,
+    /// Defaults to ~/.config/sarctool/config.toml
+    #[structopt(long, global = true)]
+    config: Option<PathBuf>,
+    /// Show what is being done to each file, twice for offsets and alignment
+    #[structopt(short, long, global = true, parse(from_occurrences))]
+    verbose: u64,
+    /// Only print errors
+    #[structopt(short, long, global = true, conflicts_with = "verbose")]
+    quiet: bool,
+    /// Write log messages as JSON lines
+    #[structopt(long, global = true)]
+    log_json: bool,
+    /// Write failures to stderr as text, or as JSON objects of their kind, message, and the path
+    /// and entry they happened in
+    #[structopt(long, global = true, default_value = "text", possible_values = &["text", "json"])]
+    error_format: ErrorFormat,
+    /// Leave colors out of the output, as does setting NO_COLOR
+    #[structopt(long, global = true)]
+    no_color: bool,
+    /// Print the files that would be written, with their sizes, instead of writing them
+    #[structopt(long, global = true)]
+    dry_run: bool,
+    /// Overwrite outputs that already exist, and extract into directories that aren't empty
+    #[structopt(long, global = true)]
+    force: bool,
+    /// Leave outputs that already exist as they are, to resume an extraction
+    #[structopt(long, global = true, conflicts_with = "force")]
+    skip_existing: bool,
+    /// Make the same inputs give the same outputs on any machine: zip entries all get the same
+    /// fixed time, rather than that of the archive or the current one, and files not in
+    /// .sarctool.yml are packed in the order of their names
+    #[structopt(long, global = true)]
+    reproducible: bool,
+    /// Threads to work on entries, archives and zstd compression with, all of the cores by
+    /// default
+    #[structopt(long, global = true)]
+    threads: Option<usize>,
     #[structopt(subcommand)]
     command: Command,
 }
@@ -19,47 +94,473 @@ struct Args {
 enum Command {
     #[structopt(alias = "z")]
     Zip {
-        #[structopt(short, long, alias = "compress", alias = "c")]
-        yaz0: bool,
-        #[structopt(short, long, conflicts_with = "yaz0")]
-        zstd: bool,
+        #[structopt(flatten)]
+        pack: PackArgs,
+        #[structopt(flatten)]
+        scan: ScanArgs,
+
+        /// Fail if the directory has no .sarctool.yml, which is otherwise used when it's there
+        #[structopt(short, long)]
+        preserve: bool,
+        /// Don't reproduce the layout recorded in .sarctool.yml
+        #[structopt(long, conflicts_with = "preserve")]
+        ignore_metadata: bool,
+        /// Pack the files listed in this YAML or TOML manifest, in its order, rather than
+        /// everything in the directory
+        #[structopt(long, conflicts_with_all = &["preserve", "ignore-metadata"])]
+        manifest: Option<PathBuf>,
+        /// Keep running, and repack whenever something in the directory changes
+        #[structopt(short, long)]
+        watch: bool,
+        /// Reuse the entries earlier builds converted from YAML and compressed with
+        /// --compress-inner, kept in .sarctool-cache in the directory
+        #[structopt(long)]
+        update: bool,
 
-        #[structopt(short, long, alias = "big")]
-        big_endian: bool,
-        #[structopt(short, long, alias = "little", conflicts_with = "big")]
-        little_endian: bool,
+        in_dir: PathBuf,
+        out_file: PathBuf,
+    },
+    /// Pack a directory edited from an archive with the same settings the archive has: its
+    /// byte order, compression, hash key, alignment and order of entries
+    Repack {
+        #[structopt(flatten)]
+        pack: PackArgs,
+        #[structopt(flatten)]
+        scan: ScanArgs,
 
+        original: PathBuf,
         in_dir: PathBuf,
         out_file: PathBuf,
     },
     #[structopt(alias = "u", alias = "x", alias = "extract")]
     Unzip {
-        in_file: PathBuf,
-        out_dir: Option<PathBuf>,
+        /// Does nothing, the layout is always recorded in .sarctool.yml unless --no-metadata is given
+        #[structopt(short, long)]
+        preserve: bool,
+        /// Don't write .sarctool.yml next to the extracted files
+        #[structopt(long, conflicts_with = "preserve")]
+        no_metadata: bool,
+        /// Extract BYML entries as editable YAML, which `zip` turns back into BYML
+        #[structopt(long)]
+        byml_to_yaml: bool,
+        /// Extract AAMP parameter files, like actor .bxml and .bphysics, as editable YAML, which
+        /// `zip` turns back into AAMP
+        #[structopt(long)]
+        aamp_to_yaml: bool,
+        /// Also write the messages of MSBT entries as text next to them, which `zip` leaves out
+        #[structopt(long)]
+        msbt_to_text: bool,
+        /// Extract yaz0 and zstd compressed entries decompressed, under the same names, for
+        /// `zip --compress-inner` to compress again
+        #[structopt(long)]
+        decompress_inner: bool,
+        /// Extract every entry straight into the output directory, leaving out its directories.
+        /// Entries that end up with the same name are numbered
+        #[structopt(long)]
+        flatten: bool,
+        /// Only extract entries of at least this size, in bytes or with K, M or G after it, as
+        /// they're stored in the archive
+        #[structopt(long, parse(try_from_str = extract::parse_size))]
+        min_size: Option<usize>,
+        /// Only extract entries of at most this size
+        #[structopt(long, parse(try_from_str = extract::parse_size))]
+        max_size: Option<usize>,
+        /// Only extract entries of these types, like BYML or AAMP, or starting with these bytes in
+        /// hex. Nothing else being extracted, .sarctool.yml is left out.
+        #[structopt(long, use_delimiter = true)]
+        magic: Vec<extract::Magic>,
+        /// Extract each archive to a directory named after it in here, instead of next to it
+        #[structopt(long)]
+        out_root: Option<PathBuf>,
+        /// Archives or globs to extract, or a single archive followed by the directory to extract to
+        #[structopt(required = true)]
+        inputs: Vec<PathBuf>,
     },
     IntoZip {
+        /// Write each zip in here, instead of next to its archive
+        #[structopt(long)]
+        out_root: Option<PathBuf>,
+        /// How to compress the entries of the zip
+        #[structopt(long, default_value = "deflate", possible_values = ZIP_METHODS)]
+        method: ZipMethod,
+        /// Compression level of the method, 0-9 for deflate, 1-9 for bzip2 and up to 22 for zstd
+        #[structopt(long)]
+        level: Option<i32>,
+        /// Don't add the .sarctool.yml that lets from-zip rebuild the archive as it was
+        #[structopt(long)]
+        no_metadata: bool,
+        /// Archives or globs to convert, or a single archive followed by the zip to write
+        #[structopt(required = true)]
+        inputs: Vec<PathBuf>,
+    },
+    /// Rewrite archives with different compression, byte order or alignment
+    Convert {
+        #[structopt(flatten)]
+        pack: PackArgs,
+
+        #[structopt(long)]
+        out_root: PathBuf,
+        #[structopt(required = true)]
+        inputs: Vec<PathBuf>,
+    },
+    /// Change the outer compression of an archive, like a `.szs` into a `.zs`, leaving the
+    /// archive inside exactly as it was. Only the compression flags of `zip` apply.
+    Recompress {
+        #[structopt(flatten)]
+        pack: PackArgs,
+
         in_file: PathBuf,
         out_file: PathBuf,
     },
     FromZip {
-        #[structopt(short, long, alias = "compress", alias = "c")]
-        yaz0: bool,
-        #[structopt(short, long, conflicts_with = "yaz0")]
-        zstd: bool,
+        #[structopt(flatten)]
+        pack: PackArgs,
+
+        in_file: PathBuf,
+        out_file: PathBuf,
+    },
+    /// Extract archives in memory and pack them again like `zip` would, with the same flags and
+    /// config, to check that they come out byte-for-byte the same
+    Roundtrip {
+        #[structopt(flatten)]
+        pack: PackArgs,
+
+        /// Archives or globs to check
+        #[structopt(required = true)]
+        inputs: Vec<PathBuf>,
+    },
+    /// Convert archives into tarballs
+    IntoTar {
+        /// Write each tarball in here, instead of next to its archive
+        #[structopt(long)]
+        out_root: Option<PathBuf>,
+        /// Compress the tarball, by default picked from the extension of the output
+        #[structopt(long, possible_values = TAR_COMPRESSIONS)]
+        compression: Option<TarCompression>,
+        /// Archives or globs to convert, or a single archive followed by the tarball to write
+        #[structopt(required = true)]
+        inputs: Vec<PathBuf>,
+    },
+    /// Pack the files of a tarball, gzip and zstd compressed ones included, into an archive
+    FromTar {
+        #[structopt(flatten)]
+        pack: PackArgs,
 
-        #[structopt(short, long, alias = "big")]
-        big_endian: bool,
-        #[structopt(short, long, alias = "little", conflicts_with = "big")]
-        little_endian: bool,
+        in_file: PathBuf,
+        out_file: PathBuf,
+    },
+    /// Convert archives into 7z archives
+    #[structopt(name = "into-7z")]
+    IntoSevenZ {
+        /// Write each 7z in here, instead of next to its archive
+        #[structopt(long)]
+        out_root: Option<PathBuf>,
+        /// Archives or globs to convert, or a single archive followed by the 7z to write
+        #[structopt(required = true)]
+        inputs: Vec<PathBuf>,
+    },
+    /// Pack the files of a 7z archive into an archive
+    #[structopt(name = "from-7z")]
+    FromSevenZ {
+        #[structopt(flatten)]
+        pack: PackArgs,
 
         in_file: PathBuf,
         out_file: PathBuf,
     },
     #[structopt(alias = "-l", alias = "l")]
-    List {
+    List(ListArgs),
+    Hash {
+        #[structopt(long)]
+        crc32: bool,
+        in_file: PathBuf,
+    },
+    /// Check archives for problems that stop games from loading them or finding their entries
+    Verify {
+        /// Archives or globs to check
+        #[structopt(required = true)]
+        inputs: Vec<PathBuf>,
+    },
+    /// Compress an archive, or a directory packed like `zip` would, in different ways and compare
+    /// the sizes and how long each took
+    Analyze {
+        /// Comma-separated yaz0 levels to try
+        #[structopt(long, require_delimiter = true, default_value = "1,10", parse(try_from_str = pack::parse_yaz0_level))]
+        yaz0_levels: Vec<usize>,
+        /// Comma-separated zstd levels to try
+        #[structopt(long, require_delimiter = true, allow_hyphen_values = true, default_value = "1,3,9,15,19")]
+        zstd_levels: Vec<i32>,
+        /// Show sizes in bytes
         #[structopt(short, long)]
-        byte_count: bool,
+        bytes: bool,
+        #[structopt(flatten)]
+        scan: ScanArgs,
+        /// Archive or directory
+        input: PathBuf,
+    },
+    /// Look through every archive in a directory, like a game dump, for entries with matching
+    /// names
+    Find {
+        /// Directory to search
+        dir: PathBuf,
+        /// Glob matched against full entry names, like `Actor/Pack/Enemy_Lizalfos*.sbactorpack`
+        pattern: glob::Pattern,
+    },
+    /// Compare a modded archive to the vanilla one and list the entries that were added, changed
+    /// or removed
+    Diff {
+        /// Vanilla archive, or a dump to find the archive with the same path or name in
+        #[structopt(long)]
+        against: PathBuf,
+        /// Write the added and changed entries to this directory, ready to be packed into a mod
+        #[structopt(long)]
+        export: Option<PathBuf>,
         in_file: PathBuf,
+    },
+    /// Run the steps of a build listed in a YAML script, each one the arguments of a command
+    Run {
+        script: PathBuf,
+    },
+    /// What paths given without a command go to, as when they're dropped onto the executable
+    #[structopt(setting = structopt::clap::AppSettings::Hidden)]
+    Open {
+        #[structopt(required = true)]
+        paths: Vec<PathBuf>,
+    },
+    /// Merge mods made of partial archives into one, entry by entry, on top of the base game.
+    /// Later mods win when two change the same entry.
+    MergeMods {
+        /// Dump of the base game the mods were made for
+        #[structopt(long)]
+        base: PathBuf,
+        /// Directory to write the merged mod to
+        #[structopt(long)]
+        out: PathBuf,
+        /// Mod directories, laid out like the dump, from lowest to highest priority
+        #[structopt(required = true)]
+        mods: Vec<PathBuf>,
+    },
+    /// Mount an archive as a read-only directory until it's unmounted
+    #[cfg(unix)]
+    Mount {
+        /// Show archives inside of the archive as directories
+        #[structopt(long)]
+        nested: bool,
+        in_file: PathBuf,
+        mount_point: PathBuf,
+    },
+    /// Build and search an index of the entries of every archive in a directory
+    Index(IndexCommand),
+    /// Write the size and SHA-256 of every entry of an archive to publish with a mod, or check an
+    /// archive against them
+    Manifest(ManifestCommand),
+    /// Search the data of every entry for text, a regex or bytes, and print where it was found
+    Grep {
+        /// Search for the pattern as plain text, the default
+        #[structopt(long)]
+        text: bool,
+        /// Search for the pattern as a regex
+        #[structopt(long, conflicts_with = "text")]
+        regex: bool,
+        /// Search for the pattern as hex bytes, like `DE AD BE EF`
+        #[structopt(long, conflicts_with_all = &["text", "regex"])]
+        hex: bool,
+        /// Search yaz0 and zstd compressed entries after decompressing them. Offsets are into the
+        /// decompressed data
+        #[structopt(long)]
+        decompress_inner: bool,
+        in_file: PathBuf,
+        pattern: String,
+    },
+    /// Find entries with identical data, within an archive or across several, and how much space
+    /// the copies take up
+    DedupReport {
+        /// Show sizes in bytes
+        #[structopt(short, long)]
+        bytes: bool,
+        /// Archives or globs to compare
+        #[structopt(required = true)]
+        inputs: Vec<PathBuf>,
+    },
+    /// Totals by type, extension and size of the entries of archives, to see what takes up the
+    /// space in a mod or a dump
+    Stats {
+        /// Show sizes in bytes
+        #[structopt(short, long)]
+        bytes: bool,
+        /// How many of the biggest archives to list
+        #[structopt(long, default_value = "10")]
+        top: usize,
+        /// Print everything as JSON instead, along with the totals of every archive, to compare
+        /// against later
+        #[structopt(long)]
+        json: bool,
+        /// Archives, globs, or directories to look for archives in
+        #[structopt(required = true)]
+        inputs: Vec<PathBuf>,
+    },
+    /// Write the data of entries to stdout
+    Cat {
+        /// Write MSBT entries as text and BYML and AAMP entries as YAML
+        #[structopt(long)]
+        decode: bool,
+        in_file: PathBuf,
+        #[structopt(required = true)]
+        names: Vec<String>,
+    },
+    /// Print part of an entry as hex and ASCII, like `hexdump -C`
+    Hexdump {
+        /// Where to start, from the start of the entry
+        #[structopt(long, default_value = "0", parse(try_from_str = parse_int))]
+        offset: usize,
+        /// How many bytes to dump, by default everything after the offset
+        #[structopt(long, parse(try_from_str = parse_int))]
+        length: Option<usize>,
+        /// Dump yaz0 and zstd compressed entries after decompressing them. The offset is into the
+        /// decompressed data
+        #[structopt(long)]
+        decompress_inner: bool,
+        in_file: PathBuf,
+        name: String,
+    },
+    /// Run a command for each entry, extracted to a temporary file given to it as `{}`, or as
+    /// the last argument without one
+    Exec {
+        #[structopt(long)]
+        filter: Option<glob::Pattern>,
+        /// Put the entries the command changed back into the archive
+        #[structopt(long)]
+        write_back: bool,
+        /// With --write-back, write the updated archive here instead of over the original
+        #[structopt(short, long, requires = "write-back")]
+        out: Option<PathBuf>,
+        in_file: PathBuf,
+        #[structopt(required = true, last = true)]
+        command: Vec<String>,
+    },
+    /// Replace the data of one entry, keeping the rest of the archive as it is
+    Update {
+        /// Write the updated archive here instead of over the original
+        #[structopt(short, long)]
+        out: Option<PathBuf>,
+        in_file: PathBuf,
+        /// Name of the entry, or `[HASH]` for one without a name
+        name: String,
+        /// File with the new data, `-` for stdin
+        data: PathBuf,
+    },
+    Repair {
+        in_file: PathBuf,
+        out_file: PathBuf,
+    },
+    ConvertEndian {
+        #[structopt(long, possible_values = &["big", "little"])]
+        to: ByteOrder,
+        in_file: PathBuf,
+        out_file: PathBuf,
+    },
+}
+
+#[derive(StructOpt, Debug, Clone)]
+enum IndexCommand {
+    /// Index every archive in a directory, like a game dump
+    Build {
+        dir: PathBuf,
+        #[structopt(long, default_value = "index.db")]
+        out: PathBuf,
+    },
+    /// Find which archives have an entry, by a name glob or a hash like `0x1234ABCD`
+    Query {
+        #[structopt(long, default_value = "index.db")]
+        index: PathBuf,
+        query: String,
+    },
+}
+
+#[derive(StructOpt, Debug, Clone)]
+enum ManifestCommand {
+    /// Write the checksums of every entry as JSON
+    Create {
+        in_file: PathBuf,
+        #[structopt(short, long, default_value = "-")]
+        out: PathBuf,
+    },
+    /// Print every entry that was changed, added or removed since the manifest was made
+    Verify {
+        in_file: PathBuf,
+        manifest: PathBuf,
+    },
+}
+
+#[derive(StructOpt, Debug, Clone)]
+struct ListArgs {
+    #[structopt(short, long)]
+    byte_count: bool,
+    #[structopt(short, long, default_value = "table", possible_values = &["table", "csv", "tsv"])]
+    format: ListFormat,
+    #[structopt(short = "0", long = "null")]
+    null: bool,
+    #[structopt(short, long, possible_values = &["size", "name", "offset"])]
+    sort: Option<SortKey>,
+    #[structopt(short, long)]
+    reverse: bool,
+    #[structopt(long)]
+    filter: Option<glob::Pattern>,
+    #[structopt(long)]
+    sha256: bool,
+    #[structopt(long)]
+    crc32: bool,
+    /// Also show where the data of each entry is: its offset, the alignment of the offset, the
+    /// space it takes up to the next entry, the padding in that space, and its hash
+    #[structopt(short, long)]
+    long: bool,
+    /// Bytes of each entry to preview, as text for entries that look like it and as hex for the
+    /// rest, or 0 to leave the preview out. CSV and TSV always give them in hex, as
+    /// first_bytes_hex.
+    #[structopt(long, default_value = "4")]
+    preview_bytes: usize,
+    /// Only print the names, in the order the data is stored in, for `zip --order-from`
+    #[structopt(long, conflicts_with_all = &["sort", "reverse", "null"])]
+    order: bool,
+    #[structopt(required = true)]
+    in_files: Vec<PathBuf>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ErrorFormat {
+    Text,
+    Json,
+}
+
+impl FromStr for ErrorFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(ErrorFormat::Text),
+            "json" => Ok(ErrorFormat::Json),
+            _ => Err(format!("unknown error format '{}'", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ListFormat {
+    Table,
+    Csv,
+    Tsv,
+}
+
+impl FromStr for ListFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "table" => Ok(ListFormat::Table),
+            "csv" => Ok(ListFormat::Csv),
+            "tsv" => Ok(ListFormat::Tsv),
+            _ => Err(format!("unknown list format '{}'", s)),
+        }
     }
 }
 
@@ -82,304 +583,1134 @@ fn byte_char(byte: &u8) -> char {
     }
 }
 
-fn list(in_file: PathBuf, byte_count: bool) {
-    let sarc = SarcFile::read_from_file(in_file).unwrap();
-    println!("Endian: {}", match sarc.byte_order {
-        Endian::Little => "Little",
-        Endian::Big => "Big"
-    });
-    let mut table = Table::new();
-    let mut total_size = 0;
-    table.set_titles(row![
-        c->"Size", c->"Name", c->"First bytes"
-    ]);
-    table.set_format(
-        FormatBuilder::new()
-            .column_separator(' ')
-            .borders(' ')
-
-            .separators(&[
-                LinePosition::Title
-            ], LineSeparator::new('-', ' ', ' ', ' '))
-            .build()
-    );
-    for file in &sarc.files {
-        let name = file.name.as_ref().map(|n| &**n).unwrap_or("[no name]");
-        let bytes: String = file.data[..4].iter().map(hex).collect();
-        let str_bytes: String = file.data[..4].iter().map(byte_char).collect();
-        let bytes = bytes + " | " + &str_bytes;
-        table.add_row(row![
-            size(file.data.len(), byte_count), name, bytes
-        ]);
-        total_size += file.data.len();
-    }
-    table.add_row(row![
-        "--------", "", "---------------"
-    ]);
-    table.add_row(row![
-        size(total_size, byte_count), "", format!("{} file(s)", sarc.files.len())
-    ]);
-    table.printstd();
+fn file_type(data: &[u8]) -> &'static str {
+    FileType::detect(data).map(FileType::name).unwrap_or("-")
 }
 
-fn endian(big: bool) -> Endian {
-    if big {
-        Endian::Big
+/// Whether data reads as text: UTF-8 without control characters other than whitespace, as far as
+/// the start of it goes
+fn is_text(data: &[u8]) -> bool {
+    let sample = &data[..data.len().min(512)];
+    let text = match std::str::from_utf8(sample) {
+        Ok(text) => text,
+        // a character cut off by the end of the sample
+        Err(err) if err.error_len().is_none() => std::str::from_utf8(&sample[..err.valid_up_to()]).unwrap(),
+        Err(_) => return false,
+    };
+    !text.is_empty()
+        && FileType::detect(data).is_none()
+        && text.chars().all(|c| !c.is_control() || c.is_whitespace())
+}
+
+/// The first `len` bytes of an entry, quoted for text and as hex followed by the printable
+/// characters for anything else
+fn preview(data: &[u8], len: usize) -> String {
+    if data.is_empty() {
+        return String::new()
+    }
+    if is_text(data) {
+        let mut end = data.len().min(len);
+        while std::str::from_utf8(&data[..end]).is_err() {
+            end -= 1;
+        }
+        let text = std::str::from_utf8(&data[..end]).unwrap();
+        return format!("{:?}{}", text, if end < data.len() { "\u{2026}" } else { "" })
+    }
+    let bytes: String = data.iter().take(len).map(hex).collect();
+    let str_bytes: String = data.iter().take(len).map(byte_char).collect();
+    format!("{} | {}", bytes, str_bytes)
+}
+
+fn list(args: ListArgs, terminal: Terminal, dict: Option<&Path>) -> Result<(), ConvertError> {
+    let inputs = batch::expand(&args.in_files)?;
+    let multiple = inputs.len() > 1;
+
+    // delimited output gets a single header and an archive column when listing several archives
+    let mut delimited = if !args.null && args.format != ListFormat::Table {
+        let mut writer = csv::WriterBuilder::new()
+            .delimiter(if args.format == ListFormat::Tsv { b'\t' } else { b',' })
+            .from_writer(std::io::stdout());
+        let archive_title: &[&str] = if multiple { &["archive"] } else { &[] };
+        writer.write_record([
+            archive_title, &["size", "name", "type"], &column_titles(&args, true), &preview_title(&args, "first_bytes_hex"),
+        ].concat())?;
+        Some(writer)
     } else {
-        Endian::Little
+        None
+    };
+
+    let mut first = true;
+    batch::run(&inputs, |in_file| {
+        if multiple && args.format == ListFormat::Table && !args.null {
+            println!("{}{}:", if first { "" } else { "\n" }, in_file.display());
+        }
+        first = false;
+        list_archive(in_file, &args, delimited.as_mut().map(|writer| (writer, multiple)), terminal, dict)
+    })?;
+    if let Some(mut writer) = delimited {
+        writer.flush()?;
     }
+    Ok(())
 }
 
-fn write(sarc: SarcFile, out_file: PathBuf, yaz0: bool, zstd: bool) {
-    if yaz0 {
-        sarc.write_yaz0(&mut fs::File::create(out_file).unwrap()).unwrap()
-    } else if zstd {
-        sarc.write_zstd(&mut fs::File::create(out_file).unwrap()).unwrap();
+fn list_archive(
+    in_file: &Path,
+    args: &ListArgs,
+    delimited: Option<(&mut csv::Writer<std::io::Stdout>, bool)>,
+    terminal: Terminal,
+    dict: Option<&Path>,
+) -> Result<(), ConvertError> {
+    let archive = Archive::open(in_file, dict)?;
+    if args.order {
+        for entry in inspect::entries(&archive, args.filter.as_ref(), Some(SortKey::Offset), false) {
+            println!("{}", entry.display_name());
+        }
+        return Ok(())
+    }
+    let entries = inspect::entries(&archive, args.filter.as_ref(), args.sort, args.reverse);
+
+    let starts = data_starts(&archive);
+    let file_size = archive.layout.file_size as usize;
+    let delimited_columns = delimited.is_some();
+    // checksums are the slow part of listing, so they're computed up front on all cores
+    let columns: Vec<Vec<String>> = entries.par_iter()
+        .map(|entry| {
+            let long = if args.long { long_columns(entry, &starts, file_size, args, delimited_columns) } else { vec![] };
+            [long, checksums(entry.data, args)].concat()
+        })
+        .collect();
+
+    if args.null {
+        list_null(&entries)
+    } else if let Some((writer, with_archive)) = delimited {
+        list_delimited(writer, with_archive.then_some(in_file), &entries, &columns, args)
     } else {
-        sarc.write_to_file(out_file).unwrap();
+        list_table(&archive.layout, &entries, &columns, args, terminal);
+        Ok(())
     }
 }
 
-fn zip(yaz0: bool, zstd: bool, in_dir: PathBuf, out_file: PathBuf, byte_order: Endian) {
-    let pattern = in_dir.to_string_lossy() + "/**/*.*";
-    let dir = glob::glob(&pattern).unwrap();
-    let files = dir.map(|child|{
-        let path = child.unwrap();
-        let name = Some(path.strip_prefix(&in_dir).unwrap().to_string_lossy().replace("\\", "/").into());
-        let data = fs::read(path).unwrap();
+/// Title of the preview column, unless `--preview-bytes 0` leaves it out
+fn preview_title(args: &ListArgs, title: &'static str) -> Vec<&'static str> {
+    (args.preview_bytes > 0).then_some(title).into_iter().collect()
+}
 
-        SarcEntry {
-            name,
-            data
-        }
-    }).collect();
+/// Titles of the columns `--long` and the checksums add after the type
+fn column_titles(args: &ListArgs, delimited: bool) -> Vec<&'static str> {
+    let mut titles = vec![];
+    if args.long && delimited {
+        titles.extend(["offset", "alignment", "aligned_size", "padding", "hash"]);
+    } else if args.long {
+        titles.extend(["Offset", "Alignment", "Aligned size", "Padding", "Hash"]);
+    }
+    if args.crc32 {
+        titles.push(if delimited { "crc32" } else { "CRC32" });
+    }
+    if args.sha256 {
+        titles.push(if delimited { "sha256" } else { "SHA-256" });
+    }
+    titles
+}
+
+/// Where the data of every entry starts, in order, without repeats
+fn data_starts(archive: &Archive) -> Vec<usize> {
+    let mut starts: Vec<usize> = archive.entries().map(|entry| entry.offset).collect();
+    starts.sort_unstable();
+    starts.dedup();
+    starts
+}
 
-    let sarc = SarcFile {
-        byte_order,
-        files
+/// The columns of `--long`. Entries take up the space up to where the data of the next one
+/// starts, or the end of the archive, and whatever of it is past their data is padding.
+fn long_columns(entry: &Entry, starts: &[usize], file_size: usize, args: &ListArgs, delimited: bool) -> Vec<String> {
+    let start = entry.offset;
+    let end = start + entry.data.len();
+    let next = starts.get(starts.partition_point(|&other| other <= start)).copied().unwrap_or(file_size).max(end);
+    let alignment = match start {
+        0 => None,
+        start => Some(1usize << start.trailing_zeros()),
     };
-    
-    write(sarc, out_file, yaz0, zstd);
+    if delimited {
+        vec![
+            start.to_string(),
+            alignment.map(|alignment| alignment.to_string()).unwrap_or_default(),
+            (next - start).to_string(),
+            (next - end).to_string(),
+            format!("{:08X}", entry.hash),
+        ]
+    } else {
+        vec![
+            format!("{:#x}", start),
+            alignment.map_or_else(|| "-".to_owned(), |alignment| format!("{:#x}", alignment)),
+            size(next - start, args.byte_count),
+            size(next - end, args.byte_count),
+            format!("{:08X}", entry.hash),
+        ]
+    }
 }
 
-fn unzip(in_file: PathBuf, out_dir: PathBuf) {
-    let sarc = SarcFile::read_from_file(in_file).unwrap();
-    let mut unk = 0;
-    for file in sarc.files {
-        let name = if let Some(x) = file.name {
-            x
-        } else {
-            println!("WARN: file does not have name");
-            let s = format!("unk{}.bin", unk);
-            unk += 1;
-            s
-        };
+fn checksums(data: &[u8], args: &ListArgs) -> Vec<String> {
+    let mut checksums = vec![];
+    if args.crc32 {
+        checksums.push(crc32(data));
+    }
+    if args.sha256 {
+        checksums.push(sha256(data));
+    }
+    checksums
+}
 
-        let mut path = out_dir.clone();
-        path.extend(std::iter::once(name));
+fn table_row<S: AsRef<str>>(columns: &[S]) -> Row {
+    Row::new(columns.iter().map(|column| Cell::new(column.as_ref())).collect())
+}
 
-        let _ = fs::create_dir_all(path.parent().unwrap());
+fn table_format() -> TableFormat {
+    FormatBuilder::new()
+        .column_separator(' ')
+        .borders(' ')
 
-        fs::write(path, file.data).unwrap();
-    }
+        .separators(&[
+            LinePosition::Title
+        ], LineSeparator::new('-', ' ', ' ', ' '))
+        .build()
 }
 
-fn main() {
-    let args = Args::from_args();
+/// How `list` fits its table to where it's printed
+#[derive(Debug, Clone, Copy)]
+struct Terminal {
+    color: bool,
+    /// Columns of the terminal, unless stdout isn't one
+    width: Option<usize>,
+}
 
-    match args.command {
-        Command::Zip {
-            yaz0, zstd, in_dir, out_file, little_endian: _, big_endian
-        } => {
-            zip(yaz0, zstd, in_dir, out_file, endian(big_endian));
+impl Terminal {
+    /// Colors are left out with `--no-color` or a non-empty `NO_COLOR`, like other tools
+    fn detect(no_color: bool) -> Self {
+        let term = console::Term::stdout();
+        let no_color = no_color || std::env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty());
+        Terminal {
+            color: term.is_term() && !no_color,
+            width: term.size_checked().map(|(_, columns)| columns as usize),
         }
-        Command::Unzip {
-            in_file, out_dir
-        } => {
-            let out_dir = 
-                out_dir.unwrap_or_else(||{
-                    let mut path = in_file.parent().unwrap().to_path_buf();
-                    path.push(in_file.file_stem().unwrap());
-                    path
-                });
-            unzip(
-                in_file,
-                out_dir
-            );
-        }
-        Command::FromZip {
-            yaz0, zstd, in_file, out_file, big_endian, little_endian: _
-        } => {
-            from_zip(yaz0, zstd, in_file, out_file, endian(big_endian));
-        }
-        Command::IntoZip {
-            in_file, out_file
-        } => {
-            to_zip(in_file, out_file);
-        }
-        Command::List { in_file, byte_count } => list(in_file, byte_count),
     }
 }
 
-pub struct SarcConverter;
+/// Style of the name and type of an entry: blue for archives, magenta for other compressed
+/// files and yellow for entries without a name
+fn entry_style(entry: &Entry) -> Option<&'static str> {
+    let is_archive_name = entry.name.is_some_and(|name| archive::is_archive_name(Path::new(name)));
+    match FileType::detect(entry.data) {
+        Some(FileType::Sarc | FileType::U8) => Some("bFb"),
+        Some(FileType::Yaz0 | FileType::Zstd | FileType::Lz4) if is_archive_name => Some("bFb"),
+        Some(FileType::Yaz0 | FileType::Zstd | FileType::Lz4) => Some("Fm"),
+        _ if entry.name.is_none() => Some("Fy"),
+        _ => None,
+    }
+}
+
+/// A name cut down to `width` characters, keeping its start and its end
+fn truncate(name: &str, width: usize) -> String {
+    let len = name.chars().count();
+    if len <= width {
+        return name.to_owned()
+    }
+    let end = (width - 1) * 2 / 3;
+    let start = width - 1 - end;
+    let start: String = name.chars().take(start).collect();
+    let end: String = name.chars().skip(len - end).collect();
+    format!("{}\u{2026}{}", start, end)
+}
 
-fn to_zip(in_file: PathBuf, out_file: PathBuf) {
-    let sarc = SarcFile::read_from_file(in_file).unwrap();
-    let mut zip = ZipWriter::new(File::create(&out_file).unwrap());
+fn list_table(layout: &Layout, entries: &[Entry], columns: &[Vec<String>], args: &ListArgs, terminal: Terminal) {
+    if layout.container != Container::SARC {
+        println!("Format: {}", layout.container.name().to_uppercase());
+    } else {
+        println!("Endian: {}", match layout.byte_order {
+            Endian::Little => "Little",
+            Endian::Big => "Big"
+        });
+        println!("Hash key: {:#x}", layout.hash_key);
+    }
+    let titles: Vec<String> = [&["Size", "Name", "Type"][..], &column_titles(args, false), &preview_title(args, "Preview")].concat()
+        .into_iter()
+        .map(String::from)
+        .collect();
+    let mut rows: Vec<Vec<String>> = entries.iter().zip(columns)
+        .map(|(entry, columns)| {
+            let preview = (args.preview_bytes > 0).then(|| preview(entry.data, args.preview_bytes));
+            [
+                vec![size(entry.data.len(), args.byte_count), entry.display_name().into_owned(), file_type(entry.data).to_owned()],
+                columns.clone(),
+                preview.into_iter().collect(),
+            ].concat()
+        })
+        .collect();
+    // the total size goes under the sizes, and the count of files in the last column
+    let total_size: usize = entries.iter().map(|entry| entry.data.len()).sum();
+    let last = titles.len() - 1;
+    let mut separator = vec![String::new(); titles.len()];
+    separator[0] = "--------".to_owned();
+    separator[last] = "---------------".to_owned();
+    let mut totals = vec![String::new(); titles.len()];
+    totals[0] = size(total_size, args.byte_count);
+    totals[last] = format!("{} file(s)", entries.len());
+    rows.extend([separator, totals]);
 
-    let options = FileOptions::default().compression_method(CompressionMethod::Deflated);
-    for (i, file) in sarc.files.into_iter().enumerate() {
-        zip.start_file(file.name.unwrap_or_else(|| format!("{}.bin", i)), options).unwrap();
-        zip.write(&file.data).unwrap();
+    // names get whatever the other columns leave of the terminal, which are separated by a
+    // space, with one more on either side and a column to spare so lines don't wrap
+    if let Some(width) = terminal.width {
+        let others: usize = (0..titles.len())
+            .filter(|&column| column != 1)
+            .map(|column| rows.iter().chain([&titles]).map(|row| row[column].chars().count()).max().unwrap_or(0) + 1)
+            .sum();
+        let name_width = width.saturating_sub(others + 4).max(12);
+        for row in &mut rows {
+            row[1] = truncate(&row[1], name_width);
+        }
     }
+
+    let mut table = Table::new();
+    table.set_titles(Row::new(titles.iter().map(|title| Cell::new(title).style_spec("c")).collect()));
+    table.set_format(table_format());
+    for (i, row) in rows.iter().enumerate() {
+        let mut row = table_row(row);
+        let style = entries.get(i).filter(|_| terminal.color).and_then(entry_style);
+        if let Some(style) = style {
+            for column in [1, 2] {
+                let cell = row.get_cell(column).map(|cell| cell.get_content()).unwrap_or_default();
+                row.set_cell(Cell::new(&cell).style_spec(style), column).unwrap();
+            }
+        }
+        table.add_row(row);
+    }
+    table.printstd();
+}
+
+// Sizes are always written as byte counts here, and previews as hex whatever the entry is, since
+// these formats are meant for scripts
+fn list_delimited(
+    writer: &mut csv::Writer<std::io::Stdout>,
+    archive: Option<&Path>,
+    entries: &[Entry],
+    columns: &[Vec<String>],
+    args: &ListArgs,
+) -> Result<(), ConvertError> {
+    let archive: Vec<String> = archive.map(|path| path.display().to_string()).into_iter().collect();
+    for (entry, columns) in entries.iter().zip(columns) {
+        writer.write_record([
+            archive.clone(),
+            vec![
+                entry.data.len().to_string(),
+                entry.display_name().into_owned(),
+                FileType::detect(entry.data).map(FileType::name).unwrap_or_default().to_owned(),
+            ],
+            columns.clone(),
+            (args.preview_bytes > 0).then(|| entry.data.iter().take(args.preview_bytes).map(hex).collect()).into_iter().collect(),
+        ].concat())?;
+    }
+    Ok(())
 }
 
-fn from_zip(yaz0: bool, zstd: bool, in_file: PathBuf, out_file: PathBuf, byte_order: Endian) {
-    let mut zip = ZipArchive::new(File::open(in_file).unwrap()).unwrap();
+fn list_null(entries: &[Entry]) -> Result<(), ConvertError> {
+    let stdout = std::io::stdout();
+    let mut stdout = stdout.lock();
+    for name in entries.iter().filter_map(|entry| entry.name) {
+        stdout.write_all(name.as_bytes())?;
+        stdout.write_all(b"\0")?;
+    }
+    stdout.flush()?;
+    Ok(())
+}
 
-    let files = (0..zip.len())
-        .map(|i| {
-            let file = zip.by_index(i).unwrap();
-            let name = Some(file.name().to_owned());
-            let data = file.bytes().collect::<Result<_, _>>().unwrap();
-            SarcEntry {
-                name, data
+fn hash(in_file: PathBuf, with_crc32: bool, dict: Option<&Path>) -> Result<(), ConvertError> {
+    let archive = Archive::open(&in_file, dict)?;
+    let entries: Vec<Entry> = archive.entries().collect();
+    let lines: Vec<String> = entries.par_iter()
+        .map(|entry| {
+            let name = entry.display_name();
+            if with_crc32 {
+                format!("{}  {}  {}", crc32(entry.data), sha256(entry.data), name)
+            } else {
+                format!("{}  {}", sha256(entry.data), name)
             }
         })
-        .collect::<Vec<_>>();
+        .collect();
+    for line in lines {
+        println!("{}", line);
+    }
+    Ok(())
+}
 
-    let sarc = SarcFile {
-        byte_order, files,
+fn cat(in_file: &Path, names: &[String], decode: bool, dict: Option<&Path>) -> Result<(), ConvertError> {
+    let archive = Archive::open(in_file, dict)?;
+    let mut data = vec![];
+    for name in names {
+        let entry = archive.entry(name)
+            .ok_or_else(|| ConvertError::file(&format!("no entry named {}", name)).in_file(in_file))?;
+        if decode {
+            data.extend_from_slice(inspect::decoded(&entry).map_err(|err| err.in_entry(name))?.as_bytes());
+        } else {
+            data.extend_from_slice(entry.data);
+        }
+    }
+    stdio::write(Path::new("-"), &data)
+}
+
+/// Bytes per line of a hex dump
+const HEXDUMP_WIDTH: usize = 16;
+
+fn hexdump_line(offset: usize, bytes: &[u8]) -> String {
+    let mut line = format!("{:08X} ", offset);
+    for i in 0..HEXDUMP_WIDTH {
+        // an extra space halfway through, like `hexdump -C`
+        if i % 8 == 0 {
+            line.push(' ');
+        }
+        match bytes.get(i) {
+            Some(byte) => line.push_str(&format!("{} ", hex(byte))),
+            None => line.push_str("   "),
+        }
+    }
+    let text: String = bytes.iter().map(byte_char).collect();
+    format!("{} |{}|", line, text)
+}
+
+fn hexdump(
+    in_file: &Path, name: &str, offset: usize, length: Option<usize>, decompress_inner: bool, dict: Option<&Path>
+) -> Result<(), ConvertError> {
+    let archive = Archive::open(in_file, dict)?;
+    let entry = archive.entry(name)
+        .ok_or_else(|| ConvertError::file(&format!("no entry named {}", name)).in_file(in_file))?;
+    let decompressed;
+    let data = if decompress_inner && Compression::detect(entry.data) != Compression::None {
+        decompressed = compression::decompress(entry.data, &archive.dictionaries).map_err(|err| err.in_entry(name))?;
+        &decompressed[..]
+    } else {
+        entry.data
+    };
+    if offset > data.len() {
+        return Err(ConvertError::param(&format!(
+            "offset {:#x} is past the end of {}, which is {:#x} bytes", offset, name, data.len()
+        )))
+    }
+    let end = length.map_or(data.len(), |length| offset.saturating_add(length).min(data.len()));
+
+    let stdout = std::io::stdout();
+    let mut stdout = stdout.lock();
+    for (i, bytes) in data[offset..end].chunks(HEXDUMP_WIDTH).enumerate() {
+        writeln!(stdout, "{}", hexdump_line(offset + i * HEXDUMP_WIDTH, bytes))?;
+    }
+    stdout.flush()?;
+    Ok(())
+}
+
+fn analyze(
+    input: &Path, scan: &ScanArgs, yaz0_levels: &[usize], zstd_levels: &[i32], byte_count: bool, dict: Option<&Path>
+) -> Result<(), ConvertError> {
+    let zstd_range = zstd::compression_level_range();
+    if let Some(level) = zstd_levels.iter().find(|level| !zstd_range.contains(level)) {
+        return Err(ConvertError::param(&format!(
+            "zstd levels go from {} to {}, not {}", zstd_range.start(), zstd_range.end(), level
+        )))
+    }
+
+    let sarc = if input.is_dir() {
+        let files = scan.files(input)?.into_iter()
+            .filter(|file| file.name != METADATA_FILE)
+            .map(|file| Ok(WriterEntry::named(file.name, pack::entry_data(&file.path, None)?, DEFAULT_HASH_KEY)))
+            .collect::<Result<Vec<_>, ConvertError>>()?;
+        let mut sarc = SarcWriter::new(Endian::Little, files);
+        Alignment::new(None, None).apply(&mut sarc);
+        sarc
+    } else {
+        Archive::open(input, dict)?.to_writer()
     };
+    let mut data = Vec::with_capacity(sarc.file_size());
+    sarc.write(&mut data)?;
+
+    let mut strategies = vec![("none".to_owned(), Compression::None, CompressionOptions::default())];
+    strategies.extend(yaz0_levels.iter().map(|&yaz0_level| (
+        format!("yaz0 level {}", yaz0_level), Compression::Yaz0, CompressionOptions { yaz0_level, ..Default::default() }
+    )));
+    strategies.extend(zstd_levels.iter().map(|&zstd_level| (
+        format!("zstd level {}", zstd_level), Compression::Zstd, CompressionOptions { zstd_level, ..Default::default() }
+    )));
+    strategies.push(("lz4".to_owned(), Compression::Lz4, CompressionOptions::default()));
+
+    let mut table = Table::new();
+    table.set_titles(Row::new(
+        ["Compression", "Size", "Ratio", "Time"].iter()
+            .map(|title| Cell::new(title).style_spec("c"))
+            .collect()
+    ));
+    table.set_format(table_format());
+    // one at a time, so the times aren't skewed by the others running alongside
+    for (label, compression, options) in strategies {
+        log::info!("Compressing with {}", label);
+        let start = Instant::now();
+        let compressed = compression.compress(data.clone(), &options)?;
+        let elapsed = start.elapsed();
+        table.add_row(table_row(&[
+            label,
+            size(compressed.len(), byte_count),
+            format!("{:.1}%", compressed.len() as f64 * 100.0 / data.len() as f64),
+            format!("{:.2}s", elapsed.as_secs_f64()),
+        ]));
+    }
+    println!("{} entries, {} uncompressed", sarc.entries.len(), size(data.len(), byte_count));
+    table.printstd();
+    Ok(())
+}
 
-    write(sarc, out_file, yaz0, zstd);
+fn verify(in_file: &Path, dict: Option<&Path>) -> Result<(), ConvertError> {
+    let problems = inspect::problems(&Archive::open_unchecked(in_file, dict)?);
+    if problems.is_empty() {
+        println!("{}: OK", in_file.display());
+        return Ok(())
+    }
+    for problem in &problems {
+        println!("{}: {}", in_file.display(), problem);
+    }
+    Err(ConvertError::sarc(format!("{}: {} problem(s) found", in_file.display(), problems.len())))
+}
+
+fn verify_manifest(in_file: &Path, manifest: &Path, dict: Option<&Path>) -> Result<(), ConvertError> {
+    let manifest = ChecksumManifest::read(manifest)?;
+    let mismatches = manifest.verify(&Archive::open(in_file, dict)?);
+    if mismatches.is_empty() {
+        println!("{}: OK, {} entries match", in_file.display(), manifest.entries.len());
+        return Ok(())
+    }
+    for mismatch in &mismatches {
+        println!("{}: {}", in_file.display(), mismatch);
+    }
+    Err(ConvertError::sarc(format!(
+        "{}: {} entries don't match {}", in_file.display(), mismatches.len(), manifest.archive
+    )))
 }
 
-use std::fmt;
+/// Differing regions printed for each archive, the rest are only counted
+const ROUNDTRIP_REGIONS: usize = 20;
 
-pub struct ConvertError {
-    pub message: String,
-    pub kind: ConvertErrorKind,
+fn roundtrip(pack: &PackArgs, config: &Config, in_file: &Path, dict: Option<&Path>) -> Result<(), ConvertError> {
+    let result = convert::roundtrip(pack, config, in_file, dict)?;
+    if result.identical {
+        println!("{}: identical", in_file.display());
+        return Ok(())
+    }
+    if result.archive_identical {
+        // a game only ever sees the decompressed archive
+        println!(
+            "{}: identical once decompressed, but the {:?} compression is not ({} bytes instead of {})",
+            in_file.display(), result.compression, result.sizes.1, result.sizes.0
+        );
+        return Ok(())
+    }
+    for (range, label) in result.regions.iter().take(ROUNDTRIP_REGIONS) {
+        println!("{}: {:#x}..{:#x} differs ({})", in_file.display(), range.start, range.end, label);
+    }
+    if result.regions.len() > ROUNDTRIP_REGIONS {
+        println!("{}: and {} more regions", in_file.display(), result.regions.len() - ROUNDTRIP_REGIONS);
+    }
+    for entry in &result.entries {
+        println!("{}: {}", in_file.display(), entry);
+    }
+    Err(ConvertError::sarc(format!("{}: not the same after a round trip", in_file.display())))
 }
 
-impl fmt::Debug for ConvertError {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "ConvertError '{:?}', message: \"{}\"", self.kind, self.message)
+fn repair(in_file: &Path, out_file: &Path, dict: Option<&Path>) -> Result<(), ConvertError> {
+    let fixes = convert::repair(in_file, out_file, dict)?;
+    // the report can't share stdout with the repaired archive
+    let report = |line: &str| if stdio::is_stdio(out_file) { eprintln!("{}", line) } else { println!("{}", line) };
+    if fixes.is_empty() {
+        report("No problems found");
+    }
+    for fix in fixes {
+        report(&fix);
     }
+    Ok(())
 }
 
-#[derive(Debug)]
-pub enum ConvertErrorKind {
-    Param,
-    Nus3audio,
-    Msc,
-    File,
-    HandleNone,
-    YamlError,
-    Utf8Error,
-    ParseIntError,
-    MessageFormat,
-    WaveError,
-    SarcError,
-    ZipError,
-    Byml,
-    Yaz0Error
+fn diff(in_file: &Path, against: &Path, export: Option<&Path>, dict: Option<&Path>) -> Result<(), ConvertError> {
+    let vanilla_path = inspect::vanilla_archive(against, in_file)?;
+    log::info!("Comparing to {}", vanilla_path.display());
+    let vanilla = Archive::open(&vanilla_path, dict)?;
+    let modded = Archive::open(in_file, dict)?;
+    let changes = inspect::diff(&modded, &vanilla);
+    for (change, name) in &changes {
+        println!("{}  {}", change.letter(), name);
+    }
+
+    if let Some(out_dir) = export {
+        let changed: HashSet<&str> = changes.iter()
+            .filter(|(change, _)| *change != inspect::Change::Deleted)
+            .map(|(_, name)| name.as_str())
+            .collect();
+        inspect::export(modded.entries().filter(|entry| changed.contains(entry.display_name().as_ref())), out_dir)?;
+    }
+    Ok(())
 }
 
-impl ConvertError {
-    pub fn param(message: &str) -> ConvertError {
-        ConvertError {
-            message: message.to_string(),
-            kind: ConvertErrorKind::Param
+fn find(dir: &Path, pattern: &glob::Pattern, dict: Option<&Path>) -> Result<(), ConvertError> {
+    for (path, names) in inspect::find(dir, pattern, dict)? {
+        for name in names {
+            println!("{}: {}", path.display(), name);
         }
     }
+    Ok(())
+}
 
-    pub fn nus3audio(message: &str) -> ConvertError {
-        ConvertError {
-            message: message.to_string(),
-            kind: ConvertErrorKind::Nus3audio
+fn grep(in_file: &Path, pattern: &Pattern, decompress_inner: bool, dict: Option<&Path>) -> Result<(), ConvertError> {
+    let archive = Archive::open(in_file, dict)?;
+    for (entry, offsets) in inspect::grep(&archive, pattern, decompress_inner)? {
+        for offset in offsets {
+            println!("{}: {:#x}", entry.display_name(), offset);
         }
     }
+    Ok(())
+}
 
-    pub fn file(message: &str) -> ConvertError {
-        ConvertError {
-            message: message.to_string(),
-            kind: ConvertErrorKind::File
+fn dedup_report(inputs: &[PathBuf], byte_count: bool, dict: Option<&Path>) -> Result<(), ConvertError> {
+    let archives = inputs.iter()
+        .map(|in_file| Archive::open(in_file, dict))
+        .collect::<Result<Vec<_>, _>>()?;
+    // only say which archive an entry is from when there's more than one
+    let entries: Vec<(String, &[u8])> = inputs.iter().zip(&archives)
+        .flat_map(|(in_file, archive)| archive.entries().map(move |entry| {
+            let name = if inputs.len() > 1 {
+                format!("{}:{}", in_file.display(), entry.display_name())
+            } else {
+                entry.display_name().into_owned()
+            };
+            (name, entry.data)
+        }))
+        .collect();
+    let groups = inspect::duplicates(&entries);
+
+    if groups.is_empty() {
+        println!("No duplicate entries");
+        return Ok(())
+    }
+    for group in &groups {
+        println!(
+            "{} copies of {} ({} wasted):",
+            group.names.len(), size(group.size, byte_count), size(group.wasted(), byte_count)
+        );
+        for name in &group.names {
+            println!("    {}", name);
         }
     }
+    let total: usize = groups.iter().map(inspect::Duplicates::wasted).sum();
+    println!(
+        "{} group(s) of duplicates, {} wasted in total",
+        groups.len(), size(total, byte_count)
+    );
+    Ok(())
+}
+
+/// A table of totals by what's in the first column, the biggest first
+fn totals_table<'a>(title: &str, totals: impl Iterator<Item = (&'a String, &'a inspect::Total)>, all: u64, byte_count: bool) {
+    let mut totals: Vec<_> = totals.collect();
+    totals.sort_by_key(|(_, total)| std::cmp::Reverse(total.size));
+    let mut table = Table::new();
+    table.set_titles(Row::new([title, "Entries", "Size", "Share"].iter().map(|title| Cell::new(title).style_spec("c")).collect()));
+    table.set_format(table_format());
+    for (name, total) in totals {
+        let share = if all == 0 { 0.0 } else { total.size as f64 * 100.0 / all as f64 };
+        table.add_row(table_row(&[
+            name.clone(), total.count.to_string(), size(total.size as usize, byte_count), format!("{:.1}%", share),
+        ]));
+    }
+    table.printstd();
+}
+
+fn stats(inputs: &[PathBuf], byte_count: bool, top: usize, json: bool, dict: Option<&Path>) -> Result<(), ConvertError> {
+    let stats = inspect::stats(inputs, dict)?;
+    if json {
+        let json = serde_json::to_string_pretty(&stats).map_err(|err| ConvertError::file(&err.to_string()))?;
+        println!("{}", json);
+        return Ok(())
+    }
 
-    pub fn msc(message: &str) -> ConvertError {
-        ConvertError {
-            message: message.to_string(),
-            kind: ConvertErrorKind::Msc
+    let on_disk: u64 = stats.archives.iter().map(|archive| archive.file_size).sum();
+    println!(
+        "{} archive(s), {} entries taking up {} ({} on disk)\n", stats.archives.len(), stats.entries.count,
+        size(stats.entries.size as usize, byte_count), size(on_disk as usize, byte_count)
+    );
+    totals_table("Type", stats.by_type.iter(), stats.entries.size, byte_count);
+    println!();
+    totals_table("Extension", stats.by_extension.iter(), stats.entries.size, byte_count);
+    println!();
+
+    let mut table = Table::new();
+    table.set_titles(Row::new(["Size", "Entries", "Total", ""].iter().map(|title| Cell::new(title).style_spec("c")).collect()));
+    table.set_format(table_format());
+    let most = stats.by_size.iter().map(|total| total.count).max().unwrap_or(0).max(1);
+    for (i, total) in stats.by_size.iter().enumerate() {
+        let bucket = match inspect::SIZE_BUCKETS.get(i) {
+            Some(&bound) => format!("< {}", size(bound as usize, byte_count)),
+            None => format!(">= {}", size(inspect::SIZE_BUCKETS[i - 1] as usize, byte_count)),
+        };
+        table.add_row(table_row(&[
+            bucket, total.count.to_string(), size(total.size as usize, byte_count), "#".repeat(total.count * 30 / most),
+        ]));
+    }
+    table.printstd();
+
+    if top > 0 && !stats.archives.is_empty() {
+        println!();
+        let mut archives: Vec<_> = stats.archives.iter().collect();
+        archives.sort_by_key(|archive| std::cmp::Reverse(archive.entries.size));
+        let mut table = Table::new();
+        table.set_titles(Row::new(["Size", "Entries", "Archive"].iter().map(|title| Cell::new(title).style_spec("c")).collect()));
+        table.set_format(table_format());
+        for archive in archives.into_iter().take(top) {
+            table.add_row(table_row(&[
+                size(archive.entries.size as usize, byte_count), archive.entries.count.to_string(), archive.path.display().to_string(),
+            ]));
         }
+        table.printstd();
     }
+    Ok(())
+}
 
-    pub fn message_format(message: &str) -> ConvertError {
-        ConvertError {
-            message: message.to_string(),
-            kind: ConvertErrorKind::MessageFormat
+fn main() {
+    let raw_args: Vec<OsString> = std::env::args_os().collect();
+    let args = match Args::from_iter_safe(&raw_args).or_else(|err| bare_paths(&raw_args).ok_or(err)) {
+        Ok(args) => args,
+        Err(err) if err.use_stderr() => {
+            // the arguments couldn't be parsed, so --error-format is looked for by hand
+            let json = raw_args.iter().any(|arg| arg == "--error-format=json")
+                || raw_args.windows(2).any(|args| args[0] == "--error-format" && args[1] == "json");
+            if json {
+                logger::init(0, false, false, true);
+                logger::error(&ConvertError::param(err.message.trim_start_matches("error: ")));
+            } else {
+                eprintln!("{}", err.message);
+            }
+            std::process::exit(ConvertErrorKind::Param.exit_code());
         }
+        Err(err) => err.exit(),
+    };
+    logger::init(args.verbose, args.quiet, args.log_json, args.error_format == ErrorFormat::Json);
+    progress::init(!args.quiet && !args.log_json);
+    if args.no_color {
+        console::set_colors_enabled(false);
+        console::set_colors_enabled_stderr(false);
     }
+    if let Some(threads) = args.threads {
+        if let Err(err) = rayon::ThreadPoolBuilder::new().num_threads(threads).build_global() {
+            log::warn!("Couldn't limit the threads used: {}", err);
+        }
+    }
+    stdio::set_dry_run(args.dry_run);
+    stdio::set_existing(existing(args.force, args.skip_existing));
+    stdio::set_reproducible(args.reproducible);
 
-    pub fn byml<S: AsRef<str>>(message: S) -> ConvertError {
-        ConvertError {
-            message: message.as_ref().to_string(),
-            kind: ConvertErrorKind::Byml,
+    let dropped = matches!(args.command, Command::Open { .. });
+    if let Err(err) = run(args) {
+        logger::error(&err);
+        if dropped {
+            wait_for_enter();
         }
+        std::process::exit(err.kind.exit_code());
     }
 }
 
-impl std::convert::From<std::io::Error> for ConvertError {
-    fn from(err: std::io::Error) -> Self {
-        ConvertError {
-            message: format!("{:?}", err),
-            kind: ConvertErrorKind::File,
-        }
+/// Keep the console Windows opens for files dropped onto the executable from closing before the
+/// error can be read
+fn wait_for_enter() {
+    use std::io::IsTerminal;
+
+    if cfg!(windows) && std::io::stdin().is_terminal() {
+        eprintln!("Press Enter to close");
+        let _ = std::io::stdin().read_line(&mut String::new());
     }
 }
 
-impl std::convert::From<std::str::Utf8Error> for ConvertError {
-    fn from(err: std::str::Utf8Error) -> Self {
-        ConvertError {
-            message: format!("{:?}", err),
-            kind: ConvertErrorKind::Utf8Error,
-        }
+/// Paths given without a command, after any flags, as when they're dropped onto the executable
+fn bare_paths(raw_args: &[OsString]) -> Option<Args> {
+    let start = raw_args.iter().skip(1).position(|arg| !arg.to_string_lossy().starts_with('-'))? + 1;
+    if !raw_args[start..].iter().all(|arg| Path::new(arg).exists()) {
+        return None
     }
+    let (flags, paths) = raw_args.split_at(start);
+    Args::from_iter_safe(flags.iter().cloned().chain(std::iter::once("open".into())).chain(paths.iter().cloned())).ok()
 }
 
-impl std::convert::From<std::num::ParseIntError> for ConvertError {
-    fn from(err: std::num::ParseIntError) -> Self {
-        ConvertError {
-            message: format!("{:?}", err),
-            kind: ConvertErrorKind::ParseIntError,
-        }
+fn existing(force: bool, skip_existing: bool) -> Existing {
+    match (force, skip_existing) {
+        (true, _) => Existing::Overwrite,
+        (_, true) => Existing::Skip,
+        _ => Existing::Refuse,
     }
 }
 
-impl std::convert::From<sarc::parser::Error> for ConvertError {
-    fn from(err: sarc::parser::Error) -> Self {
-        ConvertError {
-            message: format!("SarcParseError: {:?}", err),
-            kind: ConvertErrorKind::SarcError
+/// Run every step of a script in the directory it's in. Steps get the options `run` was given
+/// that are about outputs on top of their own, and its config, dictionaries and name map unless
+/// they have their own, but logging and threads are only set up once.
+fn run_script(path: &Path, args: &Args) -> Result<(), ConvertError> {
+    let Args { dry_run, force, skip_existing, quiet, reproducible, .. } = *args;
+    let script = script::Script::load(path)?;
+    let steps = script.steps(path)?;
+    // paths given to `run` are relative to where it was run, not to the script
+    let absolute = |path: &Option<PathBuf>| {
+        path.as_deref().map(|path| std::path::absolute(path).map_err(|err| ConvertError::io(path, err))).transpose()
+    };
+    let (config, dict, name_map) = (absolute(&args.config)?, absolute(&args.dict)?, absolute(&args.name_map)?);
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        std::env::set_current_dir(dir).map_err(|err| ConvertError::io(dir, err))?;
+    }
+    for (i, step) in steps.iter().enumerate() {
+        let mut args = Args::from_iter_safe(std::iter::once("sarc").chain(step.iter().map(String::as_str)))
+            .map_err(|err| {
+                // only the error, without the usage and help that follow it
+                let message = err.message.lines()
+                    .take_while(|line| !line.starts_with("USAGE:"))
+                    .map(str::trim)
+                    .filter(|line| !line.is_empty())
+                    .collect::<Vec<_>>()
+                    .join(" ");
+                ConvertError::param(&format!("step {}: {}", i + 1, message.trim_start_matches("error: ")))
+            })?;
+        if let Command::Run { .. } = args.command {
+            return Err(ConvertError::param(&format!("step {}: scripts can't run other scripts", i + 1)))
         }
+        if !quiet {
+            progress::suspend(|| println!(
+                "[{}/{}] sarc {}", i + 1, steps.len(), shlex::try_join(step.iter().map(String::as_str)).unwrap_or_else(|_| step.join(" "))
+            ));
+        }
+        args.config = args.config.or_else(|| config.clone());
+        args.dict = args.dict.or_else(|| dict.clone());
+        args.name_map = args.name_map.or_else(|| name_map.clone());
+        stdio::set_dry_run(dry_run || args.dry_run);
+        stdio::set_reproducible(reproducible || args.reproducible);
+        stdio::set_existing(existing(force || args.force, skip_existing || args.skip_existing));
+        run(args)?;
     }
+    Ok(())
+}
+
+/// Extract an archive next to itself, or pack a directory extracted from one back into the
+/// archive it came from, with the layout it was extracted with. Either one writes over what's
+/// there without --force, since the point of dropping them is to update it.
+fn open(path: &Path) -> Result<(), ConvertError> {
+    let args: Vec<OsString> = if path.is_dir() {
+        let metadata = ArchiveMetadata::find(path, false, false)?.ok_or_else(|| {
+            ConvertError::param("wasn't extracted by sarctool, pack it with `sarc zip` instead").in_file(path)
+        })?;
+        // only the name, so the metadata can't point anywhere but next to the directory
+        let name = metadata.archive.as_deref().and_then(|name| Path::new(name).file_name()).ok_or_else(|| {
+            ConvertError::param("doesn't say which archive it was extracted from, pack it with `sarc zip` instead").in_file(path)
+        })?;
+        vec!["zip".into(), path.into(), in_place(&path.with_file_name(name)).into()]
+    } else {
+        in_place(path);
+        vec!["unzip".into(), path.into()]
+    };
+    let args = Args::from_iter_safe(std::iter::once("sarc".into()).chain(args)).map_err(|err| ConvertError::param(&err.message))?;
+    run(args)
 }
 
-impl std::convert::From<sarc::writer::Error> for ConvertError {
-    fn from(err: sarc::writer::Error) -> Self {
-        ConvertError {
-            message: format!("SarcWriteError: {:?}", err),
-            kind: ConvertErrorKind::SarcError
+/// An archive to write over, which doesn't need --force since replacing it is the point
+fn in_place(in_file: &Path) -> PathBuf {
+    if stdio::existing() == Existing::Refuse {
+        stdio::set_existing(Existing::Overwrite);
+    }
+    in_file.to_owned()
+}
+
+/// The old `command <input> <output>` form, told apart from two inputs by whether the second path
+/// looks like an output
+fn single_output(inputs: &mut Vec<PathBuf>, out_root: Option<&Path>, is_output: impl Fn(&Path) -> bool) -> Option<PathBuf> {
+    if out_root.is_none() && inputs.len() == 2 && is_output(&inputs[1]) {
+        inputs.pop()
+    } else {
+        None
+    }
+}
+
+fn run(mut args: Args) -> Result<(), ConvertError> {
+    if let Some(path) = &args.name_map {
+        name_map::init(NameMap::load(path)?);
+    }
+    let config = Config::load(args.config.as_deref())?;
+    // manifests and metadata go between the command line and the config, so zip and from-zip
+    // apply the config themselves once they've loaded them
+    match &mut args.command {
+        Command::FromTar { pack, .. } | Command::FromSevenZ { pack, .. } => pack.apply_config(&config),
+        // the byte order and layout of the config are for new archives, not ones being rewritten
+        Command::Convert { pack, .. } | Command::Recompress { pack, .. } => pack.apply_compression_config(&config),
+        _ => (),
+    }
+    let dict = args.dict.as_deref().or(config.dict.as_deref());
+
+    match args.command {
+        Command::Zip {
+            out_file, watch: true, ..
+        } if stdio::is_stdio(&out_file) => {
+            Err(ConvertError::param("--watch can't write to stdout"))
+        }
+        Command::Zip {
+            pack, scan, in_dir, out_file, preserve, ignore_metadata, manifest, watch, update
+        } => {
+            cache::init(update.then(|| in_dir.join(cache::CACHE_DIR)).as_deref())?;
+            let build = |cache: Option<&FileCache>| {
+                match &manifest {
+                    Some(manifest) => pack::pack_manifest(&pack, &config, manifest, &in_dir, &out_file, dict, cache),
+                    None => {
+                        let metadata = ArchiveMetadata::find(&in_dir, preserve, ignore_metadata)?;
+                        let mut pack = pack.clone();
+                        if let Some(metadata) = &metadata {
+                            pack.apply_metadata(metadata);
+                        }
+                        pack.apply_config(&config);
+                        pack::pack_dir(&pack, &scan, &in_dir, &out_file, metadata.as_ref(), dict, cache)
+                    }
+                }?;
+                // only after a build that went through, which used everything that's still needed
+                cache::prune();
+                Ok(())
+            };
+            if !watch {
+                return build(None)
+            }
+            let cache = FileCache::default();
+            build(Some(&cache))?;
+            // the archive is rebuilt over the one just written
+            stdio::set_existing(Existing::Overwrite);
+            watch::watch(&in_dir, &out_file, || build(Some(&cache)))
+        }
+        Command::Repack { pack, scan, original, in_dir, out_file } => {
+            pack::repack(&pack, &config, &scan, &original, &in_dir, &out_file, dict)
+        }
+        Command::Unzip {
+            mut inputs, out_root, preserve, no_metadata, byml_to_yaml, aamp_to_yaml, msbt_to_text, decompress_inner, flatten,
+            min_size, max_size, magic,
+        } => {
+            let options = ExtractOptions {
+                write_metadata: preserve || !no_metadata,
+                to_yaml: [(byml_to_yaml, YamlFormat::Byml), (aamp_to_yaml, YamlFormat::Aamp)].iter()
+                    .filter(|(enabled, _)| *enabled)
+                    .map(|&(_, format)| format)
+                    .collect(),
+                msbt_to_text,
+                decompress_inner,
+                flatten,
+                filter: EntryFilter { min_size, max_size, magic },
+            };
+            let out_dir = single_output(&mut inputs, out_root.as_deref(), |path| !path.is_file());
+            let inputs = batch::expand(&inputs)?;
+            batch::run_parallel(&inputs, |in_file| {
+                let out_dir = match &out_dir {
+                    Some(out_dir) => out_dir.clone(),
+                    None => batch::output_path(in_file, out_root.as_deref(), None)?,
+                };
+                extract::unzip(in_file, &out_dir, &options, dict)
+            })
+        }
+        Command::FromZip {
+            pack, in_file, out_file
+        } => {
+            convert::from_zip(&pack, &config, &in_file, &out_file, dict)
+        }
+        Command::IntoZip {
+            mut inputs, out_root, method, level, no_metadata
+        } => {
+            let options = method.options(level)?;
+            let out_file = single_output(&mut inputs, out_root.as_deref(), |path| {
+                !path.is_file() || path.extension().is_some_and(|ext| ext == "zip")
+            });
+            if let Some(out_root) = &out_root {
+                stdio::create_dir_all(out_root)?;
+            }
+            let inputs = batch::expand(&inputs)?;
+            batch::run_parallel(&inputs, |in_file| {
+                let out_file = match &out_file {
+                    Some(out_file) => out_file.clone(),
+                    None => batch::output_path(in_file, out_root.as_deref(), Some("zip"))?,
+                };
+                convert::to_zip(in_file, &out_file, options, !no_metadata, dict)
+            })
+        }
+        Command::FromTar {
+            pack, in_file, out_file
+        } => {
+            convert::from_tar(&pack, &in_file, &out_file, dict)
+        }
+        Command::IntoTar {
+            mut inputs, out_root, compression
+        } => {
+            let out_file = single_output(&mut inputs, out_root.as_deref(), |path| {
+                !path.is_file() || TarCompression::from_extension(path).is_some()
+            });
+            if let Some(out_root) = &out_root {
+                stdio::create_dir_all(out_root)?;
+            }
+            let inputs = batch::expand(&inputs)?;
+            batch::run_parallel(&inputs, |in_file| {
+                let out_file = match &out_file {
+                    Some(out_file) => out_file.clone(),
+                    None => {
+                        let extension = compression.unwrap_or(TarCompression::None).extension();
+                        batch::output_path(in_file, out_root.as_deref(), Some(extension))?
+                    }
+                };
+                let compression = compression
+                    .or_else(|| TarCompression::from_extension(&out_file))
+                    .unwrap_or(TarCompression::None);
+                convert::to_tar(in_file, &out_file, compression, dict)
+            })
+        }
+        Command::FromSevenZ {
+            pack, in_file, out_file
+        } => {
+            convert::from_7z(&pack, &in_file, &out_file, dict)
+        }
+        Command::IntoSevenZ {
+            mut inputs, out_root
+        } => {
+            let out_file = single_output(&mut inputs, out_root.as_deref(), |path| {
+                !path.is_file() || path.extension().is_some_and(|ext| ext == "7z")
+            });
+            if let Some(out_root) = &out_root {
+                stdio::create_dir_all(out_root)?;
+            }
+            let inputs = batch::expand(&inputs)?;
+            batch::run_parallel(&inputs, |in_file| {
+                let out_file = match &out_file {
+                    Some(out_file) => out_file.clone(),
+                    None => batch::output_path(in_file, out_root.as_deref(), Some("7z"))?,
+                };
+                convert::to_7z(in_file, &out_file, dict)
+            })
+        }
+        Command::Convert {
+            pack, out_root, inputs
+        } => {
+            stdio::create_dir_all(&out_root)?;
+            let inputs = batch::expand(&inputs)?;
+            batch::run_parallel(&inputs, |in_file| {
+                let out_file = out_root.join(in_file.file_name().unwrap_or_default());
+                convert::convert(&pack, in_file, &out_file, dict)
+            })
+        }
+        Command::Roundtrip { pack, inputs } => {
+            let inputs = batch::expand(&inputs)?;
+            batch::run(&inputs, |in_file| roundtrip(&pack, &config, in_file, dict))
+        }
+        Command::Verify { inputs } => {
+            let inputs = batch::expand(&inputs)?;
+            batch::run(&inputs, |in_file| verify(in_file, dict))
         }
+        Command::Analyze { yaz0_levels, zstd_levels, bytes, scan, input } => {
+            analyze(&input, &scan, &yaz0_levels, &zstd_levels, bytes, dict)
+        }
+        Command::Diff { against, export, in_file } => diff(&in_file, &against, export.as_deref(), dict),
+        Command::Run { ref script } => run_script(script, &args),
+        Command::Open { paths } => batch::run(&paths, open),
+        Command::MergeMods { base, out, mods } => convert::merge_mods(&base, &mods, &out, dict),
+        #[cfg(unix)]
+        Command::Mount { nested, in_file, mount_point } => mount::mount(&in_file, &mount_point, nested, dict),
+        Command::Find { dir, pattern } => find(&dir, &pattern, dict),
+        Command::Index(IndexCommand::Build { dir, out }) => {
+            if !dir.is_dir() {
+                return Err(ConvertError::file("not a directory").in_file(&dir))
+            }
+            // queries may be run from anywhere
+            let dir = fs::canonicalize(&dir).map_err(|err| ConvertError::io(&dir, err))?;
+            let index = Index::build(&dir, &Dictionaries::load_in(dict, &dir)?);
+            index.write(&out)?;
+            log::info!("Indexed {} entries of {} archives into {}", index.entry_count(), index.archives.len(), out.display());
+            Ok(())
+        }
+        Command::Index(IndexCommand::Query { index, query }) => {
+            let index = Index::read(&index)?;
+            let query = index::Query::parse(&query)?;
+            for (archive, entry) in index.query(&query) {
+                let name = entry.name.clone().unwrap_or_else(|| format!("[{:08X}]", entry.hash));
+                println!("{}: {}", index.root.join(&archive.path).display(), name);
+            }
+            Ok(())
+        }
+        Command::Manifest(ManifestCommand::Create { in_file, out }) => {
+            let archive = Archive::open(&in_file, dict)?;
+            let name = in_file.file_name().unwrap_or_default().to_string_lossy();
+            ChecksumManifest::create(&archive, &name).write(&out)
+        }
+        Command::Manifest(ManifestCommand::Verify { in_file, manifest }) => verify_manifest(&in_file, &manifest, dict),
+        Command::Grep { text, regex, hex, decompress_inner, in_file, pattern } => {
+            let kind = match (text, regex, hex) {
+                (false, true, _) => PatternKind::Regex,
+                (false, false, true) => PatternKind::Hex,
+                _ => PatternKind::Text,
+            };
+            grep(&in_file, &Pattern::new(&pattern, kind)?, decompress_inner, dict)
+        }
+        Command::Stats { bytes, top, json, inputs } => stats(&batch::expand(&inputs)?, bytes, top, json, dict),
+        Command::DedupReport { bytes, inputs } => dedup_report(&batch::expand(&inputs)?, bytes, dict),
+        Command::List(list_args) => list(list_args, Terminal::detect(args.no_color), dict),
+        Command::Hash { crc32, in_file } => hash(in_file, crc32, dict),
+        Command::Cat { decode, in_file, names } => cat(&in_file, &names, decode, dict),
+        Command::Hexdump { offset, length, decompress_inner, in_file, name } => {
+            hexdump(&in_file, &name, offset, length, decompress_inner, dict)
+        }
+        Command::Exec { filter, write_back, out, in_file, command } => {
+            let out_file = write_back.then(|| out.unwrap_or_else(|| in_place(&in_file)));
+            exec::exec(&in_file, filter.as_ref(), &command, out_file.as_deref(), dict)
+        }
+        Command::Recompress { pack, in_file, out_file } => convert::recompress(&pack, &in_file, &out_file, dict),
+        Command::Update { out, in_file, name, data } => {
+            let data = stdio::read(&data)?;
+            let out_file = out.unwrap_or_else(|| in_place(&in_file));
+            convert::update(&in_file, &name, data, &out_file, dict)
+        }
+        Command::Repair { in_file, out_file } => repair(&in_file, &out_file, dict),
+        Command::ConvertEndian { to, in_file, out_file } => convert::convert_endian(&in_file, &out_file, to, dict),
     }
 }
 
-impl std::convert::From<zip::result::ZipError> for ConvertError {
-    fn from(err: zip::result::ZipError) -> Self {
-        ConvertError {
-            message: format!("ZipError: {:?}", err),
-            kind: ConvertErrorKind::ZipError
+pub struct SarcConverter;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Puts back what's done with outputs that exist when dropped, even if the test fails
+    struct RestoreExisting(Existing);
+
+    impl Drop for RestoreExisting {
+        fn drop(&mut self) {
+            stdio::set_existing(self.0);
         }
     }
+
+    #[test]
+    fn dropped_archives_round_trip() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let dir = temp_dir.path();
+        let archive = dir.join("Foo.sarc");
+        let files = vec![
+            WriterEntry::named("a.txt".to_owned(), b"first".to_vec(), DEFAULT_HASH_KEY),
+            WriterEntry::named("sub/b.txt".to_owned(), b"second".to_vec(), DEFAULT_HASH_KEY),
+        ];
+        let mut data = vec![];
+        SarcWriter::new(Endian::Little, files).write(&mut data).unwrap();
+        fs::write(&archive, &data).unwrap();
+
+        // both write over what's already there, as the archive and the directory always are
+        let _restore = RestoreExisting(stdio::existing());
+        stdio::set_existing(Existing::Refuse);
+        open(&archive).unwrap();
+        fs::write(dir.join("Foo/a.txt"), b"edited").unwrap();
+        open(&dir.join("Foo")).unwrap();
+        open(&archive).unwrap();
+
+        let repacked = Archive::open(&archive, None).unwrap();
+        let entries: Vec<(String, Vec<u8>)> = repacked.entries()
+            .map(|entry| (entry.display_name().into_owned(), entry.data.to_vec()))
+            .collect();
+        assert_eq!(entries, [("a.txt".to_owned(), b"edited".to_vec()), ("sub/b.txt".to_owned(), b"second".to_vec())]);
+    }
 }
@@ -0,0 +1,88 @@
+//! Manifests for `zip --manifest`, listing exactly what goes into an archive and in what order,
+//! so a pack can be reviewed and rebuilt without depending on what's in the directory
+//!
+//! ```yaml
+//! endian: big
+//! compression: yaz0
+//! alignment: 0x80
+//! entries:
+//!   - source: models/Link.bfres
+//!     name: Model/Link.bfres
+//!     alignment: 0x2000
+//!   - source: Actor/Link.bxml
+//! ```
+//!
+//! Entries are stored in the order they're listed. TOML manifests use the same keys, with
+//! `[[entries]]` tables. BYML and AAMP sources extracted as YAML are converted back, and named without
+//! the `.yml` unless a name is given.
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+
+use crate::compression::Compression;
+use crate::metadata::ByteOrder;
+use crate::yaml::YamlFormat;
+use crate::ConvertError;
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Manifest {
+    #[serde(default)]
+    pub endian: Option<ByteOrder>,
+    #[serde(default)]
+    pub compression: Option<Compression>,
+    /// Alignment of entries that don't set their own
+    #[serde(default)]
+    pub alignment: Option<usize>,
+    pub entries: Vec<ManifestEntry>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct ManifestEntry {
+    /// File to pack, relative to the directory being zipped
+    pub source: PathBuf,
+    /// Name in the archive, the source path if not given
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub alignment: Option<usize>,
+}
+
+impl ManifestEntry {
+    pub fn name(&self) -> String {
+        self.name.clone().unwrap_or_else(|| {
+            let source = self.source.to_string_lossy().replace("\\", "/");
+            YamlFormat::from_yaml_path(&source).map_or(source.as_str(), |(_, name)| name).to_owned()
+        })
+    }
+}
+
+impl Manifest {
+    /// Load a TOML manifest if `path` ends in `.toml`, a YAML one otherwise
+    pub fn load(path: &Path) -> Result<Self, ConvertError> {
+        let error = |message: String| ConvertError::param(&format!("{}: {}", path.display(), message));
+
+        let text = fs::read_to_string(path).map_err(|err| ConvertError::io(path, err))?;
+        let manifest: Manifest = if path.extension().is_some_and(|ext| ext == "toml") {
+            toml::from_str(&text).map_err(|err| error(err.to_string()))?
+        } else {
+            serde_yaml::from_str(&text).map_err(|err| error(err.to_string()))?
+        };
+
+        let alignments = manifest.alignment.iter().chain(manifest.entries.iter().filter_map(|entry| entry.alignment.as_ref()));
+        if let Some(alignment) = alignments.into_iter().find(|alignment| !alignment.is_power_of_two()) {
+            return Err(error(format!("alignment {:#x} is not a power of two", alignment)))
+        }
+        let mut names = HashSet::new();
+        for entry in &manifest.entries {
+            let name = entry.name();
+            if !names.insert(name.clone()) {
+                return Err(error(format!("{} is listed more than once", name)))
+            }
+        }
+        Ok(manifest)
+    }
+}
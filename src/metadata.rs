@@ -0,0 +1,178 @@
+//! Layout information recorded by `unzip` next to the extracted files, so `zip` can reproduce
+//! the original archive byte-for-byte without being told how it was built
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+use sarc::Endian;
+use serde::{Deserialize, Serialize};
+
+use crate::compression::Compression;
+use crate::container::Container;
+use crate::layout::{self, Layout};
+use crate::writer::{SarcWriter, WriterEntry, DEFAULT_ALIGNMENT};
+use crate::{stdio, ConvertError};
+
+pub const METADATA_FILE: &str = ".sarctool.yml";
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ByteOrder {
+    Big,
+    Little,
+}
+
+impl FromStr for ByteOrder {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "big" => Ok(ByteOrder::Big),
+            "little" => Ok(ByteOrder::Little),
+            _ => Err(format!("unknown byte order '{}'", s)),
+        }
+    }
+}
+
+impl fmt::Display for ByteOrder {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            ByteOrder::Big => "big",
+            ByteOrder::Little => "little",
+        })
+    }
+}
+
+impl From<&Endian> for ByteOrder {
+    fn from(endian: &Endian) -> Self {
+        match endian {
+            Endian::Big => ByteOrder::Big,
+            Endian::Little => ByteOrder::Little,
+        }
+    }
+}
+
+impl From<ByteOrder> for Endian {
+    fn from(byte_order: ByteOrder) -> Self {
+        match byte_order {
+            ByteOrder::Big => Endian::Big,
+            ByteOrder::Little => Endian::Little,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ArchiveMetadata {
+    /// File name of the archive the entries were extracted from, missing from metadata written
+    /// by older versions or extracted from stdin
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub archive: Option<String>,
+    /// Kind of archive, missing from metadata of SARCs written by older versions
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub container: Option<Container>,
+    pub byte_order: ByteOrder,
+    /// Outer compression, missing from metadata written by older versions
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub compression: Option<Compression>,
+    /// Alignment shared by all of the entries, used for entries that no longer fit where they
+    /// were
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub alignment: Option<usize>,
+    pub hash_key: u32,
+    pub data_offset: u32,
+    pub file_size: u32,
+    /// Entries in SFAT order
+    pub entries: Vec<EntryMetadata>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct EntryMetadata {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// Path of the extracted file, relative to the extracted directory
+    pub path: String,
+    pub hash: u32,
+    /// Offset of the data, relative to the start of the data section
+    pub offset: u32,
+    /// Position in the SFAT of entries without a name, which they're put back at even if the
+    /// named entries around them are reordered
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub index: Option<usize>,
+    /// BYML version of entries extracted as YAML
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub byml_version: Option<u16>,
+}
+
+impl ArchiveMetadata {
+    /// Metadata of an archive with this layout, whose entries end up at `entries`
+    pub fn new(layout: &Layout, compression: Compression, entries: Vec<EntryMetadata>) -> Self {
+        ArchiveMetadata {
+            archive: None,
+            container: Some(layout.container),
+            byte_order: (&layout.byte_order).into(),
+            compression: Some(compression),
+            alignment: Some(layout.alignment(DEFAULT_ALIGNMENT)),
+            hash_key: layout.hash_key,
+            data_offset: layout.data_offset,
+            file_size: layout.file_size,
+            entries,
+        }
+    }
+
+    /// The metadata in `dir`, unless it's `ignored` or there is none. `required` makes the
+    /// latter an error.
+    pub fn find(dir: &Path, required: bool, ignored: bool) -> Result<Option<Self>, ConvertError> {
+        if ignored {
+            return Ok(None)
+        }
+        if !required && !dir.join(METADATA_FILE).is_file() {
+            return Ok(None)
+        }
+        let metadata = ArchiveMetadata::read(dir)?;
+        log::debug!("Using the layout recorded in {}", dir.join(METADATA_FILE).display());
+        Ok(Some(metadata))
+    }
+
+    pub fn read(dir: &Path) -> Result<Self, ConvertError> {
+        let path = dir.join(METADATA_FILE);
+        let text = fs::read_to_string(&path).map_err(|err| ConvertError::io(&path, err))?;
+        serde_yaml::from_str(&text).map_err(|err| ConvertError::from(err).in_file(&path))
+    }
+
+    pub fn write(&self, dir: &Path) -> Result<(), ConvertError> {
+        let path = dir.join(METADATA_FILE);
+        stdio::write(&path, serde_yaml::to_string(self)?.as_bytes())
+    }
+
+    /// Give the entries of an archive packed from named files the names, hashes and offsets
+    /// recorded for their paths, in the recorded order, followed by the files it doesn't know
+    pub fn restore(&self, sarc: &mut SarcWriter) {
+        let mut files: Vec<Option<WriterEntry>> = std::mem::take(&mut sarc.entries).into_iter().map(Some).collect();
+        let positions: HashMap<String, usize> = files.iter()
+            .enumerate()
+            .filter_map(|(i, file)| Some((file.as_ref()?.name.clone()?, i)))
+            .collect();
+        let mut entries: Vec<(usize, &EntryMetadata)> = self.entries.iter().enumerate().collect();
+        entries.sort_by_key(|&(i, entry)| entry.index.unwrap_or(i));
+        for (_, entry) in entries {
+            if let Some(mut file) = positions.get(&entry.path).and_then(|&i| files[i].take()) {
+                file.hash = entry.name.as_ref().map_or(entry.hash, |name| layout::hash(name, self.hash_key));
+                file.name = entry.name.clone();
+                file.offset = Some(entry.offset as usize);
+                sarc.entries.push(file);
+            }
+        }
+        for mut file in files.into_iter().flatten() {
+            if let Some(name) = &file.name {
+                file.hash = layout::hash(name, self.hash_key);
+            }
+            sarc.entries.push(file);
+        }
+        sarc.container = self.container.unwrap_or_default();
+        sarc.hash_key = self.hash_key;
+        sarc.data_offset = Some(self.data_offset as usize);
+        sarc.file_size = Some(self.file_size as usize);
+    }
+}
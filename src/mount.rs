@@ -0,0 +1,259 @@
+//! Read-only FUSE filesystem of the entries of an archive, for `mount`
+use std::collections::BTreeMap;
+use std::ffi::OsStr;
+use std::ops::Range;
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+use fuser::{FileAttr, FileType, Filesystem, MountOption, ReplyAttr, ReplyData, ReplyDirectory, ReplyEmpty, ReplyEntry, ReplyXattr, Request};
+use libc::{ENODATA, ENOENT};
+
+use crate::archive::{self, Archive};
+use crate::compression::{self, Compression};
+use crate::dictionary::Dictionaries;
+use crate::ConvertError;
+
+/// Nothing changes while mounted, so the kernel can cache everything for as long as it likes
+const TTL: Duration = Duration::from_secs(3600);
+const ROOT: u64 = 1;
+
+enum Node {
+    Dir { parent: u64, children: BTreeMap<String, u64> },
+    File { archive: usize, data: Range<usize> },
+}
+
+struct SarcFs {
+    /// The mounted archive followed by any nested ones
+    archives: Vec<Archive>,
+    /// Indexed by inode - 1
+    nodes: Vec<Node>,
+    /// Owner and times of the archive file, used for everything in it
+    uid: u32,
+    gid: u32,
+    time: SystemTime,
+}
+
+impl SarcFs {
+    fn dir(&mut self, parent: u64) -> u64 {
+        self.nodes.push(Node::Dir { parent, children: BTreeMap::new() });
+        self.nodes.len() as u64
+    }
+
+    fn node(&self, ino: u64) -> Option<&Node> {
+        self.nodes.get(ino.checked_sub(1)? as usize)
+    }
+
+    fn children(&mut self, ino: u64) -> &mut BTreeMap<String, u64> {
+        match &mut self.nodes[ino as usize - 1] {
+            Node::Dir { children, .. } => children,
+            Node::File { .. } => unreachable!("only directories have children"),
+        }
+    }
+
+    /// The directory for a path under `dir`, creating whatever's missing. A file in the way
+    /// leaves the path out.
+    fn make_dirs(&mut self, mut dir: u64, components: &[&str]) -> Option<u64> {
+        for component in components {
+            dir = match self.children(dir).get(*component) {
+                Some(&child) => match self.node(child)? {
+                    Node::Dir { .. } => child,
+                    Node::File { .. } => return None,
+                },
+                None => {
+                    let child = self.dir(dir);
+                    self.children(dir).insert(component.to_string(), child);
+                    child
+                }
+            };
+        }
+        Some(dir)
+    }
+
+    /// Add the entries of an archive under `dir`, with nested archives as directories if asked
+    fn add_archive(&mut self, archive: Archive, dir: u64, nested: bool, dictionaries: &Dictionaries) {
+        let index = self.archives.len();
+        let mut files = vec![];
+        for entry in archive.entries() {
+            let name = entry.display_name();
+            let components: Vec<&str> = name.split('/').filter(|component| !component.is_empty()).collect();
+            let (file_name, parents) = match components.split_last() {
+                Some(split) => split,
+                None => continue,
+            };
+            match self.make_dirs(dir, parents) {
+                Some(parent) if !self.children(parent).contains_key(*file_name) => {
+                    files.push((parent, file_name.to_string(), entry.offset..entry.offset + entry.data.len()));
+                }
+                _ => log::warn!("Leaving out {}, which clashes with another entry", name),
+            }
+        }
+        let nested_archives: Vec<(usize, Archive)> = if nested {
+            files.iter().enumerate()
+                .filter_map(|(i, (_, name, range))| Some((i, nested_archive(name, &archive.data[range.clone()], dictionaries)?)))
+                .collect()
+        } else {
+            vec![]
+        };
+        self.archives.push(archive);
+
+        let mut nested_archives = nested_archives.into_iter().peekable();
+        for (i, (parent, name, data)) in files.into_iter().enumerate() {
+            let ino = match nested_archives.next_if(|(nested, _)| *nested == i) {
+                Some((_, nested)) => {
+                    let ino = self.dir(parent);
+                    self.add_archive(nested, ino, true, dictionaries);
+                    ino
+                }
+                None => {
+                    self.nodes.push(Node::File { archive: index, data });
+                    self.nodes.len() as u64
+                }
+            };
+            self.children(parent).insert(name, ino);
+        }
+    }
+
+    fn attr(&self, ino: u64) -> Option<FileAttr> {
+        let (kind, size, perm, nlink) = match self.node(ino)? {
+            Node::Dir { .. } => (FileType::Directory, 0, 0o555, 2),
+            Node::File { data, .. } => (FileType::RegularFile, data.len() as u64, 0o444, 1),
+        };
+        Some(FileAttr {
+            ino,
+            size,
+            blocks: size.div_ceil(512),
+            atime: self.time,
+            mtime: self.time,
+            ctime: self.time,
+            crtime: self.time,
+            kind,
+            perm,
+            nlink,
+            uid: self.uid,
+            gid: self.gid,
+            rdev: 0,
+            blksize: 512,
+            flags: 0,
+        })
+    }
+}
+
+/// An entry that's an archive itself. Only entries with archive names are decompressed to check.
+fn nested_archive(name: &str, data: &[u8], dictionaries: &Dictionaries) -> Option<Archive> {
+    let data = match Compression::detect(data) {
+        Compression::None if data.starts_with(b"SARC") => data.to_vec(),
+        Compression::None => return None,
+        _ if archive::is_archive_name(Path::new(name)) => compression::decompress(data, dictionaries).ok()?,
+        _ => return None,
+    };
+    match Archive::from_bytes(data, dictionaries) {
+        Ok(archive) => Some(archive),
+        Err(err) => {
+            log::warn!("{}: {}", name, err);
+            None
+        }
+    }
+}
+
+impl Filesystem for SarcFs {
+    fn lookup(&mut self, _req: &Request<'_>, parent: u64, name: &OsStr, reply: ReplyEntry) {
+        let child = match self.node(parent) {
+            Some(Node::Dir { children, .. }) => children.get(name.to_string_lossy().as_ref()).copied(),
+            _ => None,
+        };
+        match child.and_then(|child| self.attr(child)) {
+            Some(attr) => reply.entry(&TTL, &attr, 0),
+            None => reply.error(ENOENT),
+        }
+    }
+
+    fn getattr(&mut self, _req: &Request<'_>, ino: u64, _fh: Option<u64>, reply: ReplyAttr) {
+        match self.attr(ino) {
+            Some(attr) => reply.attr(&TTL, &attr),
+            None => reply.error(ENOENT),
+        }
+    }
+
+    fn read(
+        &mut self,
+        _req: &Request<'_>,
+        ino: u64,
+        _fh: u64,
+        offset: i64,
+        size: u32,
+        _flags: i32,
+        _lock_owner: Option<u64>,
+        reply: ReplyData,
+    ) {
+        match self.node(ino) {
+            Some(Node::File { archive, data }) => {
+                let data = &self.archives[*archive].data[data.clone()];
+                let start = (offset.max(0) as usize).min(data.len());
+                let end = start.saturating_add(size as usize).min(data.len());
+                reply.data(&data[start..end]);
+            }
+            _ => reply.error(ENOENT),
+        }
+    }
+
+    fn readdir(&mut self, _req: &Request<'_>, ino: u64, _fh: u64, offset: i64, mut reply: ReplyDirectory) {
+        let (parent, children) = match self.node(ino) {
+            Some(Node::Dir { parent, children }) => (*parent, children),
+            _ => return reply.error(ENOENT),
+        };
+        let entries = vec![(ino, FileType::Directory, "."), (parent, FileType::Directory, "..")].into_iter()
+            .chain(children.iter().map(|(name, &child)| {
+                let kind = match self.node(child) {
+                    Some(Node::Dir { .. }) => FileType::Directory,
+                    _ => FileType::RegularFile,
+                };
+                (child, kind, name.as_str())
+            }));
+        for (i, (child, kind, name)) in entries.enumerate().skip(offset.max(0) as usize) {
+            // the offset given is where to carry on from once the buffer is full
+            if reply.add(child, i as i64 + 1, kind, name) {
+                break
+            }
+        }
+        reply.ok();
+    }
+
+    // entries have no extended attributes, answered here so fuser doesn't warn each time
+    fn getxattr(&mut self, _req: &Request<'_>, _ino: u64, _name: &OsStr, _size: u32, reply: ReplyXattr) {
+        reply.error(ENODATA);
+    }
+
+    fn listxattr(&mut self, _req: &Request<'_>, _ino: u64, size: u32, reply: ReplyXattr) {
+        if size == 0 {
+            reply.size(0);
+        } else {
+            reply.data(&[]);
+        }
+    }
+
+    fn flush(&mut self, _req: &Request<'_>, _ino: u64, _fh: u64, _lock_owner: u64, reply: ReplyEmpty) {
+        reply.ok();
+    }
+}
+
+/// Mount `archive` on `mount_point` until it's unmounted
+pub fn mount(path: &Path, mount_point: &Path, nested: bool, dict: Option<&Path>) -> Result<(), ConvertError> {
+    let metadata = std::fs::metadata(path).map_err(|err| ConvertError::io(path, err))?;
+    let dictionaries = Dictionaries::load(dict, path)?;
+    let archive = Archive::open_shared(path, &dictionaries)?;
+    let mut fs = SarcFs {
+        archives: vec![],
+        nodes: vec![],
+        uid: metadata.uid(),
+        gid: metadata.gid(),
+        time: metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH),
+    };
+    let root = fs.dir(ROOT);
+    fs.add_archive(archive, root, nested, &dictionaries);
+
+    let name = path.file_name().unwrap_or_default().to_string_lossy().into_owned();
+    let options = [MountOption::RO, MountOption::FSName(name), MountOption::Subtype("sarctool".to_owned())];
+    log::info!("Mounted {} on {}, unmount it to stop", path.display(), mount_point.display());
+    fuser::mount2(fs, mount_point, &options).map_err(|err| ConvertError::io(mount_point, err))
+}
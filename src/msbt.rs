@@ -0,0 +1,211 @@
+//! Reading the messages out of MSBT files, for `unzip --msbt-to-text` and `cat --decode`
+//!
+//! Messages are written as YAML mapping each label to its text, in the order of the text table.
+//! Control tags, like colors and button icons, are written as `{group.type:params}` with the
+//! parameters in hex, and closing tags as `{/group.type}`.
+use serde::{Serialize, Serializer};
+
+use crate::ConvertError;
+
+const MAGIC: &[u8] = b"MsgStdBn";
+const HEADER_SIZE: usize = 0x20;
+const SECTION_HEADER_SIZE: usize = 0x10;
+
+const TAG_START: u32 = 0x0E;
+const TAG_END: u32 = 0x0F;
+
+/// Extension of text exported next to an MSBT file, which `zip` leaves out
+const EXPORT_EXTENSION: &str = ".msbt.yml";
+
+#[derive(Clone, Copy)]
+enum Encoding {
+    Utf8,
+    Utf16,
+    Utf32,
+}
+
+impl Encoding {
+    fn unit_size(self) -> usize {
+        match self {
+            Encoding::Utf8 => 1,
+            Encoding::Utf16 => 2,
+            Encoding::Utf32 => 4,
+        }
+    }
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    big_endian: bool,
+}
+
+fn error(message: &str) -> ConvertError {
+    ConvertError::message_format(&format!("MSBT: {}", message))
+}
+
+impl Reader<'_> {
+    fn bytes(&self, offset: usize, len: usize) -> Result<&[u8], ConvertError> {
+        offset.checked_add(len)
+            .and_then(|end| self.data.get(offset..end))
+            .ok_or_else(|| error(&format!("{:#x} is past the end of the file", offset + len)))
+    }
+
+    fn u16(&self, offset: usize) -> Result<u16, ConvertError> {
+        let bytes = [self.bytes(offset, 2)?[0], self.bytes(offset, 2)?[1]];
+        Ok(if self.big_endian { u16::from_be_bytes(bytes) } else { u16::from_le_bytes(bytes) })
+    }
+
+    fn u32(&self, offset: usize) -> Result<u32, ConvertError> {
+        let mut bytes = [0; 4];
+        bytes.copy_from_slice(self.bytes(offset, 4)?);
+        Ok(if self.big_endian { u32::from_be_bytes(bytes) } else { u32::from_le_bytes(bytes) })
+    }
+
+    fn unit(&self, offset: usize, encoding: Encoding) -> Result<u32, ConvertError> {
+        match encoding {
+            Encoding::Utf8 => Ok(self.bytes(offset, 1)?[0] as u32),
+            Encoding::Utf16 => self.u16(offset).map(u32::from),
+            Encoding::Utf32 => self.u32(offset),
+        }
+    }
+}
+
+pub fn is_export(path: &str) -> bool {
+    path.to_lowercase().ends_with(EXPORT_EXTENSION)
+}
+
+/// Path the text of an MSBT entry is exported to
+pub fn export_path(name: &str) -> String {
+    format!("{}.yml", name)
+}
+
+/// Labels and texts in the order of the text table
+struct Messages(Vec<(String, String)>);
+
+impl Serialize for Messages {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_map(self.0.iter().map(|(label, text)| (label, text)))
+    }
+}
+
+pub fn to_text(data: &[u8]) -> Result<String, ConvertError> {
+    if !data.starts_with(MAGIC) {
+        return Err(error("not an MSBT file"))
+    }
+    let big_endian = match data.get(8..10) {
+        Some([0xFE, 0xFF]) => true,
+        Some([0xFF, 0xFE]) => false,
+        _ => return Err(error("invalid byte order mark")),
+    };
+    let reader = Reader { data, big_endian };
+    let encoding = match reader.bytes(0xC, 1)?[0] {
+        0 => Encoding::Utf8,
+        1 => Encoding::Utf16,
+        2 => Encoding::Utf32,
+        other => return Err(error(&format!("unknown text encoding {}", other))),
+    };
+    let section_count = reader.u16(0xE)?;
+
+    let mut labels = vec![];
+    let mut texts = vec![];
+    let mut offset = HEADER_SIZE;
+    for _ in 0..section_count {
+        let magic = reader.bytes(offset, 4)?;
+        let size = reader.u32(offset + 4)? as usize;
+        let start = offset + SECTION_HEADER_SIZE;
+        reader.bytes(start, size)?;
+        match magic {
+            b"LBL1" => labels = read_labels(&reader, start)?,
+            b"TXT2" => texts = read_texts(&reader, start, size, encoding)?,
+            _ => (),
+        }
+        // sections are padded to 16 bytes
+        offset = (start + size + 0xF) & !0xF;
+    }
+
+    labels.sort_by_key(|&(_, index)| index);
+    let messages = labels.into_iter()
+        .map(|(label, index)| {
+            let text = texts.get(index as usize)
+                .ok_or_else(|| error(&format!("label {} points to missing text {}", label, index)))?;
+            Ok((label, text.clone()))
+        })
+        .collect::<Result<Vec<_>, ConvertError>>()?;
+    Ok(serde_yaml::to_string(&Messages(messages))?)
+}
+
+/// Labels and the index of their text, from the buckets of the label hash table
+fn read_labels(reader: &Reader, start: usize) -> Result<Vec<(String, u32)>, ConvertError> {
+    let bucket_count = reader.u32(start)? as usize;
+    let mut labels = vec![];
+    for bucket in 0..bucket_count {
+        let count = reader.u32(start + 4 + bucket * 8)?;
+        let mut offset = start + reader.u32(start + 8 + bucket * 8)? as usize;
+        for _ in 0..count {
+            let len = reader.bytes(offset, 1)?[0] as usize;
+            let label = String::from_utf8_lossy(reader.bytes(offset + 1, len)?).into_owned();
+            labels.push((label, reader.u32(offset + 1 + len)?));
+            offset += 1 + len + 4;
+        }
+    }
+    Ok(labels)
+}
+
+fn read_texts(reader: &Reader, start: usize, size: usize, encoding: Encoding) -> Result<Vec<String>, ConvertError> {
+    let count = reader.u32(start)? as usize;
+    let offsets = (0..count)
+        .map(|i| reader.u32(start + 4 + i * 4).map(|offset| start + offset as usize))
+        .collect::<Result<Vec<_>, _>>()?;
+    offsets.iter().enumerate()
+        .map(|(i, &offset)| {
+            let end = offsets.get(i + 1).copied().unwrap_or(start + size);
+            read_text(reader, offset, end, encoding)
+        })
+        .collect()
+}
+
+/// Text up to the terminating null, with control tags written out
+fn read_text(reader: &Reader, mut offset: usize, end: usize, encoding: Encoding) -> Result<String, ConvertError> {
+    let mut text = String::new();
+    // runs of characters between tags, decoded together so multi-unit characters stay whole
+    let mut run: Vec<u32> = vec![];
+    while offset < end {
+        let unit = reader.unit(offset, encoding)?;
+        offset += encoding.unit_size();
+        match unit {
+            0 => break,
+            TAG_START => {
+                text.push_str(&decode(&run, encoding));
+                run.clear();
+                let (group, kind) = (reader.u16(offset)?, reader.u16(offset + 2)?);
+                let len = reader.u16(offset + 4)? as usize;
+                let params: String = reader.bytes(offset + 6, len)?.iter().map(|byte| format!("{:02X}", byte)).collect();
+                offset += 6 + len;
+                if params.is_empty() {
+                    text.push_str(&format!("{{{}.{}}}", group, kind));
+                } else {
+                    text.push_str(&format!("{{{}.{}:{}}}", group, kind, params));
+                }
+            }
+            TAG_END => {
+                text.push_str(&decode(&run, encoding));
+                run.clear();
+                text.push_str(&format!("{{/{}.{}}}", reader.u16(offset)?, reader.u16(offset + 2)?));
+                offset += 4;
+            }
+            unit => run.push(unit),
+        }
+    }
+    text.push_str(&decode(&run, encoding));
+    Ok(text)
+}
+
+fn decode(units: &[u32], encoding: Encoding) -> String {
+    match encoding {
+        Encoding::Utf8 => String::from_utf8_lossy(&units.iter().map(|&unit| unit as u8).collect::<Vec<_>>()).into_owned(),
+        Encoding::Utf16 => char::decode_utf16(units.iter().map(|&unit| unit as u16))
+            .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+            .collect(),
+        Encoding::Utf32 => units.iter().map(|&unit| char::from_u32(unit).unwrap_or(char::REPLACEMENT_CHARACTER)).collect(),
+    }
+}
@@ -0,0 +1,65 @@
+//! Names for entries of archives without a name table, from the CSV given with `--name-map`
+//!
+//! Each row is either `hash,name`, with the hash in hex, or just a name, which is matched by
+//! hashing it with the hash key of each archive.
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::OnceLock;
+
+use crate::layout;
+use crate::ConvertError;
+
+static NAME_MAP: OnceLock<NameMap> = OnceLock::new();
+
+#[derive(Default)]
+pub struct NameMap {
+    by_hash: HashMap<u32, String>,
+    names: Vec<String>,
+}
+
+impl NameMap {
+    pub fn load(path: &Path) -> Result<Self, ConvertError> {
+        let error = |message: String| ConvertError::param(&format!("{}: {}", path.display(), message));
+        let mut reader = csv::ReaderBuilder::new()
+            .has_headers(false)
+            .flexible(true)
+            .trim(csv::Trim::All)
+            .from_path(path)
+            .map_err(|err| error(err.to_string()))?;
+
+        let mut map = NameMap::default();
+        for (i, record) in reader.records().enumerate() {
+            let record = record.map_err(|err| error(err.to_string()))?;
+            match (record.get(0), record.get(1)) {
+                (Some("hash"), Some("name")) if i == 0 => (),
+                (Some(hash), Some(name)) => {
+                    let hash = u32::from_str_radix(hash.trim_start_matches("0x"), 16)
+                        .map_err(|_| error(format!("line {}: '{}' is not a hex hash", i + 1, hash)))?;
+                    map.by_hash.insert(hash, name.to_owned());
+                }
+                (Some(""), None) | (None, _) => (),
+                (Some(name), None) => map.names.push(name.to_owned()),
+            }
+        }
+        log::debug!("Loaded {} hashes and {} names from {}", map.by_hash.len(), map.names.len(), path.display());
+        Ok(map)
+    }
+
+    /// Names for the hashes of an archive using `hash_key`
+    fn for_key(&self, hash_key: u32) -> HashMap<u32, String> {
+        let mut names: HashMap<u32, String> = self.names.iter()
+            .map(|name| (layout::hash(name, hash_key), name.clone()))
+            .collect();
+        names.extend(self.by_hash.iter().map(|(&hash, name)| (hash, name.clone())));
+        names
+    }
+}
+
+pub fn init(map: NameMap) {
+    let _ = NAME_MAP.set(map);
+}
+
+/// Names from the map for the hashes of an archive using `hash_key`, empty without a map
+pub fn names(hash_key: u32) -> HashMap<u32, String> {
+    NAME_MAP.get().map(|map| map.for_key(hash_key)).unwrap_or_default()
+}
@@ -0,0 +1,622 @@
+//! Building archives out of directories, manifests and in-memory entries
+use std::collections::{HashMap, HashSet};
+use std::convert::TryFrom;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use rayon::prelude::*;
+use sarc::Endian;
+use structopt::StructOpt;
+
+use crate::alignment::{parse_alignment, parse_int, Alignment, Game};
+use crate::archive::Archive;
+use crate::cache;
+use crate::compression::{Compression, CompressionOptions};
+use crate::config::Config;
+use crate::container::{Container, CONTAINERS};
+use crate::dictionary::Dictionaries;
+use crate::layout::{self, DEFAULT_HASH_KEY};
+use crate::manifest::Manifest;
+use crate::metadata::{ArchiveMetadata, ByteOrder, METADATA_FILE};
+use crate::preset::{Preset, PRESETS};
+use crate::scan::ScanArgs;
+use crate::watch::FileCache;
+use crate::writer::{self, EntryData, SarcWriter, WriterEntry};
+use crate::yaml::{self, YamlFormat};
+use crate::{extract, msbt, progress, stdio, ConvertError};
+
+/// How to build an archive, which are also the flags of every command that packs one. Anything
+/// left unset falls back to the metadata, manifest or config, in that order, then the defaults.
+#[derive(StructOpt, Debug, Clone, Default)]
+pub struct PackArgs {
+    #[structopt(short, long, alias = "compress", alias = "c")]
+    pub yaz0: bool,
+    #[structopt(short, long, conflicts_with = "yaz0")]
+    pub zstd: bool,
+    /// Compress with LZ4, as frames unless `--lz4-block` is also given
+    #[structopt(long, conflicts_with_all = &["yaz0", "zstd"])]
+    pub lz4: bool,
+    /// Write LZ4 as a single raw block after the decompressed size, instead of as frames
+    #[structopt(long, requires = "lz4")]
+    pub lz4_block: bool,
+    #[structopt(long, conflicts_with_all = &["yaz0", "zstd", "lz4"])]
+    pub no_compression: bool,
+
+    #[structopt(short, long, alias = "big")]
+    pub big_endian: bool,
+    #[structopt(short, long, alias = "little", conflicts_with = "big-endian")]
+    pub little_endian: bool,
+
+    #[structopt(short, long, parse(try_from_str = parse_alignment))]
+    pub alignment: Option<usize>,
+    #[structopt(short, long, possible_values = &["botw", "totk"])]
+    pub game: Option<Game>,
+    /// Byte order, alignment and compression defaults for a game, overridden by explicit flags
+    #[structopt(long, possible_values = PRESETS)]
+    pub preset: Option<Preset>,
+
+    /// Defaults to 10
+    #[structopt(long, parse(try_from_str = parse_yaz0_level))]
+    pub yaz0_level: Option<usize>,
+    #[structopt(long, conflicts_with = "yaz0-level")]
+    pub fast: bool,
+    /// Defaults to 3
+    #[structopt(long, allow_hyphen_values = true)]
+    pub zstd_level: Option<i32>,
+    /// Leave out the name table, so entries can only be found by hash
+    #[structopt(long)]
+    pub no_names: bool,
+    /// Multiplier of the name hash, for games that don't use the usual 0x65
+    #[structopt(long, parse(try_from_str = parse_hash_key))]
+    pub hash_key: Option<u32>,
+    /// Fail instead of warning about duplicate names and hash collisions
+    #[structopt(long)]
+    pub strict: bool,
+    /// Compress entries the way their names say they should be, with zstd for `.zs` and yaz0
+    /// for `.s` extensions like `.sbfres`, unless they already are
+    #[structopt(long)]
+    pub compress_inner: bool,
+    /// Store the data of entries in the order of this list of names, one per line like `list
+    /// --order` prints them, with entries that aren't listed after the rest. The SFAT is always
+    /// sorted by hash, since that's how games look entries up.
+    #[structopt(long)]
+    pub order_from: Option<PathBuf>,
+    /// Store the data of entries in the order of their hashes, the same as the SFAT
+    #[structopt(long, conflicts_with = "order-from")]
+    pub sort_by_hash: bool,
+    /// Store the data of entries in the order they come in, which is the default: the order
+    /// `unzip` recorded followed by new files by path, or the order of the zip, tar or 7z
+    #[structopt(long, conflicts_with_all = &["order-from", "sort-by-hash"])]
+    pub keep_order: bool,
+
+    /// Kind of archive to write, which is otherwise picked by the extension of the output,
+    /// `.arc` for U8 and `.sarc` for SARC, or else the kind the files were extracted from
+    #[structopt(long, possible_values = CONTAINERS)]
+    pub container: Option<Container>,
+
+    /// Compression from the config file, for outputs without a known extension
+    #[structopt(skip)]
+    pub default_compression: Option<Compression>,
+}
+
+impl PackArgs {
+    pub fn compression(&self, out_file: &Path) -> Compression {
+        self.explicit_compression()
+            .or_else(|| Compression::from_extension(out_file))
+            .or(self.default_compression)
+            .or_else(|| self.preset.map(Preset::compression))
+            .unwrap_or(Compression::None)
+    }
+
+    /// Compression picked by a flag, rather than inferred
+    pub fn explicit_compression(&self) -> Option<Compression> {
+        if self.yaz0 {
+            Some(Compression::Yaz0)
+        } else if self.zstd {
+            Some(Compression::Zstd)
+        } else if self.lz4 && self.lz4_block {
+            Some(Compression::Lz4Block)
+        } else if self.lz4 {
+            Some(Compression::Lz4)
+        } else if self.no_compression {
+            Some(Compression::None)
+        } else {
+            None
+        }
+    }
+
+    /// Set the flag that picks a compression
+    pub fn set_compression(&mut self, compression: Compression) {
+        match compression {
+            Compression::Yaz0 => self.yaz0 = true,
+            Compression::Zstd => self.zstd = true,
+            Compression::Lz4 => self.lz4 = true,
+            Compression::Lz4Block => {
+                self.lz4 = true;
+                self.lz4_block = true;
+            }
+            Compression::None => self.no_compression = true,
+        }
+    }
+
+    /// Whether anything that decides the data layout was given
+    pub fn sets_alignment(&self) -> bool {
+        self.alignment.is_some() || self.game.is_some() || self.preset.is_some()
+    }
+
+    pub fn sets_byte_order(&self) -> bool {
+        self.big_endian || self.little_endian || self.preset.is_some()
+    }
+
+    pub fn byte_order(&self) -> Endian {
+        if self.big_endian && !self.little_endian {
+            Endian::Big
+        } else if self.little_endian {
+            Endian::Little
+        } else {
+            self.preset.map_or(Endian::Little, Preset::byte_order)
+        }
+    }
+
+    pub fn alignment(&self) -> Alignment {
+        Alignment::new(self.alignment, self.game.or_else(|| self.preset.and_then(Preset::game)))
+    }
+
+    /// Whether to look for dictionaries next to the output when none are passed with `--dict`
+    pub fn find_dictionaries(&self) -> bool {
+        self.preset.is_none_or(Preset::uses_dictionaries)
+    }
+
+    /// Fill in whatever wasn't given on the command line from the metadata of an extracted
+    /// archive, before the config. A preset replaces it like it does the config.
+    pub fn apply_metadata(&mut self, metadata: &ArchiveMetadata) {
+        if self.preset.is_some() {
+            return
+        }
+        if !self.big_endian && !self.little_endian {
+            match metadata.byte_order {
+                ByteOrder::Big => self.big_endian = true,
+                ByteOrder::Little => self.little_endian = true,
+            }
+        }
+        if let (None, Some(compression)) = (self.explicit_compression(), metadata.compression) {
+            self.set_compression(compression);
+        }
+        if self.game.is_none() {
+            self.alignment = self.alignment.or(metadata.alignment);
+        }
+    }
+
+    /// Fill in whatever wasn't given on the command line from a manifest, before the config
+    pub fn apply_manifest(&mut self, manifest: &Manifest) {
+        if !self.big_endian && !self.little_endian {
+            match manifest.endian {
+                Some(ByteOrder::Big) => self.big_endian = true,
+                Some(ByteOrder::Little) => self.little_endian = true,
+                None => (),
+            }
+        }
+        if let (None, Some(compression)) = (self.explicit_compression(), manifest.compression) {
+            self.set_compression(compression);
+        }
+        self.alignment = self.alignment.or(manifest.alignment);
+    }
+
+    /// Fill in whatever wasn't given on the command line from the config file
+    pub fn apply_config(&mut self, config: &Config) {
+        if self.preset.is_none() {
+            if !self.big_endian && !self.little_endian {
+                match config.endian {
+                    Some(ByteOrder::Big) => self.big_endian = true,
+                    Some(ByteOrder::Little) => self.little_endian = true,
+                    None => (),
+                }
+            }
+            self.preset = config.preset;
+        }
+        self.apply_compression_config(config);
+    }
+
+    /// Fill in only the compression from the config file, for commands that rewrite an archive
+    /// rather than create one, which keep the byte order and layout it had unless given flags
+    pub fn apply_compression_config(&mut self, config: &Config) {
+        if self.preset.is_none() {
+            self.default_compression = config.compression;
+        }
+        self.zstd_level = self.zstd_level.or(config.zstd_level);
+        if !self.fast {
+            self.yaz0_level = self.yaz0_level.or(config.yaz0_level);
+        }
+    }
+
+    pub fn compression_options(&self) -> CompressionOptions<'static> {
+        CompressionOptions {
+            yaz0_level: if self.fast { 1 } else { self.yaz0_level.unwrap_or(10) },
+            zstd_level: self.zstd_level.unwrap_or(zstd::DEFAULT_COMPRESSION_LEVEL),
+            threads: rayon::current_num_threads() as u32,
+            dictionary: None,
+        }
+    }
+}
+
+fn parse_hash_key(s: &str) -> Result<u32, String> {
+    let key = parse_int(s).map_err(|err| err.to_string())?;
+    u32::try_from(key).map_err(|_| "hash key must fit in 32 bits".to_owned())
+}
+
+pub fn parse_yaz0_level(s: &str) -> Result<usize, String> {
+    match s.parse() {
+        Ok(level) if level <= 10 => Ok(level),
+        _ => Err("yaz0 level must be between 0 and 10".to_owned()),
+    }
+}
+
+/// Without a cache to fill, files are left on disk and only copied into the archive as it's
+/// written, so the whole archive never has to fit in memory
+pub fn entry_data(path: &Path, cache: Option<&FileCache>) -> Result<EntryData, ConvertError> {
+    match cache {
+        Some(cache) => cache.read(path).map(EntryData::from),
+        None => {
+            let metadata = fs::metadata(path).map_err(|err| ConvertError::io(path, err))?;
+            Ok(EntryData::File { path: path.to_owned(), len: metadata.len() as usize })
+        }
+    }
+}
+
+/// Data of a file to pack, turned back into BYML or AAMP if it was extracted as YAML. `name` is
+/// the path relative to the packed directory.
+pub fn packed_data(
+    path: &Path, name: &str, byte_order: Endian, byml_version: Option<u16>, cache: Option<&FileCache>
+) -> Result<EntryData, ConvertError> {
+    let format = match YamlFormat::from_yaml_path(name) {
+        Some((format, _)) => format,
+        None => return entry_data(path, cache),
+    };
+    let text = match cache {
+        Some(cache) => cache.read(path)?,
+        None => fs::read(path).map_err(|err| ConvertError::io(path, err))?,
+    };
+    let version = byml_version.unwrap_or(yaml::DEFAULT_BYML_VERSION);
+    let settings = format!("{:?} {:?} {}", format, byte_order, version);
+    cache::cached(&[b"yaml", settings.as_bytes()], text, |text| {
+        std::str::from_utf8(&text)
+            .map_err(ConvertError::from)
+            .and_then(|text| format.to_binary(text, byte_order, version))
+    })
+    .map(EntryData::from)
+    .map_err(|err| err.in_file(path))
+}
+
+/// Pack every file in a directory, keeping the order and offsets of the entries recorded in its
+/// metadata by `unzip`
+pub fn pack_dir(
+    pack: &PackArgs,
+    scan: &ScanArgs,
+    in_dir: &Path,
+    out_file: &Path,
+    metadata: Option<&ArchiveMetadata>,
+    dict: Option<&Path>,
+    cache: Option<&FileCache>,
+) -> Result<(), ConvertError> {
+    if !in_dir.is_dir() {
+        return Err(ConvertError::file("not a directory").in_file(in_dir))
+    }
+    let hash_key = metadata.as_ref().map(|metadata| metadata.hash_key).unwrap_or(DEFAULT_HASH_KEY);
+
+    // the output may be inside of the directory, and shouldn't end up packed into itself
+    let out_path = fs::canonicalize(out_file).ok();
+    let scanned: Vec<_> = scan.files(in_dir)?.into_iter()
+        .filter(|file| out_path.is_none() || fs::canonicalize(&file.path).ok() != out_path)
+        .collect();
+    let known_count = metadata.map_or(0, |metadata| metadata.entries.len());
+    let progress = progress::entries(known_count + scanned.len(), "Reading");
+
+    // entries recorded by `unzip` keep their original order and data offset
+    let known_entries = metadata.map_or(&[][..], |metadata| &metadata.entries[..]);
+    let known_paths: HashSet<&str> = known_entries.iter().map(|entry| entry.path.as_str()).collect();
+    let mut files = known_entries.par_iter()
+        .filter_map(|entry| {
+            progress.inc(1);
+            let path = in_dir.join(&entry.path);
+            if !path.exists() || scan.is_excluded(&entry.path) {
+                return None
+            }
+            log::info!("Adding {}", entry.path);
+            Some(packed_data(&path, &entry.path, pack.byte_order(), entry.byml_version, cache).map(|data| WriterEntry {
+                hash: entry.name.as_ref().map(|name| layout::hash(name, hash_key)).unwrap_or(entry.hash),
+                name: entry.name.clone(),
+                data,
+                offset: Some(entry.offset as usize),
+                alignment: None,
+            }))
+        })
+        .collect::<Result<Vec<_>, ConvertError>>()?;
+
+    let mut new_files = scanned.par_iter()
+        .filter_map(|file| {
+            progress.inc(1);
+            if file.name == METADATA_FILE || known_paths.contains(file.name.as_str()) {
+                return None
+            }
+            if msbt::is_export(&file.name) {
+                log::debug!("Leaving out {}, text exported from an MSBT file", file.name);
+                return None
+            }
+            let name = YamlFormat::from_yaml_path(&file.name).map_or(file.name.as_str(), |(_, name)| name);
+            log::info!("Adding {}", name);
+            Some(
+                packed_data(&file.path, &file.name, pack.byte_order(), None, cache)
+                    .map(|data| WriterEntry::named(name.to_owned(), data, hash_key))
+            )
+        })
+        .collect::<Result<Vec<_>, ConvertError>>()?;
+    // by their whole names rather than directory by directory, which is the same on every OS
+    if stdio::is_reproducible() {
+        new_files.sort_by(|a, b| a.name.cmp(&b.name));
+    }
+    files.extend(new_files);
+    progress.finish_and_clear();
+
+    let mut sarc = SarcWriter::new(pack.byte_order(), files);
+    pack.alignment().apply(&mut sarc);
+    if let Some(metadata) = metadata {
+        sarc.container = metadata.container.unwrap_or_default();
+        sarc.hash_key = metadata.hash_key;
+        sarc.data_offset = Some(metadata.data_offset as usize);
+        sarc.file_size = Some(metadata.file_size as usize);
+    }
+
+    write(sarc, out_file, pack, dict)
+}
+
+/// Pack a directory edited from `original` the way `original` was packed, with its byte order,
+/// compression, hash key, alignment and order of entries. Metadata in the directory is only used
+/// for where the entries are, since `unzip` may have put them somewhere other than their names.
+pub fn repack(
+    pack: &PackArgs,
+    config: &Config,
+    scan: &ScanArgs,
+    original: &Path,
+    in_dir: &Path,
+    out_file: &Path,
+    dict: Option<&Path>,
+) -> Result<(), ConvertError> {
+    let metadata = {
+        let archive = Archive::open(original, dict)?;
+        let entries = match ArchiveMetadata::find(in_dir, false, false)? {
+            Some(metadata) => metadata.entries,
+            None => {
+                let mut entries = extract::entry_metadata(&archive);
+                extract::rename_collisions(&mut entries);
+                entries
+            }
+        };
+        ArchiveMetadata::new(&archive.layout, archive.compression, entries)
+    };
+    let mut pack = pack.clone();
+    pack.apply_metadata(&metadata);
+    pack.apply_config(config);
+    pack_dir(&pack, scan, in_dir, out_file, Some(&metadata), dict, None)
+}
+
+/// Pack the files listed in a manifest. It's loaded on every build, so `--watch` picks up edits
+/// to it as well.
+pub fn pack_manifest(
+    pack: &PackArgs,
+    config: &Config,
+    manifest: &Path,
+    in_dir: &Path,
+    out_file: &Path,
+    dict: Option<&Path>,
+    cache: Option<&FileCache>,
+) -> Result<(), ConvertError> {
+    let manifest = Manifest::load(manifest)?;
+    let mut pack = pack.clone();
+    pack.apply_manifest(&manifest);
+    pack.apply_config(config);
+
+    let progress = progress::entries(manifest.entries.len(), "Reading");
+    let files = manifest.entries.par_iter()
+        .map(|entry| {
+            progress.inc(1);
+            let name = entry.name();
+            log::info!("Adding {}", name);
+            let source = entry.source.to_string_lossy();
+            packed_data(&in_dir.join(&entry.source), &source, pack.byte_order(), None, cache)
+                .map(|data| WriterEntry::named(name, data, DEFAULT_HASH_KEY))
+        })
+        .collect::<Result<Vec<_>, ConvertError>>()?;
+    progress.finish_and_clear();
+
+    let mut sarc = SarcWriter::new(pack.byte_order(), files);
+    pack.alignment().apply(&mut sarc);
+    for (entry, listed) in sarc.entries.iter_mut().zip(&manifest.entries) {
+        entry.alignment = listed.alignment.or(entry.alignment);
+    }
+
+    write(sarc, out_file, &pack, dict)
+}
+
+/// Write an archive with the compression picked by `pack`, or else by the extension of `out_file`
+pub fn write(sarc: SarcWriter, out_file: &Path, pack: &PackArgs, dict: Option<&Path>) -> Result<(), ConvertError> {
+    write_with(sarc, out_file, pack.compression(out_file), pack, dict)
+}
+
+/// Rehash, check the names of, and write an archive
+pub fn write_with(
+    sarc: SarcWriter, out_file: &Path, compression: Compression, pack: &PackArgs, dict: Option<&Path>
+) -> Result<(), ConvertError> {
+    prepare(sarc, out_file, compression, pack, dict, |sarc, options| {
+        log::info!("Writing {}", out_file.display());
+        write_archive(sarc, out_file, compression, options)
+    })
+}
+
+/// The file [`write_with`] would write to `out_file`, in memory instead
+pub fn to_bytes(
+    sarc: SarcWriter, out_file: &Path, compression: Compression, pack: &PackArgs, dict: Option<&Path>
+) -> Result<Vec<u8>, ConvertError> {
+    prepare(sarc, out_file, compression, pack, dict, |sarc, options| {
+        let mut out = Vec::with_capacity(sarc.file_size());
+        compression.compress_to(sarc.file_size(), &mut out, options, |out| sarc.write(out))?;
+        Ok(out)
+    })
+}
+
+/// Everything [`write_with`] does before writing, with the options to compress the archive with
+fn prepare<T, F>(
+    mut sarc: SarcWriter, out_file: &Path, compression: Compression, pack: &PackArgs, dict: Option<&Path>, write: F
+) -> Result<T, ConvertError>
+    where F: FnOnce(&SarcWriter, &CompressionOptions) -> Result<T, ConvertError>
+{
+    let container = pack.container.or_else(|| Container::from_extension(out_file)).unwrap_or(sarc.container);
+    if container != sarc.container {
+        // the layout of one kind of archive is no use in the other
+        log::info!("Converting to {}", container);
+        for entry in &mut sarc.entries {
+            entry.offset = None;
+        }
+        sarc.data_offset = None;
+        sarc.file_size = None;
+        if sarc.alignment == sarc.container.default_alignment() {
+            sarc.alignment = container.default_alignment();
+        }
+        sarc.container = container;
+    }
+    if !pack.sets_alignment() {
+        sarc.alignment = sarc.container.default_alignment();
+    }
+    if let Some(hash_key) = pack.hash_key {
+        // unnamed entries can't be rehashed, and keep whatever hash they had
+        sarc.hash_key = hash_key;
+        for entry in &mut sarc.entries {
+            if let Some(name) = &entry.name {
+                entry.hash = layout::hash(name, hash_key);
+            }
+        }
+    }
+    reorder(&mut sarc, pack)?;
+    let problems = layout::name_problems(sarc.entries.iter().map(|entry| (writer::display_name(entry), entry.hash)));
+    if pack.strict && !problems.is_empty() {
+        for problem in &problems {
+            log::error!("{}", problem);
+        }
+        return Err(ConvertError::sarc(format!(
+            "{}: not writing an archive with {} naming problem(s)", out_file.display(), problems.len()
+        )))
+    }
+    for problem in &problems {
+        log::warn!("{}", problem);
+    }
+    if pack.no_names {
+        for entry in &mut sarc.entries {
+            entry.name = None;
+        }
+    }
+    let uses_zstd = compression == Compression::Zstd || pack.compress_inner;
+    let dictionaries = if uses_zstd && (dict.is_some() || pack.find_dictionaries()) {
+        Dictionaries::load(dict, out_file)?
+    } else {
+        Dictionaries::default()
+    };
+    if pack.compress_inner {
+        compress_inner(&mut sarc, &dictionaries, &pack.compression_options())?;
+    }
+    let options = CompressionOptions {
+        dictionary: dictionaries.for_output(out_file),
+        ..pack.compression_options()
+    };
+    log::debug!(
+        "Compressing with {:?} (yaz0 level {}, zstd level {}, {} dictionary)", compression,
+        options.yaz0_level, options.zstd_level, if options.dictionary.is_some() { "with" } else { "no" }
+    );
+    write(&sarc, &options)
+}
+
+/// Put the data of entries in the order `--order-from` or `--sort-by-hash` asks for, dropping
+/// the offsets they had since those would keep them where they were
+fn reorder(sarc: &mut SarcWriter, pack: &PackArgs) -> Result<(), ConvertError> {
+    if let Some(path) = &pack.order_from {
+        let list = stdio::read(path)?;
+        let names: Vec<&str> = std::str::from_utf8(&list)
+            .map_err(|err| ConvertError::from(err).in_file(path))?
+            .lines()
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+            .collect();
+        let mut positions: HashMap<&str, usize> = HashMap::new();
+        for (i, name) in names.iter().enumerate() {
+            positions.entry(name).or_insert(i);
+        }
+        let packed: HashSet<String> = sarc.entries.iter().map(writer::display_name).collect();
+        for name in names.iter().filter(|name| !packed.contains(**name)) {
+            log::warn!("{} is listed in {} but isn't being packed", name, path.display());
+        }
+        sarc.entries.sort_by_key(|entry| positions.get(writer::display_name(entry).as_str()).copied().unwrap_or(usize::MAX));
+    } else if pack.sort_by_hash {
+        sarc.entries.sort_by_key(|entry| entry.hash);
+    } else {
+        return Ok(())
+    }
+    for entry in &mut sarc.entries {
+        entry.offset = None;
+    }
+    sarc.file_size = None;
+    Ok(())
+}
+
+/// Compress the entries whose names say they should be, leaving alone those that already are
+pub fn compress_inner(sarc: &mut SarcWriter, dictionaries: &Dictionaries, options: &CompressionOptions) -> Result<(), ConvertError> {
+    let progress = progress::entries(sarc.entries.len(), "Compressing entries");
+    sarc.entries.par_iter_mut().try_for_each(|entry| -> Result<(), ConvertError> {
+        progress.inc(1);
+        let (name, compression) = match entry.name.as_deref().and_then(|name| Some((name, Compression::for_entry(name)?))) {
+            Some(found) => found,
+            None => return Ok(()),
+        };
+        let data = std::mem::replace(&mut entry.data, EntryData::Bytes(vec![])).into_bytes()?;
+        if Compression::detect(&data) != Compression::None {
+            entry.data = data.into();
+            return Ok(())
+        }
+        let options = CompressionOptions {
+            dictionary: dictionaries.for_output(Path::new(name)),
+            ..*options
+        };
+        // zstd writes the same frames with any number of workers, but not without them
+        let settings = format!(
+            "{:?} {} {} {}", compression, options.yaz0_level, options.zstd_level, options.threads > 0
+        );
+        let dictionary = options.dictionary.unwrap_or_default();
+        entry.data = cache::cached(&[b"compress", settings.as_bytes(), dictionary], data, |data| {
+            log::info!("Compressing {} with {:?}", name, compression);
+            compression.compress_entry(data, &options)
+        })
+        .map_err(|err| err.in_entry(name))?
+        .into();
+        Ok(())
+    })?;
+    progress.finish_and_clear();
+    Ok(())
+}
+
+/// Write with the outer compression of an input archive
+pub fn write_compressed(
+    sarc: SarcWriter, out_file: &Path, compression: Compression, dictionaries: &Dictionaries
+) -> Result<(), ConvertError> {
+    let options = CompressionOptions {
+        dictionary: dictionaries.for_output(out_file),
+        ..CompressionOptions::default()
+    };
+    log::info!("Writing {} ({:?})", out_file.display(), compression);
+    write_archive(&sarc, out_file, compression, &options)
+}
+
+/// Write an archive as it is, to a file or stdout for `-`
+pub fn write_archive(
+    sarc: &SarcWriter, out_file: &Path, compression: Compression, options: &CompressionOptions
+) -> Result<(), ConvertError> {
+    stdio::create(out_file, |out| {
+        compression.compress_to(sarc.file_size(), out, options, |out| sarc.write(out))
+    })
+}
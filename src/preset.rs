@@ -0,0 +1,69 @@
+//! Per-game defaults for building archives, so the byte order, alignment and compression don't
+//! all have to be picked by hand
+use std::str::FromStr;
+
+use sarc::Endian;
+use serde::Deserialize;
+
+use crate::alignment::Game;
+use crate::compression::Compression;
+
+pub const PRESETS: &[&str] = &["botw-wiiu", "botw-switch", "totk", "smash", "splatoon3"];
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Preset {
+    #[serde(rename = "botw-wiiu")]
+    BotwWiiU,
+    BotwSwitch,
+    Totk,
+    Smash,
+    Splatoon3,
+}
+
+impl FromStr for Preset {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "botw-wiiu" => Ok(Preset::BotwWiiU),
+            "botw-switch" => Ok(Preset::BotwSwitch),
+            "totk" => Ok(Preset::Totk),
+            "smash" => Ok(Preset::Smash),
+            "splatoon3" => Ok(Preset::Splatoon3),
+            _ => Err(format!("unknown preset '{}'", s)),
+        }
+    }
+}
+
+impl Preset {
+    pub fn byte_order(self) -> Endian {
+        match self {
+            Preset::BotwWiiU => Endian::Big,
+            _ => Endian::Little,
+        }
+    }
+
+    /// Used when the output file's extension doesn't already say how it's compressed
+    pub fn compression(self) -> Compression {
+        match self {
+            Preset::BotwWiiU | Preset::BotwSwitch => Compression::Yaz0,
+            Preset::Totk | Preset::Splatoon3 => Compression::Zstd,
+            Preset::Smash => Compression::None,
+        }
+    }
+
+    /// The alignment table to use, games without one get the conservative default alignment
+    pub fn game(self) -> Option<Game> {
+        match self {
+            Preset::BotwWiiU | Preset::BotwSwitch => Some(Game::Botw),
+            Preset::Totk => Some(Game::Totk),
+            Preset::Smash | Preset::Splatoon3 => None,
+        }
+    }
+
+    /// Splatoon 3 compresses with plain zstd, only TOTK expects `ZsDic.pack` dictionaries
+    pub fn uses_dictionaries(self) -> bool {
+        self == Preset::Totk
+    }
+}
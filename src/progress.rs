@@ -0,0 +1,42 @@
+//! Progress bars for long packs, unpacks and compressions, only drawn when someone is watching
+//! the terminal
+use std::io::IsTerminal;
+use std::sync::OnceLock;
+
+use indicatif::{MultiProgress, ProgressBar, ProgressDrawTarget, ProgressStyle};
+
+static BARS: OnceLock<MultiProgress> = OnceLock::new();
+
+/// Bars are hidden unless both stdout and stderr are terminals
+pub fn init(enabled: bool) {
+    let target = if enabled && std::io::stdout().is_terminal() && std::io::stderr().is_terminal() {
+        ProgressDrawTarget::stderr()
+    } else {
+        ProgressDrawTarget::hidden()
+    };
+    let _ = BARS.set(MultiProgress::with_draw_target(target));
+}
+
+fn bars() -> &'static MultiProgress {
+    BARS.get_or_init(|| MultiProgress::with_draw_target(ProgressDrawTarget::hidden()))
+}
+
+fn bar(len: usize, message: &'static str, template: &str) -> ProgressBar {
+    let style = ProgressStyle::with_template(template).unwrap().progress_chars("=> ");
+    bars().add(ProgressBar::new(len as u64).with_style(style).with_message(message))
+}
+
+/// Progress through the entries of an archive
+pub fn entries(len: usize, message: &'static str) -> ProgressBar {
+    bar(len, message, "{msg} [{bar:40}] {pos}/{len}")
+}
+
+/// Progress through a large buffer
+pub fn bytes(len: usize, message: &'static str) -> ProgressBar {
+    bar(len, message, "{msg} [{bar:40}] {bytes}/{total_bytes} ({eta})")
+}
+
+/// Run `f` with the bars cleared, so whatever it prints isn't drawn over
+pub fn suspend<R>(f: impl FnOnce() -> R) -> R {
+    bars().suspend(f)
+}
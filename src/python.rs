@@ -0,0 +1,173 @@
+//! Python module for modding scripts, built with `maturin build --release` (configured in
+//! `pyproject.toml`). It packs, extracts, lists and diffs archives the same way `sarc` does,
+//! with the same presets, alignment rules and dictionaries.
+//!
+//! ```python
+//! import sarctool
+//!
+//! sarctool.unpack("Player.sbactorpack", "Player")
+//! sarctool.pack("Player", "Player.sbactorpack", preset="botw-switch")
+//! for entry in sarctool.list("Player.sbactorpack"):
+//!     print(entry["name"], len(entry["data"]))
+//! ```
+use std::path::PathBuf;
+
+use pyo3::create_exception;
+use pyo3::exceptions::{PyException, PyOSError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::{PyBytes, PyDict};
+
+use crate::alignment::parse_alignment;
+use crate::archive::Archive;
+use crate::compression::Compression;
+use crate::filetype::FileType;
+use crate::metadata::{ArchiveMetadata, ByteOrder};
+use crate::pack::{pack_dir, PackArgs};
+use crate::scan::ScanArgs;
+use crate::yaml::YamlFormat;
+use crate::extract::{self, ExtractOptions};
+use crate::{inspect, ConvertError, ConvertErrorKind};
+
+create_exception!(sarctool, SarcError, PyException, "An archive or file in one couldn't be parsed or written");
+
+impl From<ConvertError> for PyErr {
+    fn from(err: ConvertError) -> PyErr {
+        match err.kind {
+            ConvertErrorKind::Param => PyValueError::new_err(err.message),
+            ConvertErrorKind::File => PyOSError::new_err(err.message),
+            _ => SarcError::new_err(err.message),
+        }
+    }
+}
+
+fn parse<T: std::str::FromStr<Err = String>>(value: Option<&str>) -> Result<Option<T>, ConvertError> {
+    value.map(|value| value.parse().map_err(|err: String| ConvertError::param(&err))).transpose()
+}
+
+/// Pack a directory into an archive like `sarc zip`. Whatever isn't given comes from the
+/// `.sarctool.yml` left by `unpack`, then the preset, then the defaults.
+#[pyfunction]
+#[pyo3(signature = (
+    in_dir, out_file, *, preset=None, compression=None, byte_order=None, alignment=None, game=None,
+    no_names=false, dict=None
+))]
+#[allow(clippy::too_many_arguments)]
+fn pack(
+    py: Python<'_>,
+    in_dir: PathBuf,
+    out_file: PathBuf,
+    preset: Option<&str>,
+    compression: Option<&str>,
+    byte_order: Option<&str>,
+    alignment: Option<&str>,
+    game: Option<&str>,
+    no_names: bool,
+    dict: Option<PathBuf>,
+) -> PyResult<()> {
+    let mut args = PackArgs {
+        preset: parse(preset)?,
+        game: parse(game)?,
+        alignment: alignment.map(parse_alignment).transpose().map_err(|err| ConvertError::param(&err))?,
+        no_names,
+        ..PackArgs::default()
+    };
+    match compression {
+        Some("yaz0") => args.yaz0 = true,
+        Some("zstd") => args.zstd = true,
+        Some("lz4") => args.lz4 = true,
+        Some("lz4-block") => args.set_compression(Compression::Lz4Block),
+        Some("none") => args.no_compression = true,
+        Some(other) => return Err(ConvertError::param(&format!("unknown compression '{}'", other)).into()),
+        None => (),
+    }
+    match parse(byte_order)? {
+        Some(ByteOrder::Big) => args.big_endian = true,
+        Some(ByteOrder::Little) => args.little_endian = true,
+        None => (),
+    }
+    py.detach(|| {
+        let metadata = ArchiveMetadata::find(&in_dir, false, false)?;
+        if let Some(metadata) = &metadata {
+            args.apply_metadata(metadata);
+        }
+        pack_dir(&args, &ScanArgs::default(), &in_dir, &out_file, metadata.as_ref(), dict.as_deref(), None)
+    })?;
+    Ok(())
+}
+
+/// Extract an archive into a directory like `sarc unzip`, recording its layout in
+/// `.sarctool.yml` so `pack` can rebuild it as it was
+#[pyfunction]
+#[pyo3(signature = (
+    in_file, out_dir, *, metadata=true, byml_to_yaml=false, aamp_to_yaml=false, msbt_to_text=false,
+    decompress_inner=false, flatten=false, dict=None
+))]
+#[allow(clippy::too_many_arguments)]
+fn unpack(
+    py: Python<'_>,
+    in_file: PathBuf,
+    out_dir: PathBuf,
+    metadata: bool,
+    byml_to_yaml: bool,
+    aamp_to_yaml: bool,
+    msbt_to_text: bool,
+    decompress_inner: bool,
+    flatten: bool,
+    dict: Option<PathBuf>,
+) -> PyResult<()> {
+    let options = ExtractOptions {
+        write_metadata: metadata,
+        to_yaml: [(byml_to_yaml, YamlFormat::Byml), (aamp_to_yaml, YamlFormat::Aamp)].iter()
+            .filter(|(enabled, _)| *enabled)
+            .map(|&(_, format)| format)
+            .collect(),
+        msbt_to_text,
+        decompress_inner,
+        flatten,
+        ..ExtractOptions::default()
+    };
+    py.detach(|| extract::unzip(&in_file, &out_dir, &options, dict.as_deref()))?;
+    Ok(())
+}
+
+/// The entries of an archive as dicts of their `name` (None if the archive has none), `hash`,
+/// `offset`, `type` and `data`
+#[pyfunction]
+#[pyo3(signature = (in_file, *, dict=None))]
+fn list<'py>(py: Python<'py>, in_file: PathBuf, dict: Option<PathBuf>) -> PyResult<Vec<Bound<'py, PyDict>>> {
+    let archive = py.detach(|| Archive::open(&in_file, dict.as_deref()))?;
+    archive.entries()
+        .map(|entry| {
+            let dict = PyDict::new(py);
+            dict.set_item("name", entry.name)?;
+            dict.set_item("hash", entry.hash)?;
+            dict.set_item("offset", entry.offset)?;
+            dict.set_item("type", FileType::detect(entry.data).map(FileType::name))?;
+            dict.set_item("data", PyBytes::new(py, entry.data))?;
+            Ok(dict)
+        })
+        .collect()
+}
+
+/// Changes from a vanilla archive, or the matching one in a dump, like `sarc diff`: `(change,
+/// name)` pairs where the change is `"A"`, `"M"` or `"D"`
+#[pyfunction]
+#[pyo3(signature = (in_file, against, *, dict=None))]
+fn diff(py: Python<'_>, in_file: PathBuf, against: PathBuf, dict: Option<PathBuf>) -> PyResult<Vec<(String, String)>> {
+    let changes = py.detach(|| -> Result<_, ConvertError> {
+        let vanilla = Archive::open(&inspect::vanilla_archive(&against, &in_file)?, dict.as_deref())?;
+        let modded = Archive::open(&in_file, dict.as_deref())?;
+        Ok(inspect::diff(&modded, &vanilla))
+    })?;
+    Ok(changes.into_iter().map(|(change, name)| (change.letter().to_string(), name)).collect())
+}
+
+#[pymodule]
+fn sarctool(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add("SarcError", m.py().get_type::<SarcError>())?;
+    m.add_function(wrap_pyfunction!(pack, m)?)?;
+    m.add_function(wrap_pyfunction!(unpack, m)?)?;
+    m.add_function(wrap_pyfunction!(list, m)?)?;
+    m.add_function(wrap_pyfunction!(diff, m)?)?;
+    Ok(())
+}
@@ -0,0 +1,89 @@
+//! Finding the files in a directory for `zip` to pack
+use std::path::{Path, PathBuf};
+
+use structopt::StructOpt;
+use walkdir::{DirEntry, WalkDir};
+
+use crate::cache::CACHE_DIR;
+use crate::ConvertError;
+
+#[derive(StructOpt, Debug, Clone, Default)]
+pub struct ScanArgs {
+    /// Leave out files and directories matching this glob, relative to the directory. Can be
+    /// given more than once
+    #[structopt(long, number_of_values = 1)]
+    pub exclude: Vec<glob::Pattern>,
+    /// Also pack files and directories whose names start with a dot
+    #[structopt(long)]
+    pub include_hidden: bool,
+    /// Pack what symlinks point to, the default
+    #[structopt(long, overrides_with = "no-follow-symlinks")]
+    pub follow_symlinks: bool,
+    /// Leave symlinks out of the archive
+    #[structopt(long, overrides_with = "follow-symlinks")]
+    pub no_follow_symlinks: bool,
+}
+
+pub struct ScannedFile {
+    pub path: PathBuf,
+    /// Path relative to the directory, with `/` as the separator
+    pub name: String,
+}
+
+fn archive_name(dir: &Path, path: &Path) -> String {
+    path.strip_prefix(dir).unwrap_or(path).to_string_lossy().replace("\\", "/")
+}
+
+impl ScanArgs {
+    /// Whichever of the two flags came last wins
+    fn follow_symlinks(&self) -> bool {
+        self.follow_symlinks || !self.no_follow_symlinks
+    }
+
+    pub fn is_excluded(&self, name: &str) -> bool {
+        self.exclude.iter().any(|pattern| pattern.matches(name))
+    }
+
+    fn keep(&self, dir: &Path, entry: &DirEntry) -> bool {
+        if entry.depth() == 0 {
+            return true
+        }
+        if entry.depth() == 1 && entry.file_name() == CACHE_DIR {
+            return false
+        }
+        let hidden = entry.file_name().to_string_lossy().starts_with('.');
+        if hidden && !self.include_hidden {
+            log::debug!("Skipping hidden {}", entry.path().display());
+            return false
+        }
+        !self.is_excluded(&archive_name(dir, entry.path()))
+    }
+
+    /// Every file to pack, sorted by name so archives don't depend on the order the file system
+    /// happens to list them in
+    pub fn files(&self, dir: &Path) -> Result<Vec<ScannedFile>, ConvertError> {
+        let follow_symlinks = self.follow_symlinks();
+        let walk = WalkDir::new(dir)
+            .follow_links(follow_symlinks)
+            .sort_by_file_name()
+            .into_iter()
+            .filter_entry(|entry| self.keep(dir, entry));
+
+        let mut files = vec![];
+        for entry in walk {
+            // unreadable directories and symlink loops
+            let entry = entry.map_err(|err| ConvertError::file(&err.to_string()))?;
+            if entry.path_is_symlink() && !follow_symlinks {
+                log::info!("Skipping symlink {}", entry.path().display());
+                continue
+            }
+            if entry.file_type().is_file() {
+                files.push(ScannedFile {
+                    name: archive_name(dir, entry.path()),
+                    path: entry.into_path(),
+                });
+            }
+        }
+        Ok(files)
+    }
+}
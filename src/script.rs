@@ -0,0 +1,71 @@
+//! Build scripts for `sarc run`: YAML files listing the commands of a build, each one the
+//! arguments of `sarc` as a line or a list, along with options given to all of them
+//!
+//! ```yaml
+//! # after the arguments of every step, so only options every command takes, like --dict
+//! options: --dict ZsDic.pack.zs --force
+//! steps:
+//!   - unzip vanilla/Foo.sbactorpack build/Foo
+//!   - zip build/Foo romfs/Actor/Pack/Foo.sbactorpack --yaz0
+//!   - [merge-mods, --base, vanilla, --out, romfs, "mods/A Mod", mods/Other]
+//! ```
+//!
+//! Paths are relative to the script, and the first step that fails stops the build.
+use std::fs;
+use std::path::Path;
+
+use serde::Deserialize;
+
+use sarctool::ConvertError;
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Arguments {
+    /// Split like a shell would, so arguments with spaces can be quoted
+    Line(String),
+    List(Vec<String>),
+}
+
+impl Default for Arguments {
+    fn default() -> Self {
+        Arguments::List(vec![])
+    }
+}
+
+impl Arguments {
+    fn split(&self) -> Option<Vec<String>> {
+        match self {
+            Arguments::Line(line) => shlex::split(line),
+            Arguments::List(list) => Some(list.clone()),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Script {
+    #[serde(default)]
+    options: Arguments,
+    steps: Vec<Arguments>,
+}
+
+impl Script {
+    pub fn load(path: &Path) -> Result<Self, ConvertError> {
+        let text = fs::read_to_string(path).map_err(|err| ConvertError::io(path, err))?;
+        serde_yaml::from_str(&text).map_err(|err| ConvertError::param(&format!("{}: {}", path.display(), err)))
+    }
+
+    /// The arguments of each step, with the shared options after them
+    pub fn steps(&self, path: &Path) -> Result<Vec<Vec<String>>, ConvertError> {
+        let unclosed = |what: String| ConvertError::param(&format!("{}: {} has an unclosed quote", path.display(), what));
+        let options = self.options.split().ok_or_else(|| unclosed("options".to_owned()))?;
+        self.steps.iter()
+            .enumerate()
+            .map(|(i, step)| {
+                let mut args = step.split().ok_or_else(|| unclosed(format!("step {}", i + 1)))?;
+                args.extend(options.iter().cloned());
+                Ok(args)
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,42 @@
+//! Patterns for `grep`, searching the bytes of entries
+use regex::bytes::{Regex, RegexBuilder};
+
+use crate::ConvertError;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PatternKind {
+    Text,
+    Regex,
+    Hex,
+}
+
+pub struct Pattern {
+    regex: Regex,
+}
+
+impl Pattern {
+    /// Every kind is turned into a regex, matched against raw bytes rather than UTF-8 text
+    pub fn new(pattern: &str, kind: PatternKind) -> Result<Self, ConvertError> {
+        let regex = match kind {
+            PatternKind::Text => RegexBuilder::new(&regex::escape(pattern)).build(),
+            PatternKind::Regex => RegexBuilder::new(pattern).build(),
+            PatternKind::Hex => RegexBuilder::new(&hex_regex(pattern)?).unicode(false).build(),
+        };
+        let regex = regex.map_err(|err| ConvertError::param(&err.to_string()))?;
+        Ok(Pattern { regex })
+    }
+
+    /// Offsets of every match, not counting overlapping ones
+    pub fn offsets(&self, data: &[u8]) -> Vec<usize> {
+        self.regex.find_iter(data).map(|found| found.start()).collect()
+    }
+}
+
+/// `DE AD be ef` or `deadbeef` as a regex matching those bytes
+fn hex_regex(pattern: &str) -> Result<String, ConvertError> {
+    let digits: Vec<char> = pattern.chars().filter(|c| !c.is_whitespace()).collect();
+    if digits.is_empty() || !digits.len().is_multiple_of(2) || !digits.iter().all(char::is_ascii_hexdigit) {
+        return Err(ConvertError::param(&format!("'{}' is not a sequence of hex bytes", pattern)))
+    }
+    Ok(digits.chunks(2).map(|byte| format!(r"\x{}{}", byte[0], byte[1])).collect())
+}
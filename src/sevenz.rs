@@ -0,0 +1,46 @@
+//! 7z archives for `into-7z` and `from-7z`
+use std::io::{Cursor, Seek, Write};
+
+use sevenz_rust::{Password, SevenZArchiveEntry, SevenZReader, SevenZWriter};
+
+use crate::archive::Entry;
+use crate::layout::DEFAULT_HASH_KEY;
+use crate::writer::WriterEntry;
+use crate::{progress, ConvertError};
+
+/// Compress the entries with LZMA2, finishing the archive so its header is written
+pub fn write_7z<'a, W, I>(entries: I, out: W) -> Result<W, ConvertError>
+    where W: Write + Seek, I: ExactSizeIterator<Item = Entry<'a>>
+{
+    let mut archive = SevenZWriter::new(out)?;
+    let progress = progress::entries(entries.len(), "Converting");
+    for (i, file) in entries.enumerate() {
+        progress.inc(1);
+        let name = file.name.map(String::from).unwrap_or_else(|| format!("{}.bin", i));
+        log::info!("Adding {}", name);
+        let mut entry = SevenZArchiveEntry::new();
+        entry.name = name;
+        entry.has_stream = !file.data.is_empty();
+        let data = if entry.has_stream { Some(file.data) } else { None };
+        archive.push_archive_entry(entry, data)?;
+    }
+    progress.finish_and_clear();
+    Ok(archive.finish()?)
+}
+
+pub fn read_7z(data: &[u8]) -> Result<Vec<WriterEntry>, ConvertError> {
+    let mut archive = SevenZReader::new(Cursor::new(data), data.len() as u64, Password::empty())?;
+    let mut files = vec![];
+    archive.for_each_entries(|entry, reader| {
+        if entry.is_directory() {
+            return Ok(true)
+        }
+        let mut data = vec![];
+        reader.read_to_end(&mut data)?;
+        let name = entry.name().replace("\\", "/");
+        log::info!("Adding {}", name);
+        files.push(WriterEntry::named(name, data, DEFAULT_HASH_KEY));
+        Ok(true)
+    })?;
+    Ok(files)
+}
@@ -0,0 +1,204 @@
+//! `-` as a path, for reading archives from stdin and writing outputs to stdout, `--dry-run`,
+//! which lists what would be written instead of writing it, what to do with outputs that
+//! already exist, and `--reproducible`. Every output goes through here so none of them get
+//! around either.
+use std::fs;
+use std::io::{self, BufWriter, Read, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+use std::time::SystemTime;
+
+use crate::{progress, ConvertError};
+
+static DRY_RUN: AtomicBool = AtomicBool::new(false);
+
+pub fn set_dry_run(dry_run: bool) {
+    DRY_RUN.store(dry_run, Ordering::Relaxed);
+}
+
+pub fn is_dry_run() -> bool {
+    DRY_RUN.load(Ordering::Relaxed)
+}
+
+static REPRODUCIBLE: AtomicBool = AtomicBool::new(false);
+
+/// Make outputs depend on nothing but their inputs, not on when or where they're written
+pub fn set_reproducible(reproducible: bool) {
+    REPRODUCIBLE.store(reproducible, Ordering::Relaxed);
+}
+
+pub fn is_reproducible() -> bool {
+    REPRODUCIBLE.load(Ordering::Relaxed)
+}
+
+/// What to do with an output that already exists
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Existing {
+    /// Fail, which `sarc` does unless given `--force`
+    Refuse,
+    /// Replace it, which the library does unless told otherwise
+    Overwrite,
+    /// Leave it as it is, for `--skip-existing`
+    Skip,
+}
+
+static EXISTING: AtomicU8 = AtomicU8::new(Existing::Overwrite as u8);
+
+pub fn set_existing(existing: Existing) {
+    EXISTING.store(existing as u8, Ordering::Relaxed);
+}
+
+pub fn existing() -> Existing {
+    match EXISTING.load(Ordering::Relaxed) {
+        0 => Existing::Refuse,
+        1 => Existing::Overwrite,
+        _ => Existing::Skip,
+    }
+}
+
+/// Whether `path` should be written, failing if it exists and mustn't be replaced
+pub fn should_write(path: &Path) -> Result<bool, ConvertError> {
+    if is_stdio(path) || !path.exists() {
+        return Ok(true)
+    }
+    match existing() {
+        Existing::Overwrite => Ok(true),
+        Existing::Refuse => Err(ConvertError::file(&format!(
+            "{} already exists, pass --force to overwrite it or --skip-existing to keep it", path.display()
+        ))),
+        Existing::Skip => {
+            if is_dry_run() {
+                progress::suspend(|| println!("Would skip {}, which already exists", path.display()));
+            } else {
+                log::info!("Skipping {}, which already exists", path.display());
+            }
+            Ok(false)
+        }
+    }
+}
+
+/// Print what writing `len` bytes to `path` would do
+fn report(path: &Path, len: u64) {
+    let line = if is_stdio(path) {
+        format!("Would write {} bytes to stdout", len)
+    } else {
+        match fs::metadata(path) {
+            Ok(metadata) if metadata.is_file() => format!(
+                "Would replace {} ({} bytes, was {} bytes)", path.display(), len, metadata.len()
+            ),
+            _ => format!("Would write {} ({} bytes)", path.display(), len),
+        }
+    };
+    progress::suspend(|| println!("{}", line));
+}
+
+/// Counts what's written to it, so a dry run knows how big an output would be
+#[derive(Default)]
+struct Counter(u64);
+
+impl Write for Counter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0 += buf.len() as u64;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+pub fn is_stdio(path: &Path) -> bool {
+    path == Path::new("-")
+}
+
+pub fn read(path: &Path) -> Result<Vec<u8>, ConvertError> {
+    if is_stdio(path) {
+        let mut data = vec![];
+        std::io::stdin().lock().read_to_end(&mut data).map_err(|err| ConvertError::io(Path::new("stdin"), err))?;
+        Ok(data)
+    } else {
+        fs::read(path).map_err(|err| ConvertError::io(path, err))
+    }
+}
+
+pub fn write(path: &Path, data: &[u8]) -> Result<(), ConvertError> {
+    if !should_write(path)? {
+        return Ok(())
+    }
+    if is_dry_run() {
+        report(path, data.len() as u64);
+        return Ok(())
+    }
+    if is_stdio(path) {
+        let mut stdout = std::io::stdout().lock();
+        stdout.write_all(data)
+            .and_then(|_| stdout.flush())
+            .map_err(|err| ConvertError::io(Path::new("stdout"), err))
+    } else {
+        fs::write(path, data).map_err(|err| ConvertError::io(path, err))
+    }
+}
+
+/// Write the output in pieces with `write`. A file left half written by a failure is removed.
+pub fn create<F>(path: &Path, write: F) -> Result<(), ConvertError>
+    where F: FnOnce(&mut dyn Write) -> Result<(), ConvertError>
+{
+    if !should_write(path)? {
+        return Ok(())
+    }
+    if is_dry_run() {
+        // written out all the same, to know its size
+        let mut counter = Counter::default();
+        write(&mut counter)?;
+        report(path, counter.0);
+        return Ok(())
+    }
+    if is_stdio(path) {
+        let mut stdout = std::io::stdout().lock();
+        write(&mut stdout)?;
+        return stdout.flush().map_err(|err| ConvertError::io(Path::new("stdout"), err))
+    }
+
+    let file = fs::File::create(path).map_err(|err| ConvertError::io(path, err))?;
+    let mut out = BufWriter::new(file);
+    let result = write(&mut out)
+        .and_then(|()| out.flush().map_err(|err| ConvertError::io(path, err)));
+    if result.is_err() {
+        drop(out);
+        let _ = fs::remove_file(path);
+    }
+    result
+}
+
+/// Copy a file, like a loose file of a mod
+pub fn copy(from: &Path, to: &Path) -> Result<(), ConvertError> {
+    if !should_write(to)? {
+        return Ok(())
+    }
+    if is_dry_run() {
+        let metadata = fs::metadata(from).map_err(|err| ConvertError::io(from, err))?;
+        report(to, metadata.len());
+        return Ok(())
+    }
+    fs::copy(from, to).map(drop).map_err(|err| ConvertError::io(to, err))
+}
+
+/// Create a directory to write into, which a dry run leaves to be created later
+pub fn create_dir_all(path: &Path) -> Result<(), ConvertError> {
+    if is_dry_run() {
+        return Ok(())
+    }
+    fs::create_dir_all(path).map_err(|err| ConvertError::io(path, err))
+}
+
+/// Give an output the modification time of what it was made from
+pub fn set_modified(path: &Path, time: SystemTime) -> Result<(), ConvertError> {
+    if is_stdio(path) || is_dry_run() {
+        return Ok(())
+    }
+    fs::File::options()
+        .write(true)
+        .open(path)
+        .and_then(|file| file.set_modified(time))
+        .map_err(|err| ConvertError::io(path, err))
+}
@@ -0,0 +1,141 @@
+//! Tarballs, optionally gzip or zstd compressed, for `into-tar` and `from-tar`
+use std::io::{Read, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+use flate2::{read::GzDecoder, write::GzEncoder};
+
+use crate::archive::Entry;
+use crate::compression::ZSTD_MAGIC;
+use crate::layout::DEFAULT_HASH_KEY;
+use crate::writer::WriterEntry;
+use crate::{progress, ConvertError};
+
+pub const TAR_COMPRESSIONS: &[&str] = &["none", "gzip", "zstd"];
+
+const GZIP_MAGIC: &[u8] = b"\x1F\x8B";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TarCompression {
+    None,
+    Gzip,
+    Zstd,
+}
+
+impl FromStr for TarCompression {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(TarCompression::None),
+            "gzip" => Ok(TarCompression::Gzip),
+            "zstd" => Ok(TarCompression::Zstd),
+            _ => Err(format!("unknown tar compression '{}'", s)),
+        }
+    }
+}
+
+impl TarCompression {
+    pub fn from_extension(path: &Path) -> Option<Self> {
+        let name = path.file_name()?.to_string_lossy().to_lowercase();
+        if name.ends_with(".tar") {
+            Some(TarCompression::None)
+        } else if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+            Some(TarCompression::Gzip)
+        } else if name.ends_with(".tar.zst") || name.ends_with(".tzst") {
+            Some(TarCompression::Zstd)
+        } else {
+            None
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            TarCompression::None => "tar",
+            TarCompression::Gzip => "tar.gz",
+            TarCompression::Zstd => "tar.zst",
+        }
+    }
+
+    fn detect(data: &[u8]) -> Self {
+        if data.starts_with(GZIP_MAGIC) {
+            TarCompression::Gzip
+        } else if data.starts_with(ZSTD_MAGIC) {
+            TarCompression::Zstd
+        } else {
+            TarCompression::None
+        }
+    }
+}
+
+pub fn write_tar<'a, I>(entries: I, compression: TarCompression, out: &mut dyn Write) -> Result<(), ConvertError>
+    where I: ExactSizeIterator<Item = Entry<'a>>
+{
+    match compression {
+        TarCompression::None => {
+            append_entries(tar::Builder::new(out), entries)?;
+        }
+        TarCompression::Gzip => {
+            let encoder = GzEncoder::new(out, flate2::Compression::default());
+            append_entries(tar::Builder::new(encoder), entries)?.finish()?;
+        }
+        TarCompression::Zstd => {
+            let encoder = zstd::stream::Encoder::new(out, zstd::DEFAULT_COMPRESSION_LEVEL)
+                .map_err(|err| ConvertError::zstd(err.to_string()))?;
+            append_entries(tar::Builder::new(encoder), entries)?
+                .finish()
+                .map_err(|err| ConvertError::zstd(err.to_string()))?;
+        }
+    }
+    Ok(())
+}
+
+/// Add every entry and finish the tarball, giving back the writer for the compression to be
+/// finished as well
+fn append_entries<'a, W, I>(mut builder: tar::Builder<W>, entries: I) -> Result<W, ConvertError>
+    where W: Write, I: ExactSizeIterator<Item = Entry<'a>>
+{
+    let progress = progress::entries(entries.len(), "Converting");
+    for (i, file) in entries.enumerate() {
+        progress.inc(1);
+        let name = file.name.map(String::from).unwrap_or_else(|| format!("{}.bin", i));
+        log::info!("Adding {}", name);
+        let mut header = tar::Header::new_gnu();
+        header.set_size(file.data.len() as u64);
+        header.set_mode(0o644);
+        header.set_entry_type(tar::EntryType::Regular);
+        builder.append_data(&mut header, &name, file.data)?;
+    }
+    progress.finish_and_clear();
+    Ok(builder.into_inner()?)
+}
+
+pub fn read_tar(data: &[u8]) -> Result<Vec<WriterEntry>, ConvertError> {
+    match TarCompression::detect(data) {
+        TarCompression::None => read_entries(data),
+        TarCompression::Gzip => read_entries(GzDecoder::new(data)),
+        TarCompression::Zstd => {
+            let decoder = zstd::stream::Decoder::new(data).map_err(|err| ConvertError::zstd(err.to_string()))?;
+            read_entries(decoder)
+        }
+    }
+}
+
+fn read_entries<R: Read>(reader: R) -> Result<Vec<WriterEntry>, ConvertError> {
+    let mut archive = tar::Archive::new(reader);
+    let mut files = vec![];
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        // directories and links have nothing to pack
+        if !entry.header().entry_type().is_file() {
+            continue
+        }
+        let name = entry.path()?.to_string_lossy().replace("\\", "/");
+        let name = name.strip_prefix("./").unwrap_or(&name).to_owned();
+        let mut data = vec![];
+        entry.read_to_end(&mut data)?;
+        log::info!("Adding {}", name);
+        files.push(WriterEntry::named(name, data, DEFAULT_HASH_KEY));
+    }
+    Ok(files)
+}
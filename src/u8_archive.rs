@@ -0,0 +1,257 @@
+//! U8 archives, the predecessor of SARC used by older titles. Instead of a flat table sorted by
+//! hash, they hold a tree of directories with the files in them, always big endian.
+//!
+//! They're read into the same [`Layout`] as SARCs, with the full path of every file as its name,
+//! and written from the same [`SarcWriter`], through [`U8Container`].
+use std::convert::TryInto;
+use std::io::Write;
+
+use sarc::Endian;
+
+use crate::container::{ArchiveContainer, Container};
+use crate::layout::{self, Layout, Node, DEFAULT_HASH_KEY};
+use crate::writer::{self, SarcWriter, WriterEntry};
+use crate::ConvertError;
+
+pub const U8_MAGIC: &[u8] = b"\x55\xAA\x38\x2D";
+/// Alignment of the data of files in the archives Nintendo's tools write
+pub const U8_ALIGNMENT: usize = 0x20;
+
+const HEADER_SIZE: usize = 0x20;
+const NODE_SIZE: usize = 0xC;
+const DIRECTORY: u32 = 1;
+
+pub struct U8Container;
+
+impl ArchiveContainer for U8Container {
+    fn name(&self) -> &'static str {
+        "u8"
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["arc"]
+    }
+
+    fn detect(&self, data: &[u8]) -> bool {
+        data.starts_with(U8_MAGIC)
+    }
+
+    fn parse(&self, data: &[u8]) -> Result<Layout, ConvertError> {
+        parse(data)
+    }
+
+    fn write(&self, archive: &SarcWriter, out: &mut dyn Write) -> Result<(), ConvertError> {
+        write(archive, out)
+    }
+
+    fn file_size(&self, archive: &SarcWriter) -> usize {
+        plan(archive).file_size
+    }
+
+    fn default_alignment(&self) -> usize {
+        U8_ALIGNMENT
+    }
+}
+
+/// Parse the node tree of an uncompressed U8 archive
+fn parse(data: &[u8]) -> Result<Layout, ConvertError> {
+    let u32_at = |offset: usize| {
+        data.get(offset..offset + 4)
+            .map(|bytes| u32::from_be_bytes(bytes.try_into().unwrap()))
+            .ok_or_else(|| ConvertError::sarc(format!("Unexpected end of file at {:#x}", offset)))
+    };
+    if !data.starts_with(U8_MAGIC) {
+        return Err(ConvertError::sarc("Expected U8 magic at 0x0"))
+    }
+    let root = u32_at(4)? as usize;
+    let tables_size = u32_at(8)? as usize;
+    let data_offset = u32_at(0xC)?;
+    let count = u32_at(root + 8)? as usize;
+    if count.checked_mul(NODE_SIZE).is_none_or(|size| root + size > data.len()) {
+        return Err(ConvertError::sarc(format!("{} nodes don't fit in the archive", count)))
+    }
+    let names = data.get(root + count * NODE_SIZE..root + tables_size).unwrap_or_default();
+
+    // directories the current node is in, by the index of the node after their last one
+    let mut dirs: Vec<(usize, String)> = vec![];
+    let mut nodes = vec![];
+    for i in 1..count {
+        while dirs.last().is_some_and(|(end, _)| *end <= i) {
+            dirs.pop();
+        }
+        let node = root + i * NODE_SIZE;
+        let kind_and_name = u32_at(node)?;
+        let name = layout::string_at(names, (kind_and_name & 0xFFFFFF) as usize)
+            .ok_or_else(|| ConvertError::sarc(format!("Name of node {} is outside of the string table", i)))?;
+        let path = format!("{}{}", dirs.last().map_or("", |(_, dir)| dir.as_str()), name);
+        let (offset, size) = (u32_at(node + 4)?, u32_at(node + 8)?);
+        if kind_and_name >> 24 == DIRECTORY {
+            dirs.push((size as usize, path + "/"));
+            continue
+        }
+
+        let start = offset.checked_sub(data_offset).ok_or_else(|| ConvertError::sarc(format!(
+            "Data of {} ({:#x}) is before the data section ({:#x})", path, offset, data_offset
+        )))?;
+        nodes.push(Node {
+            hash: layout::hash(&path, DEFAULT_HASH_KEY),
+            attributes: 0,
+            name: Some(path),
+            data: start..start.saturating_add(size),
+        });
+    }
+
+    Ok(Layout {
+        container: Container::U8,
+        byte_order: Endian::Big,
+        file_size: data.len() as u32,
+        data_offset,
+        hash_key: DEFAULT_HASH_KEY,
+        nodes,
+    })
+}
+
+enum Child<'a> {
+    /// Name and index of the entry
+    File(&'a str, usize),
+    Dir(&'a str, Vec<Child<'a>>),
+}
+
+struct TreeNode<'a> {
+    name: &'a str,
+    entry: Option<usize>,
+    parent: usize,
+    /// Index of the first node that isn't in the directory
+    next: usize,
+}
+
+/// Nodes of the tree in the order they're stored: each directory right before what's in it
+fn flatten<'a>(children: Vec<Child<'a>>, parent: usize, nodes: &mut Vec<TreeNode<'a>>) {
+    for child in children {
+        match child {
+            Child::File(name, entry) => nodes.push(TreeNode { name, entry: Some(entry), parent, next: 0 }),
+            Child::Dir(name, children) => {
+                let index = nodes.len();
+                nodes.push(TreeNode { name, entry: None, parent, next: 0 });
+                flatten(children, index, nodes);
+                nodes[index].next = nodes.len();
+            }
+        }
+    }
+}
+
+struct Plan<'a> {
+    nodes: Vec<TreeNode<'a>>,
+    names: Vec<u8>,
+    name_offsets: Vec<usize>,
+    data_offset: usize,
+    data_order: Vec<usize>,
+    data_ranges: Vec<(usize, usize)>,
+    file_size: usize,
+}
+
+/// Directories are made up from the paths of the entries, in the order they first come up
+fn plan(sarc: &SarcWriter) -> Plan<'_> {
+    let mut root = vec![];
+    for (i, entry) in sarc.entries.iter().enumerate() {
+        let path = entry.name.as_deref().unwrap_or_default();
+        let mut components: Vec<&str> = path.split('/').filter(|component| !component.is_empty()).collect();
+        let name = components.pop().unwrap_or_default();
+        let mut children = &mut root;
+        for dir in components {
+            let found = children.iter().position(|child| matches!(child, Child::Dir(name, _) if *name == dir));
+            let index = found.unwrap_or_else(|| {
+                children.push(Child::Dir(dir, vec![]));
+                children.len() - 1
+            });
+            children = match &mut children[index] {
+                Child::Dir(_, children) => children,
+                Child::File(..) => unreachable!(),
+            };
+        }
+        children.push(Child::File(name, i));
+    }
+    let mut nodes = vec![TreeNode { name: "", entry: None, parent: 0, next: 0 }];
+    flatten(root, 0, &mut nodes);
+    nodes[0].next = nodes.len();
+
+    let mut names = vec![];
+    let name_offsets = nodes.iter()
+        .map(|node| {
+            let offset = names.len();
+            names.extend_from_slice(node.name.as_bytes());
+            names.push(0);
+            offset
+        })
+        .collect();
+
+    let tables_end = HEADER_SIZE + nodes.len() * NODE_SIZE + names.len();
+    let alignment = |entry: &WriterEntry| entry.alignment.unwrap_or(sarc.alignment);
+    let max_alignment = sarc.entries.iter().map(alignment).fold(sarc.alignment, usize::max);
+    let data_offset = match sarc.data_offset {
+        Some(data_offset) if data_offset >= tables_end => data_offset,
+        _ => writer::align(tables_end, max_alignment),
+    };
+
+    // entries without a preferred offset are stored in the order of the tree
+    let mut tree_order = vec![0; sarc.entries.len()];
+    for (position, entry) in nodes.iter().filter_map(|node| node.entry).enumerate() {
+        tree_order[entry] = position;
+    }
+    let entries: Vec<&WriterEntry> = sarc.entries.iter().collect();
+    let (data_order, data_ranges, data_end) = writer::place_data(&entries, &tree_order, sarc.alignment);
+    let file_size = (data_offset + data_end).max(sarc.file_size.unwrap_or(0));
+
+    Plan { nodes, names, name_offsets, data_offset, data_order, data_ranges, file_size }
+}
+
+/// Write the header and node tree, then the data of every entry in order of offset
+fn write(sarc: &SarcWriter, out: &mut dyn Write) -> Result<(), ConvertError> {
+    if let Some(entry) = sarc.entries.iter().find(|entry| entry.name.is_none()) {
+        return Err(ConvertError::param(&format!(
+            "{} has no name, which every file of a U8 archive needs", writer::display_name(entry)
+        )))
+    }
+    let Plan { nodes, names, name_offsets, data_offset, data_order, data_ranges, file_size } = plan(sarc);
+    if names.len() > 0x1000000 {
+        return Err(ConvertError::param("The names of the files don't fit in a U8 archive, the most it can hold is 16 MiB"))
+    }
+    if file_size > u32::MAX as usize {
+        return Err(ConvertError::param(&format!(
+            "{} bytes don't fit in a U8 archive, the most it can hold is 4 GiB", file_size
+        )))
+    }
+
+    let mut tables = Vec::with_capacity(data_offset);
+    tables.extend_from_slice(U8_MAGIC);
+    tables.extend_from_slice(&(HEADER_SIZE as u32).to_be_bytes());
+    tables.extend_from_slice(&((nodes.len() * NODE_SIZE + names.len()) as u32).to_be_bytes());
+    tables.extend_from_slice(&(data_offset as u32).to_be_bytes());
+    tables.resize(HEADER_SIZE, 0);
+    for (node, name_offset) in nodes.iter().zip(name_offsets) {
+        let (kind, offset, size) = match node.entry {
+            Some(entry) => {
+                let (start, end) = data_ranges[entry];
+                (0, data_offset + start, end - start)
+            }
+            None => (DIRECTORY, node.parent, node.next),
+        };
+        tables.extend_from_slice(&(kind << 24 | name_offset as u32).to_be_bytes());
+        tables.extend_from_slice(&(offset as u32).to_be_bytes());
+        tables.extend_from_slice(&(size as u32).to_be_bytes());
+    }
+    tables.extend_from_slice(&names);
+    tables.resize(data_offset, 0);
+    out.write_all(&tables)?;
+
+    let mut pos = data_offset;
+    for i in data_order {
+        let (start, end) = data_ranges[i];
+        writer::write_zeros(out, data_offset + start - pos)?;
+        sarc.entries[i].data.write_to(out)?;
+        pos = data_offset + end;
+    }
+    writer::write_zeros(out, file_size - pos)?;
+
+    Ok(())
+}
@@ -0,0 +1,88 @@
+//! `zip --watch`, repacking an archive whenever its source directory changes
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Mutex};
+use std::time::{Duration, SystemTime};
+
+use notify::{Event, EventKind, RecursiveMode, Watcher};
+
+use crate::ConvertError;
+
+/// How long to wait for an editor to finish saving before repacking
+const DEBOUNCE: Duration = Duration::from_millis(200);
+
+struct CachedFile {
+    modified: SystemTime,
+    len: u64,
+    data: Vec<u8>,
+}
+
+/// Contents of the files read for the previous build, so only the ones that changed are read
+/// again
+#[derive(Default)]
+pub struct FileCache {
+    files: Mutex<HashMap<PathBuf, CachedFile>>,
+}
+
+impl FileCache {
+    pub fn read(&self, path: &Path) -> Result<Vec<u8>, ConvertError> {
+        let metadata = fs::metadata(path).map_err(|err| ConvertError::io(path, err))?;
+        let modified = metadata.modified().map_err(|err| ConvertError::io(path, err))?;
+
+        if let Some(cached) = self.files.lock().unwrap().get(path) {
+            if cached.modified == modified && cached.len == metadata.len() {
+                return Ok(cached.data.clone())
+            }
+        }
+
+        log::debug!("Reading {}", path.display());
+        let data = fs::read(path).map_err(|err| ConvertError::io(path, err))?;
+        let cached = CachedFile { modified, len: metadata.len(), data: data.clone() };
+        self.files.lock().unwrap().insert(path.to_owned(), cached);
+        Ok(data)
+    }
+}
+
+fn watch_error(err: notify::Error) -> ConvertError {
+    ConvertError::file(&format!("Failed to watch for changes: {}", err))
+}
+
+/// Call `rebuild` every time something in `dir` changes, other than `out_file` itself. Only
+/// returns if watching fails, errors from `rebuild` are logged so a half-saved file doesn't end
+/// the session.
+pub fn watch<F>(dir: &Path, out_file: &Path, mut rebuild: F) -> Result<(), ConvertError>
+    where F: FnMut() -> Result<(), ConvertError>
+{
+    let (tx, rx) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(tx).map_err(watch_error)?;
+    watcher.watch(dir, RecursiveMode::Recursive).map_err(watch_error)?;
+    let out_file = fs::canonicalize(out_file).unwrap_or_else(|_| out_file.to_owned());
+
+    let is_change = |event: notify::Result<Event>| match event {
+        Ok(event) => {
+            !matches!(event.kind, EventKind::Access(_))
+                && event.paths.iter().any(|path| fs::canonicalize(path).as_ref().unwrap_or(path) != &out_file)
+        }
+        Err(err) => {
+            log::warn!("{}", err);
+            false
+        }
+    };
+
+    println!("Watching {} for changes", dir.display());
+    loop {
+        let mut changed = is_change(rx.recv().map_err(|_| ConvertError::file("File watcher stopped"))?);
+        while let Ok(event) = rx.recv_timeout(DEBOUNCE) {
+            changed |= is_change(event);
+        }
+        if !changed {
+            continue
+        }
+
+        match rebuild() {
+            Ok(()) => println!("Rebuilt {}", out_file.display()),
+            Err(err) => log::error!("{}", err),
+        }
+    }
+}
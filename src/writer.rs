@@ -0,0 +1,317 @@
+//! SARC writer which, unlike `sarc::SarcFile::write`, keeps the hashes of unnamed entries,
+//! allows a custom hash key and can reproduce the data layout of an existing archive
+//!
+//! The tables only depend on the sizes of the entries, so entries can also be left on disk and
+//! copied straight into the output once it's written.
+use std::fs::File;
+use std::io::{self, Cursor, Write};
+use std::path::PathBuf;
+
+use sarc::Endian;
+use zip::ZipArchive;
+
+use crate::archive::SharedData;
+
+use crate::container::Container;
+use crate::layout::{self, SARC_HEADER_SIZE, SFAT_HEADER_SIZE, SFAT_NODE_SIZE, SFNT_HEADER_SIZE};
+use crate::ConvertError;
+
+pub const DEFAULT_ALIGNMENT: usize = 0x2000;
+
+pub enum EntryData {
+    Bytes(Vec<u8>),
+    /// Only read once the archive is written
+    File { path: PathBuf, len: usize },
+    /// Only decompressed once the archive is written, `len` being the size the zip claims
+    Zip { zip: ZipArchive<Cursor<SharedData>>, index: usize, len: usize },
+}
+
+impl EntryData {
+    pub fn len(&self) -> usize {
+        match self {
+            EntryData::Bytes(data) => data.len(),
+            EntryData::File { len, .. } | EntryData::Zip { len, .. } => *len,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The data in memory, reading it if it's still in a file
+    pub fn into_bytes(self) -> Result<Vec<u8>, ConvertError> {
+        match self {
+            EntryData::Bytes(data) => Ok(data),
+            _ => {
+                let mut data = Vec::with_capacity(self.len());
+                self.write_to(&mut data)?;
+                Ok(data)
+            }
+        }
+    }
+
+    pub(crate) fn write_to(&self, out: &mut dyn Write) -> Result<(), ConvertError> {
+        match self {
+            EntryData::Bytes(data) => out.write_all(data).map_err(ConvertError::from),
+            EntryData::File { path, len } => {
+                let file = File::open(path).map_err(|err| ConvertError::io(path, err))?;
+                let copied = io::copy(&mut io::Read::take(file, *len as u64), out)
+                    .map_err(|err| ConvertError::io(path, err))?;
+                if copied != *len as u64 {
+                    return Err(ConvertError::file("file changed while packing").in_file(path))
+                }
+                Ok(())
+            }
+            EntryData::Zip { zip, index, len } => {
+                let mut zip = zip.clone();
+                let file = zip.by_index(*index)?;
+                let name = file.name().to_owned();
+                // a byte more than claimed is read, so that a file larger than that is noticed,
+                // and so is a bad CRC, which is only checked at the end of the file
+                let copied = io::copy(&mut io::Read::take(file, *len as u64 + 1), out)
+                    .map_err(|err| ConvertError::from(err).in_entry(&name))?;
+                if copied != *len as u64 {
+                    return Err(ConvertError::file("size differs from the one in the zip").in_entry(&name))
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl From<Vec<u8>> for EntryData {
+    fn from(data: Vec<u8>) -> Self {
+        EntryData::Bytes(data)
+    }
+}
+
+pub struct WriterEntry {
+    pub name: Option<String>,
+    pub hash: u32,
+    pub data: EntryData,
+    /// Preferred offset relative to the data section, used as long as it doesn't overlap the
+    /// data before it
+    pub offset: Option<usize>,
+    /// Alignment of the data, if different from the archive's default
+    pub alignment: Option<usize>,
+}
+
+impl WriterEntry {
+    pub fn named<D: Into<EntryData>>(name: String, data: D, hash_key: u32) -> Self {
+        WriterEntry {
+            hash: layout::hash(&name, hash_key),
+            name: Some(name),
+            data: data.into(),
+            offset: None,
+            alignment: None,
+        }
+    }
+}
+
+pub struct SarcWriter {
+    /// Kind of archive written, which may need every entry to have a name
+    pub container: Container,
+    pub byte_order: Endian,
+    pub hash_key: u32,
+    pub alignment: usize,
+    pub entries: Vec<WriterEntry>,
+    /// Minimum offset of the data section
+    pub data_offset: Option<usize>,
+    /// Minimum size of the archive, any space after the last entry is zero-filled
+    pub file_size: Option<usize>,
+}
+
+pub fn display_name(entry: &WriterEntry) -> String {
+    entry.name.clone().unwrap_or_else(|| format!("[{:08X}]", entry.hash))
+}
+
+pub(crate) fn align(offset: usize, alignment: usize) -> usize {
+    offset.div_ceil(alignment) * alignment
+}
+
+/// Where the data of each entry goes relative to the data section, along with the order it's
+/// written in and where it ends. Data is stored in order of preferred offset, entries without
+/// one go at the end in the order of `ties`.
+pub(crate) fn place_data(
+    entries: &[&WriterEntry], ties: &[usize], default_alignment: usize
+) -> (Vec<usize>, Vec<(usize, usize)>, usize) {
+    let mut data_order: Vec<usize> = (0..entries.len()).collect();
+    data_order.sort_by_key(|&i| (entries[i].offset.unwrap_or(usize::MAX), ties[i]));
+
+    let mut data_ranges = vec![(0, 0); entries.len()];
+    let mut data_end = 0;
+    for i in data_order.iter().copied() {
+        let start = match entries[i].offset {
+            Some(offset) if offset >= data_end => offset,
+            _ => align(data_end, entries[i].alignment.unwrap_or(default_alignment)),
+        };
+        data_end = start + entries[i].data.len();
+        data_ranges[i] = (start, data_end);
+    }
+    (data_order, data_ranges, data_end)
+}
+
+struct EndianWriter<'a> {
+    out: &'a mut Vec<u8>,
+    big_endian: bool,
+}
+
+impl<'a> EndianWriter<'a> {
+    fn u16(&mut self, val: u16) {
+        self.out.extend_from_slice(&if self.big_endian { val.to_be_bytes() } else { val.to_le_bytes() });
+    }
+
+    fn u32(&mut self, val: u32) {
+        self.out.extend_from_slice(&if self.big_endian { val.to_be_bytes() } else { val.to_le_bytes() });
+    }
+}
+
+impl SarcWriter {
+    pub fn new(byte_order: Endian, entries: Vec<WriterEntry>) -> Self {
+        SarcWriter {
+            container: Container::SARC,
+            byte_order,
+            hash_key: layout::DEFAULT_HASH_KEY,
+            alignment: DEFAULT_ALIGNMENT,
+            entries,
+            data_offset: None,
+            file_size: None,
+        }
+    }
+
+    /// Where everything goes, with the SFAT sorted by hash as the games expect
+    fn plan(&self) -> Plan<'_> {
+        let mut sfat_order: Vec<usize> = (0..self.entries.len()).collect();
+        sfat_order.sort_by_key(|&i| self.entries[i].hash);
+        let entries: Vec<&WriterEntry> = sfat_order.iter().map(|&i| &self.entries[i]).collect();
+
+        let mut names = vec![];
+        let name_offsets: Vec<Option<usize>> = entries.iter()
+            .map(|entry| {
+                entry.name.as_ref().map(|name| {
+                    let offset = names.len();
+                    names.extend_from_slice(name.as_bytes());
+                    names.push(0);
+                    names.resize(align(names.len(), 4), 0);
+                    offset
+                })
+            })
+            .collect();
+
+        let tables_end = SARC_HEADER_SIZE + SFAT_HEADER_SIZE + entries.len() * SFAT_NODE_SIZE
+            + SFNT_HEADER_SIZE + names.len();
+        // aligning the data section to the largest alignment keeps the offsets relative to it
+        // aligned in the file as well
+        let alignment = |entry: &WriterEntry| entry.alignment.unwrap_or(self.alignment);
+        let max_alignment = entries.iter().map(|entry| alignment(entry)).fold(self.alignment, usize::max);
+        let data_offset = match self.data_offset {
+            Some(data_offset) if data_offset >= tables_end => data_offset,
+            _ => align(tables_end, max_alignment),
+        };
+
+        // entries without a preferred offset go in the order they were added
+        let (data_order, data_ranges, data_end) = place_data(&entries, &sfat_order, self.alignment);
+        let file_size = (data_offset + data_end).max(self.file_size.unwrap_or(0));
+
+        Plan { entries, names, name_offsets, data_offset, data_order, data_ranges, file_size }
+    }
+
+    /// Size of the serialized archive
+    pub fn file_size(&self) -> usize {
+        self.container.file_size(self)
+    }
+
+    /// Write the archive as its container does
+    pub fn write(&self, out: &mut dyn Write) -> Result<(), ConvertError> {
+        self.container.write(self, out)
+    }
+
+    pub(crate) fn sarc_file_size(&self) -> usize {
+        self.plan().file_size
+    }
+
+    /// Write the tables, then the data of every entry in order of offset
+    pub(crate) fn write_sarc(&self, out: &mut dyn Write) -> Result<(), ConvertError> {
+        let Plan { entries, names, name_offsets, data_offset, data_order, data_ranges, file_size } = self.plan();
+        // the header and SFAT have no room for more, and would silently wrap around
+        if entries.len() > u16::MAX as usize {
+            return Err(ConvertError::param(&format!(
+                "{} entries don't fit in a SARC, the most it can hold is {}", entries.len(), u16::MAX
+            )))
+        }
+        if file_size > u32::MAX as usize {
+            return Err(ConvertError::param(&format!(
+                "{} bytes don't fit in a SARC, the most it can hold is 4 GiB", file_size
+            )))
+        }
+
+        let mut tables = Vec::with_capacity(data_offset);
+        let mut writer = EndianWriter { out: &mut tables, big_endian: matches!(self.byte_order, Endian::Big) };
+
+        writer.out.extend_from_slice(b"SARC");
+        writer.u16(SARC_HEADER_SIZE as u16);
+        writer.u16(0xFEFF);
+        writer.u32(file_size as u32);
+        writer.u32(data_offset as u32);
+        writer.u16(0x0100);
+        writer.u16(0);
+
+        writer.out.extend_from_slice(b"SFAT");
+        writer.u16(SFAT_HEADER_SIZE as u16);
+        writer.u16(entries.len() as u16);
+        writer.u32(self.hash_key);
+        let mut collisions = 0;
+        for (i, ((entry, name_offset), (start, end))) in entries.iter().zip(&name_offsets).zip(&data_ranges).enumerate() {
+            // entries sharing a hash are told apart by a 1-based index in the top byte
+            collisions = if i > 0 && entries[i - 1].hash == entry.hash { collisions + 1 } else { 0 };
+            writer.u32(entry.hash);
+            writer.u32(
+                name_offset
+                    .map(|offset| ((collisions + 1) << 24) | (offset / 4) as u32)
+                    .unwrap_or(0)
+            );
+            writer.u32(*start as u32);
+            writer.u32(*end as u32);
+        }
+
+        writer.out.extend_from_slice(b"SFNT");
+        writer.u16(SFNT_HEADER_SIZE as u16);
+        writer.u16(0);
+        writer.out.extend_from_slice(&names);
+        tables.resize(data_offset, 0);
+        out.write_all(&tables)?;
+
+        let mut pos = data_offset;
+        for i in data_order {
+            let (start, end) = data_ranges[i];
+            if entries[i].offset.is_some_and(|offset| offset != start) {
+                log::debug!("{}: preferred offset overlaps the previous entry", display_name(entries[i]));
+            }
+            log::debug!(
+                "{}: data at {:#x}..{:#x}, aligned to {:#x}", display_name(entries[i]),
+                data_offset + start, data_offset + end, entries[i].alignment.unwrap_or(self.alignment)
+            );
+            write_zeros(out, data_offset + start - pos)?;
+            entries[i].data.write_to(out)?;
+            pos = data_offset + end;
+        }
+        write_zeros(out, file_size - pos)?;
+
+        Ok(())
+    }
+}
+
+struct Plan<'a> {
+    entries: Vec<&'a WriterEntry>,
+    names: Vec<u8>,
+    name_offsets: Vec<Option<usize>>,
+    data_offset: usize,
+    data_order: Vec<usize>,
+    data_ranges: Vec<(usize, usize)>,
+    file_size: usize,
+}
+
+pub(crate) fn write_zeros(out: &mut dyn Write, len: usize) -> Result<(), ConvertError> {
+    io::copy(&mut io::Read::take(io::repeat(0), len as u64), out)?;
+    Ok(())
+}
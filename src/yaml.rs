@@ -0,0 +1,115 @@
+//! BYML and AAMP entries as editable YAML, for `unzip --byml-to-yaml`/`--aamp-to-yaml` and
+//! converting them back on `zip`
+//!
+//! The YAML is the same as what oead and the tools built on it write, so files can be moved
+//! between them.
+use roead::aamp::ParameterIO;
+use roead::byml::Byml;
+use sarc::Endian;
+
+use crate::filetype::FileType;
+use crate::ConvertError;
+
+/// Extensions of BYML files that get converted
+const BYML_EXTENSIONS: &[&str] = &[
+    "byml", "bgdata", "bgsvdata", "baischedule", "baniminfo", "bmapopen", "bmaptex", "bquestpack", "bwinfo",
+];
+
+/// Extensions of AAMP parameter files that get converted
+const AAMP_EXTENSIONS: &[&str] = &[
+    "bxml", "baiprog", "bgparamlist", "bas", "baslist", "batcl", "batcllist", "bawareness", "bchemical",
+    "bdemo", "bdgnenv", "bdmgparam", "bdrop", "bgapkginfo", "bgapkglist", "bgenv", "bglght", "bgmsconf",
+    "bgsdw", "bksky", "blifecondition", "blod", "bmodellist", "bmscdef", "bmscinfo", "bnetfp", "bphysics",
+    "bphyssb", "brecipe", "brgbw", "brgcon", "brgconfig", "brgconfiglist", "bsfbt", "bsft", "bshop",
+    "bumii", "bvege", "bactcapt",
+];
+
+/// Used for new files, the version of the BYML in Breath of the Wild
+pub const DEFAULT_BYML_VERSION: u16 = 2;
+
+const YAML_EXTENSION: &str = ".yml";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum YamlFormat {
+    Byml,
+    Aamp,
+}
+
+impl YamlFormat {
+    fn extensions(self) -> &'static [&'static str] {
+        match self {
+            YamlFormat::Byml => BYML_EXTENSIONS,
+            YamlFormat::Aamp => AAMP_EXTENSIONS,
+        }
+    }
+
+    fn file_type(self) -> FileType {
+        match self {
+            YamlFormat::Byml => FileType::Byml,
+            YamlFormat::Aamp => FileType::Aamp,
+        }
+    }
+
+    fn from_extension(name: &str) -> Option<Self> {
+        let (_, extension) = name.rsplit_once('.')?;
+        let extension = extension.to_lowercase();
+        [YamlFormat::Byml, YamlFormat::Aamp].iter().copied()
+            .find(|format| format.extensions().contains(&extension.as_str()))
+    }
+
+    /// The format of an entry, going by both its name and its data
+    pub fn detect(name: &str, data: &[u8]) -> Option<Self> {
+        YamlFormat::from_extension(name).filter(|format| FileType::detect(data) == Some(format.file_type()))
+    }
+
+    /// The format of an extracted YAML file and the name of the entry it's converted back to
+    pub fn from_yaml_path(path: &str) -> Option<(Self, &str)> {
+        let name = path.strip_suffix(YAML_EXTENSION)?;
+        YamlFormat::from_extension(name).map(|format| (format, name))
+    }
+
+    pub fn to_yaml(self, data: &[u8]) -> Result<String, ConvertError> {
+        match self {
+            YamlFormat::Byml => Ok(Byml::from_binary(data).map_err(|err| self.error(err))?.to_text()),
+            YamlFormat::Aamp => Ok(ParameterIO::from_binary(data).map_err(|err| self.error(err))?.to_text()),
+        }
+    }
+
+    /// BYML is written in the given byte order and version, AAMP is always little endian
+    pub fn to_binary(self, text: &str, endian: Endian, byml_version: u16) -> Result<Vec<u8>, ConvertError> {
+        match self {
+            YamlFormat::Byml => {
+                let endian = match endian {
+                    Endian::Big => roead::Endian::Big,
+                    Endian::Little => roead::Endian::Little,
+                };
+                Ok(Byml::from_text(text).map_err(|err| self.error(err))?.to_binary_with_version(endian, byml_version))
+            }
+            YamlFormat::Aamp => Ok(ParameterIO::from_text(text).map_err(|err| self.error(err))?.to_binary()),
+        }
+    }
+
+    /// ryml spreads its errors over several lines
+    fn error(self, err: roead::Error) -> ConvertError {
+        let message = err.to_string().split_whitespace().collect::<Vec<_>>().join(" ");
+        match self {
+            YamlFormat::Byml => ConvertError::byml(message),
+            YamlFormat::Aamp => ConvertError::aamp(message),
+        }
+    }
+}
+
+/// Path an entry is extracted to as YAML
+pub fn yaml_path(name: &str) -> String {
+    format!("{}{}", name, YAML_EXTENSION)
+}
+
+/// The version from the header of a BYML file, which the YAML doesn't keep
+pub fn byml_version(data: &[u8]) -> u16 {
+    match data {
+        [b'B', b'Y', hi, lo, ..] => u16::from_be_bytes([*hi, *lo]),
+        [b'Y', b'B', lo, hi, ..] => u16::from_le_bytes([*lo, *hi]),
+        _ => DEFAULT_BYML_VERSION,
+    }
+}
+
@@ -0,0 +1,66 @@
+//! How entries are compressed inside of the zips written by `into-zip`
+use std::ops::RangeInclusive;
+use std::str::FromStr;
+
+use zip::{write::FileOptions, CompressionMethod};
+
+use crate::ConvertError;
+
+pub const ZIP_METHODS: &[&str] = &["stored", "deflate", "bzip2", "zstd"];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ZipMethod {
+    Stored,
+    Deflate,
+    Bzip2,
+    Zstd,
+}
+
+impl FromStr for ZipMethod {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "stored" => Ok(ZipMethod::Stored),
+            "deflate" => Ok(ZipMethod::Deflate),
+            "bzip2" => Ok(ZipMethod::Bzip2),
+            "zstd" => Ok(ZipMethod::Zstd),
+            _ => Err(format!("unknown zip method '{}'", s)),
+        }
+    }
+}
+
+impl ZipMethod {
+    /// Levels the method accepts, if it can be tuned at all
+    pub fn levels(self) -> Option<RangeInclusive<i32>> {
+        match self {
+            ZipMethod::Stored => None,
+            ZipMethod::Deflate => Some(0..=9),
+            ZipMethod::Bzip2 => Some(1..=9),
+            ZipMethod::Zstd => Some(zstd::compression_level_range()),
+        }
+    }
+
+    /// Options for every entry, checking the level up front rather than failing on the first
+    /// entry
+    pub fn options(self, level: Option<i32>) -> Result<FileOptions, ConvertError> {
+        if let Some(level) = level {
+            match self.levels() {
+                Some(levels) if levels.contains(&level) => {}
+                Some(levels) => {
+                    return Err(ConvertError::param(&format!(
+                        "{:?} levels go from {} to {}, not {}", self, levels.start(), levels.end(), level
+                    )))
+                }
+                None => return Err(ConvertError::param("Stored entries aren't compressed, so they have no level")),
+            }
+        }
+        let method = match self {
+            ZipMethod::Stored => CompressionMethod::Stored,
+            ZipMethod::Deflate => CompressionMethod::Deflated,
+            ZipMethod::Bzip2 => CompressionMethod::Bzip2,
+            ZipMethod::Zstd => CompressionMethod::Zstd,
+        };
+        Ok(FileOptions::default().compression_method(method).compression_level(level))
+    }
+}